@@ -9,6 +9,8 @@ use index;
 use indexmap;
 use jemalloc_ctl::{epoch, stats};
 use rand::{distributions::Distribution, Rng, RngCore, SeedableRng};
+use sashstore::indexmap as sash;
+use sashstore::indexmap::hash::{HashFunction, SipHasherBuilder};
 use std::collections::HashMap;
 use zipf::ZipfDistribution;
 
@@ -63,7 +65,16 @@ fn main() {
                 .short("b")
                 .multiple(true)
                 .takes_value(true)
-                .possible_values(&["std", "index", "indexmap"])
+                .possible_values(&[
+                    "std",
+                    "index",
+                    "sash",
+                    "sash-robinhood",
+                    "sash-group",
+                    "sash-fold",
+                    "sash-siphash",
+                    "indexmap",
+                ])
                 .help("What HashMap versions to benchmark."),
         )
         .arg(
@@ -75,6 +86,15 @@ fn main() {
                 .default_value("uniform")
                 .help("What key distribution to use."),
         )
+        .arg(
+            Arg::with_name("key-length")
+                .short("k")
+                .long("key-length")
+                .takes_value(true)
+                .possible_values(&["u64", "16", "32", "64", "mixed"])
+                .default_value("u64")
+                .help("Length of the keys of the sash benchmarks in bytes, u64 uses integers and mixed draws lengths from 16 to 64 bytes."),
+        )
         .arg(
             Arg::with_name("thread-mapping")
                 .long("thread-mapping")
@@ -90,6 +110,8 @@ fn main() {
     let dist = value_t!(matches, "distribution", String).unwrap_or_else(|e| e.exit());
     let capacity = value_t!(matches, "capacity", usize).unwrap_or_else(|e| e.exit());
     let runtime_sec = value_t!(matches, "runtime", u64).unwrap_or_else(|e| e.exit());
+    let key_length_str = value_t!(matches, "key-length", String).unwrap_or_else(|e| e.exit());
+    let key_length = KeyLength::parse(&key_length_str);
     //println!("threads={} write_ratio={} dist={} capacity={} runtime_sec={}",threads, write_ratio, dist, capacity, runtime_sec);
 
     let span = capacity;
@@ -127,7 +149,16 @@ fn main() {
 
     let versions: Vec<&str> = match matches.values_of("benchmark") {
         Some(iter) => iter.collect(),
-        None => vec!["std", "index", "indexmap"],
+        None => vec![
+            "std",
+            "index",
+            "sash",
+            "sash-robinhood",
+            "sash-group",
+            "sash-fold",
+            "sash-siphash",
+            "indexmap",
+        ],
     };
 
     if versions.contains(&"index") {
@@ -157,6 +188,80 @@ fn main() {
         stat("index", ops_mem);
     }
 
+    // The same hash function type as the key space of the server, to compare
+    // the cost of the hash functions with FNV
+    let siphash = HashFunction::SipHash13(SipHasherBuilder::new(rand::random(), rand::random()));
+    for &(name, layout, hasher) in &[
+        ("sash", sash::Layout::Probing, HashFunction::Fnv),
+        ("sash-robinhood", sash::Layout::RobinHood, HashFunction::Fnv),
+        ("sash-group", sash::Layout::Group, HashFunction::Fnv),
+        ("sash-fold", sash::Layout::Probing, HashFunction::Fold),
+        ("sash-siphash", sash::Layout::Probing, siphash),
+    ] {
+        if !versions.contains(&name) {
+            continue;
+        }
+        let mut cpus = topology.allocate(tm, threads, true).into_iter();
+
+        join.extend((0..threads).into_iter().map(|tid| {
+            let b = barrier.clone();
+            let cpu = cpus.next().unwrap().cpu;
+            let dist = dist.clone();
+
+            let thread = thread::spawn(move || {
+                pin_thread(cpu);
+
+                let params = sash::Parameters {
+                    layout,
+                    ..sash::Parameters::with_hasher(hasher)
+                };
+                if let Some(key_length) = key_length {
+                    let mut map: Arc<sash::Index<Vec<u8>, u64, HashFunction>> =
+                        Arc::new(sash::Index::with_capacity_and_parameters(capacity, params));
+                    let mut key = [0; MAX_KEY_LENGTH];
+                    for i in 0..capacity {
+                        let key = key_length.write(i as u64, &mut key).to_vec();
+                        Arc::make_mut(&mut map).insert(key, (i + 1) as u64);
+                    }
+                    if tid == 0 {
+                        // stdout is the CSV
+                        eprintln!("{} {}", name, map.stats());
+                    }
+
+                    return bench(
+                        ByteKeys { map, key_length },
+                        b,
+                        dur,
+                        span,
+                        &dist,
+                        write_ratio,
+                    );
+                }
+
+                let mut map: Arc<sash::Index<u64, u64, HashFunction>> =
+                    Arc::new(sash::Index::with_capacity_and_parameters(capacity, params));
+                for i in 0..capacity {
+                    Arc::make_mut(&mut map).insert(i as u64, (i + 1) as u64);
+                }
+                if tid == 0 {
+                    // stdout is the CSV
+                    eprintln!("{} {}", name, map.stats());
+                }
+
+                bench(map, b, dur, span, &dist, write_ratio)
+            });
+
+            thread
+        }));
+
+        let ops_mem: Vec<(usize, usize)> = join.drain(..).map(|jh| jh.join().unwrap()).collect();
+        if key_length.is_some() {
+            stat(&format!("{} keys={}", name, key_length_str), ops_mem);
+        } else {
+            stat(name, ops_mem);
+        }
+    }
+
     if versions.contains(&"indexmap") {
         let mut cpus = topology.allocate(tm, threads, true).into_iter();
 
@@ -210,6 +315,62 @@ fn main() {
     }
 }
 
+/// Longest key `KeyLength` makes.
+const MAX_KEY_LENGTH: usize = 64;
+
+/// Lengths of the byte string keys of the sash benchmarks.
+#[derive(Debug, Clone, Copy)]
+enum KeyLength {
+    /// All keys have the same length.
+    Fixed(usize),
+    /// Every key has its own length from 16 to 64 bytes, like the names of
+    /// keys of a Redis server.
+    Mixed,
+}
+
+impl KeyLength {
+    /// Parses a `--key-length`, `None` for integer keys.
+    fn parse(key_length: &str) -> Option<KeyLength> {
+        match key_length {
+            "u64" => None,
+            "mixed" => Some(KeyLength::Mixed),
+            length => Some(KeyLength::Fixed(length.parse().expect("key length"))),
+        }
+    }
+
+    /// Writes the key for `id` to `buf`: `user:` followed by `id` padded
+    /// with zeros to the length of the key.
+    fn write(self, id: u64, buf: &mut [u8; MAX_KEY_LENGTH]) -> &[u8] {
+        let length = match self {
+            KeyLength::Fixed(length) => length,
+            // Scrambles `id` so neighbours get unrelated lengths
+            KeyLength::Mixed => 16 + (id.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) as usize % 49,
+        };
+        let key = &mut buf[..length];
+        for byte in key.iter_mut() {
+            *byte = b'0';
+        }
+        key[..5].copy_from_slice(b"user:");
+        let mut digits = id;
+        for byte in key[5..].iter_mut().rev() {
+            if digits == 0 {
+                break;
+            }
+            *byte = b'0' + (digits % 10) as u8;
+            digits /= 10;
+        }
+        key
+    }
+}
+
+/// A sash `Index` with byte string keys, the key for `id` is the one
+/// `key_length` writes.
+#[derive(Clone)]
+struct ByteKeys {
+    map: Arc<sash::Index<Vec<u8>, u64, HashFunction>>,
+    key_length: KeyLength,
+}
+
 trait Backend {
     fn b_get(&mut self, key: u64) -> u64;
     fn b_put(&mut self, key: u64, value: u64);
@@ -336,6 +497,32 @@ impl Backend for Arc<index::Index<u64, u64>> {
     }
 }
 
+impl Backend for Arc<sash::Index<u64, u64, HashFunction>> {
+    fn b_put(&mut self, key: u64, val: u64) {
+        Arc::make_mut(self).insert(key, val);
+    }
+
+    fn b_get(&mut self, key: u64) -> u64 {
+        self.get(&key).map(|v| *v).unwrap()
+    }
+}
+
+impl Backend for ByteKeys {
+    fn b_put(&mut self, key: u64, val: u64) {
+        let key = self
+            .key_length
+            .write(key, &mut [0; MAX_KEY_LENGTH])
+            .to_vec();
+        Arc::make_mut(&mut self.map).insert(key, val);
+    }
+
+    fn b_get(&mut self, key: u64) -> u64 {
+        let mut buf = [0; MAX_KEY_LENGTH];
+        let key = self.key_length.write(key, &mut buf);
+        self.map.get(key).map(|v| *v).unwrap()
+    }
+}
+
 impl Backend for Arc<indexmap::IndexMap<u64, u64>> {
     fn b_put(&mut self, key: u64, val: u64) {
         Arc::make_mut(self).insert(key, val);
@@ -10,9 +10,11 @@ rm *.log *.csv || true
 CAPACITY=10000000
 RUNTIME=10
 
-declare -a benchmarks=("andreamap" "std" "index" "indexmap")
+declare -a benchmarks=("andreamap" "std" "index" "sash" "sash-robinhood" "sash-group" "sash-fold" "sash-siphash" "indexmap")
 declare -a distributions=("uniform" "skewed")
 declare -a write_ratios=("10" "20")
+declare -a hashers=("sash" "sash-fold" "sash-siphash")
+declare -a key_lengths=("16" "64" "mixed")
 
 # Maybe: disable dvfs
 # echo performance | tee /sys/devices/system/cpu/cpu*/cpufreq/scaling_governor
@@ -37,4 +39,16 @@ for benchmark in "${benchmarks[@]}"; do
     done
 done
 
+# The hash functions with byte string keys
+for benchmark in "${hashers[@]}"; do
+    for key_length in "${key_lengths[@]}"; do
+        for cores in `seq 0 4 $MAX_CORES`; do
+            if [ "$cores" -eq "0" ]; then
+                cores=1
+            fi
+            (cargo bench --bench hashbench -- -b ${benchmark} --key-length $key_length --capacity $CAPACITY --runtime $RUNTIME --threads $cores --write-ratio 10 --distribution uniform | tee -a $CSVFILE) 3>&1 1>&2 2>&3 | tee -a $LOGFILE
+        done
+    done
+done
+
 python3 plot.py $CSVFILE
\ No newline at end of file
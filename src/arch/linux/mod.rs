@@ -1,17 +1,21 @@
 use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::convert::TryInto;
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
 use std::thread::JoinHandle;
 
 use clap::{crate_version, value_t, App, Arg};
 
 use super::{CmdArgs, CpuId, NumaTopology, PlatformSupport, ThreadId, Transport};
+use crate::indexmap::hash::{HashFunction, SipHasherBuilder};
 
 mod net;
 mod topology;
 
-pub use net::server_loop;
+pub use net::{mailboxes, server_loop};
 
 #[derive(Default)]
 pub struct Platform {
@@ -80,6 +84,14 @@ impl PlatformSupport for Platform {
                     .default_value("6666")
                     .help("Default (starting) port."),
             )
+            .arg(
+                Arg::with_name("hasher")
+                    .long("hasher")
+                    .takes_value(true)
+                    .possible_values(&["fnv", "fold", "siphash"])
+                    .default_value("fnv")
+                    .help("Hash function of the key space, fold is the fastest, siphash is keyed with a random seed and resists collision attacks."),
+            )
             .get_matches();
 
         let threads = value_t!(matches, "threads", usize).unwrap_or_else(|e| e.exit());
@@ -99,6 +111,15 @@ impl PlatformSupport for Platform {
         } else {
             Transport::Udp
         };
+        let hasher_str = value_t!(matches, "hasher", String).unwrap_or_else(|e| e.exit());
+        let hasher = match hasher_str.as_str() {
+            "siphash" => {
+                let (k0, k1) = random_seed();
+                HashFunction::SipHash13(SipHasherBuilder::new(k0, k1))
+            }
+            "fold" => HashFunction::Fold,
+            _ => HashFunction::Fnv,
+        };
 
         CmdArgs {
             threads,
@@ -107,6 +128,7 @@ impl PlatformSupport for Platform {
             transport,
             port,
             tcp_connections_per_port,
+            hasher,
         }
     }
 
@@ -130,6 +152,39 @@ impl PlatformSupport for Platform {
     }
 }
 
+/// Returns the bytes allocated by the application and the bytes of
+/// physical memory the allocator holds on to.
+pub fn memory_usage() -> (usize, usize) {
+    // jemalloc caches its statistics, advancing the epoch refreshes them
+    let _ = jemalloc_ctl::epoch::advance();
+    (
+        jemalloc_ctl::stats::allocated::read().unwrap_or(0),
+        jemalloc_ctl::stats::resident::read().unwrap_or(0),
+    )
+}
+
+/// Returns the current unix time in milliseconds.
+pub fn unix_time_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("System time is before the unix epoch")
+        .as_millis() as u64
+}
+
+/// Returns a random seed for keyed hashing, read from the kernel's random
+/// number generator.
+pub fn random_seed() -> (u64, u64) {
+    let mut seed = [0; 16];
+    File::open("/dev/urandom")
+        .and_then(|mut urandom| urandom.read_exact(&mut seed))
+        .expect("Can't read a random seed from /dev/urandom");
+    let (k0, k1) = seed.split_at(8);
+    (
+        u64::from_le_bytes(k0.try_into().unwrap()),
+        u64::from_le_bytes(k1.try_into().unwrap()),
+    )
+}
+
 /// Pin a thread to a core
 fn pin_thread(id: CpuId) {
     core_affinity::set_for_current(core_affinity::CoreId { id });
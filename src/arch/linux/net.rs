@@ -1,11 +1,17 @@
+use alloc::format;
+use alloc::string::String;
 use alloc::vec::Vec;
+use std::collections::VecDeque;
 use std::net;
 use std::net::{Ipv4Addr, SocketAddrV4};
 use std::os::unix::io::AsRawFd;
 use std::os::unix::io::RawFd;
+use std::sync::mpsc::{channel, Receiver, Sender, TryIter};
+use std::time::{Duration, Instant};
 
 use mio::unix::{EventedFd, UnixReady};
-use mio::Ready;
+use mio::{Ready, Registration, SetReadiness};
+use nix::errno::Errno;
 use nix::sys::socket;
 use nix::sys::uio;
 
@@ -13,9 +19,82 @@ use log::{debug, info, trace};
 use socket2::{Domain, Socket, Type};
 
 use crate::arch::{CmdArgs, CpuId, ThreadId, Transport};
+use crate::redis::Publication;
 use crate::SashStore;
 
-pub fn server_loop(core: CpuId, tid: ThreadId, config: &CmdArgs, kvstore: &mut SashStore) {
+/// How often we reclaim expired keys (Redis does it 10 times per second).
+const EXPIRE_CYCLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Connects the server loop of a worker thread to the loops of all other
+/// threads, messages published with Pub/Sub travel through it.
+pub struct Mailbox {
+    /// Messages published on other threads
+    inbox: Receiver<Publication>,
+    /// Makes the poll of the owning thread wake up for new messages
+    registration: Registration,
+    readiness: SetReadiness,
+    /// Inboxes of the other threads and how to wake them up
+    peers: Vec<(Sender<Publication>, SetReadiness)>,
+}
+
+impl Mailbox {
+    /// Sends `publication` to all other threads.
+    fn broadcast(&self, publication: &Publication) {
+        for (sender, readiness) in &self.peers {
+            // A peer only hangs up once its server loop returned
+            if sender.send(publication.clone()).is_ok() {
+                readiness
+                    .set_readiness(Ready::readable())
+                    .expect("Can't wake up peer");
+            }
+        }
+    }
+
+    /// Returns the messages received so far.
+    fn receive(&self) -> TryIter<Publication> {
+        // Reset first, so a message arriving while we drain wakes us again
+        self.readiness
+            .set_readiness(Ready::empty())
+            .expect("Can't reset mailbox readiness");
+        self.inbox.try_iter()
+    }
+}
+
+/// Creates connected mailboxes for `threads` worker threads.
+pub fn mailboxes(threads: usize) -> Vec<Mailbox> {
+    let mut inboxes = Vec::with_capacity(threads);
+    let mut senders = Vec::with_capacity(threads);
+    for _ in 0..threads {
+        let (sender, inbox) = channel();
+        let (registration, readiness) = Registration::new2();
+        senders.push((sender, readiness.clone()));
+        inboxes.push((inbox, registration, readiness));
+    }
+
+    inboxes
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (inbox, registration, readiness))| Mailbox {
+            inbox,
+            registration,
+            readiness,
+            peers: senders
+                .iter()
+                .enumerate()
+                .filter(|(peer, _)| *peer != idx)
+                .map(|(_, sender)| sender.clone())
+                .collect(),
+        })
+        .collect()
+}
+
+pub fn server_loop(
+    core: CpuId,
+    tid: ThreadId,
+    config: &CmdArgs,
+    mailbox: Mailbox,
+    kvstore: &mut SashStore,
+) {
     debug!("Inside server loop on {}", core);
     let connections = connect(tid, config);
     debug!("Opened connection on {:?}", connections);
@@ -23,6 +102,12 @@ pub fn server_loop(core: CpuId, tid: ThreadId, config: &CmdArgs, kvstore: &mut S
     let poll = mio::Poll::new().expect("Can't create poll.");
 
     for (idx, connection) in connections.iter().enumerate() {
+        if let Connection::Stream(stream) = connection {
+            let addr = stream
+                .peer_addr()
+                .map_or_else(|_| String::from("?"), |addr| format!("{}", addr));
+            kvstore.accept_client(idx, addr);
+        }
         poll.register(
             &EventedFd(&connection.as_raw_fd()),
             mio::Token(idx),
@@ -31,70 +116,233 @@ pub fn server_loop(core: CpuId, tid: ThreadId, config: &CmdArgs, kvstore: &mut S
         )
         .expect("Can't register events.");
     }
+    let mailbox_token = mio::Token(connections.len());
+    poll.register(
+        &mailbox.registration,
+        mailbox_token,
+        Ready::readable(),
+        mio::PollOpt::edge(),
+    )
+    .expect("Can't register mailbox.");
 
     let mut events = mio::Events::with_capacity(10);
+    // Bytes of partially received RESP commands, per stream connection
+    let mut pending: Vec<Vec<u8>> = connections.iter().map(|_| Vec::new()).collect();
+    // Bytes of replies the peer didn't take yet, per stream connection
+    let mut unsent: Vec<VecDeque<u8>> = connections.iter().map(|_| VecDeque::new()).collect();
+    let mut last_expire_cycle = Instant::now();
 
     loop {
-        poll.poll(&mut events, None).expect("Can't poll channel");
+        // Wake up in time for the next blocked client that times out
+        let timeout = match kvstore.next_block_timeout_ms() {
+            Some(ms) => EXPIRE_CYCLE_INTERVAL.min(Duration::from_millis(ms)),
+            None => EXPIRE_CYCLE_INTERVAL,
+        };
+        poll.poll(&mut events, Some(timeout))
+            .expect("Can't poll channel");
+        if last_expire_cycle.elapsed() >= EXPIRE_CYCLE_INTERVAL {
+            let reclaimed = kvstore.active_expire_cycle();
+            trace!("Reclaimed {} expired keys", reclaimed);
+            kvstore.sample_stats();
+            last_expire_cycle = Instant::now();
+        }
+
         for event in events.iter() {
-            let raw_fd: RawFd = connections[event.token().0].as_raw_fd();
+            if event.token() == mailbox_token {
+                for publication in mailbox.receive() {
+                    kvstore.deliver(&publication);
+                }
+                continue;
+            }
+            let idx = event.token().0;
+            let raw_fd: RawFd = connections[idx].as_raw_fd();
             trace!("event = {:?}", event);
 
+            if event.readiness().is_writable() {
+                flush(raw_fd, &mut unsent[idx]);
+            }
             if event.readiness().is_readable() {
-                const MSG_MAX_LEN: usize = 1500;
-                let mut recv_buf: Vec<u8> = Vec::with_capacity(MSG_MAX_LEN);
-                recv_buf.resize(MSG_MAX_LEN, 0);
-
-                let msg = match socket::recvmsg(
-                    raw_fd,
-                    &[uio::IoVec::from_mut_slice(&mut recv_buf)],
-                    None,
-                    socket::MsgFlags::empty(),
-                ) {
-                    Ok(msg) => msg,
-                    Err(e) => panic!("Unexpected error during socket::recvmsg {:?}", e),
+                let open = match &connections[idx] {
+                    Connection::Datagram(_) => handle_datagram(raw_fd, kvstore),
+                    Connection::Stream(_) => {
+                        handle_stream(raw_fd, idx, &mut pending[idx], &mut unsent[idx], kvstore)
+                    }
                 };
-                if msg.bytes == 0 {
+                if !open {
                     info!("Got 0 bytes, in TCP this means connection got shut-down");
                     return;
                 }
-                assert!(
-                    msg.bytes <= MSG_MAX_LEN,
-                    "Got a message bigger than expected"
-                );
-                // Throw away zeroes at the end of the buffer:
-                recv_buf.truncate(msg.bytes);
-                let sender: socket::SockAddr = msg.address.unwrap();
-
-                trace!(
-                    "recv_buf = {:?}",
-                    recv_buf.iter().map(|c| *c as char).collect::<Vec<char>>()
-                );
-
-                let send_buf = kvstore.handle_network_request(recv_buf);
-                let sent = match socket::sendto(
-                    raw_fd,
-                    &send_buf.as_slice(),
-                    &sender,
-                    socket::MsgFlags::empty(),
-                ) {
-                    Ok(bytes_sent) => bytes_sent,
-                    Err(e) => panic!("Unexpected error during socket::send {:?}", e),
-                };
-                assert!(sent > 0);
             }
 
-            poll.reregister(
-                &EventedFd(&raw_fd),
-                mio::Token(event.token().0),
-                Ready::readable(),
-                mio::PollOpt::edge() | mio::PollOpt::oneshot(),
-            )
-            .expect("Can't re-register events.");
+            rearm(&poll, raw_fd, idx, &unsent[idx]);
+        }
+
+        // Reply to clients that were waiting in a blocking command, then
+        // run the commands they sent in the meantime (which may block again
+        // or unblock other clients).
+        let mut unblocked = kvstore.unblock_clients();
+        while !unblocked.is_empty() {
+            for (idx, reply) in unblocked {
+                let raw_fd = connections[idx].as_raw_fd();
+                send_all(raw_fd, &mut unsent[idx], &reply);
+                let send_buf = kvstore.handle_resp_request(idx, &mut pending[idx]);
+                send_all(raw_fd, &mut unsent[idx], &send_buf);
+                if !unsent[idx].is_empty() {
+                    rearm(&poll, raw_fd, idx, &unsent[idx]);
+                }
+            }
+            unblocked = kvstore.unblock_clients();
+        }
+
+        for idx in kvstore.take_killed() {
+            // Like in Redis, the peer sees the connection closing
+            poll.deregister(&EventedFd(&connections[idx].as_raw_fd()))
+                .expect("Can't deregister killed connection.");
+            if let Connection::Stream(stream) = &connections[idx] {
+                let _ = stream.shutdown(net::Shutdown::Both);
+            }
+        }
+
+        for publication in kvstore.take_published() {
+            mailbox.broadcast(&publication);
+        }
+        for (idx, message) in kvstore.take_messages() {
+            let raw_fd = connections[idx].as_raw_fd();
+            send_all(raw_fd, &mut unsent[idx], &message);
+            if !unsent[idx].is_empty() {
+                rearm(&poll, raw_fd, idx, &unsent[idx]);
+            }
+        }
+    }
+}
+
+/// Maximum size of a single packet we receive.
+const MSG_MAX_LEN: usize = 1500;
+
+/// Serve a memcached request received on a UDP socket.
+///
+/// Returns `false` if we received an empty message.
+fn handle_datagram(raw_fd: RawFd, kvstore: &mut SashStore) -> bool {
+    let mut recv_buf: Vec<u8> = Vec::with_capacity(MSG_MAX_LEN);
+    recv_buf.resize(MSG_MAX_LEN, 0);
+
+    let msg = match socket::recvmsg(
+        raw_fd,
+        &[uio::IoVec::from_mut_slice(&mut recv_buf)],
+        None,
+        socket::MsgFlags::empty(),
+    ) {
+        Ok(msg) => msg,
+        Err(e) => panic!("Unexpected error during socket::recvmsg {:?}", e),
+    };
+    if msg.bytes == 0 {
+        return false;
+    }
+    assert!(
+        msg.bytes <= MSG_MAX_LEN,
+        "Got a message bigger than expected"
+    );
+    // Throw away zeroes at the end of the buffer:
+    recv_buf.truncate(msg.bytes);
+    let sender: socket::SockAddr = msg.address.unwrap();
+
+    trace!(
+        "recv_buf = {:?}",
+        recv_buf.iter().map(|c| *c as char).collect::<Vec<char>>()
+    );
+
+    let send_buf = kvstore.handle_network_request(recv_buf);
+    let sent = match socket::sendto(
+        raw_fd,
+        &send_buf.as_slice(),
+        &sender,
+        socket::MsgFlags::empty(),
+    ) {
+        Ok(bytes_sent) => bytes_sent,
+        Err(e) => panic!("Unexpected error during socket::send {:?}", e),
+    };
+    assert!(sent > 0);
+    true
+}
+
+/// Serve RESP (Redis) requests received on a TCP stream.
+///
+/// Reads everything that is available on the socket into `pending`,
+/// executes all complete commands and sends back the replies (queuing what
+/// the peer doesn't take yet in `unsent`). `client` identifies the
+/// connection to the `SashStore`.
+///
+/// Returns `false` if the peer closed the connection.
+fn handle_stream(
+    raw_fd: RawFd,
+    client: usize,
+    pending: &mut Vec<u8>,
+    unsent: &mut VecDeque<u8>,
+    kvstore: &mut SashStore,
+) -> bool {
+    let mut recv_buf = [0u8; MSG_MAX_LEN];
+    loop {
+        match socket::recv(raw_fd, &mut recv_buf, socket::MsgFlags::empty()) {
+            Ok(0) => return false,
+            Ok(bytes) => pending.extend_from_slice(&recv_buf[..bytes]),
+            Err(nix::Error::Sys(Errno::EAGAIN)) => break,
+            Err(e) => panic!("Unexpected error during socket::recv {:?}", e),
+        }
+    }
+    trace!("pending = {:?}", pending.len());
+
+    let send_buf = kvstore.handle_resp_request(client, pending);
+    send_all(raw_fd, unsent, &send_buf);
+    true
+}
+
+/// Sends `buf` on a (non-blocking) TCP stream after the bytes still in
+/// `unsent`. What the peer doesn't take right away is queued in `unsent`,
+/// it gets sent once the stream is writable again (see `rearm`).
+fn send_all(raw_fd: RawFd, unsent: &mut VecDeque<u8>, buf: &[u8]) {
+    let mut sent = 0;
+    if unsent.is_empty() {
+        while sent < buf.len() {
+            match socket::send(raw_fd, &buf[sent..], socket::MsgFlags::empty()) {
+                Ok(bytes_sent) => sent += bytes_sent,
+                Err(nix::Error::Sys(Errno::EAGAIN)) => break,
+                Err(e) => panic!("Unexpected error during socket::send {:?}", e),
+            }
+        }
+    }
+    unsent.extend(&buf[sent..]);
+}
+
+/// Sends as much of `unsent` as the (non-blocking) TCP stream takes.
+fn flush(raw_fd: RawFd, unsent: &mut VecDeque<u8>) {
+    while !unsent.is_empty() {
+        let (front, _) = unsent.as_slices();
+        match socket::send(raw_fd, front, socket::MsgFlags::empty()) {
+            Ok(bytes_sent) => {
+                unsent.drain(..bytes_sent);
+            }
+            Err(nix::Error::Sys(Errno::EAGAIN)) => return,
+            Err(e) => panic!("Unexpected error during socket::send {:?}", e),
         }
     }
 }
 
+/// Re-registers connection `idx` for the next event: always readable, and
+/// writable while some of its replies are waiting in `unsent`.
+fn rearm(poll: &mio::Poll, raw_fd: RawFd, idx: usize, unsent: &VecDeque<u8>) {
+    let mut interest = Ready::readable();
+    if !unsent.is_empty() {
+        interest |= Ready::writable();
+    }
+    poll.reregister(
+        &EventedFd(&raw_fd),
+        mio::Token(idx),
+        interest,
+        mio::PollOpt::edge() | mio::PollOpt::oneshot(),
+    )
+    .expect("Can't re-register events.");
+}
+
 #[derive(Debug)]
 pub enum Connection {
     Datagram(net::UdpSocket),
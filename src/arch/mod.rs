@@ -1,6 +1,8 @@
 use alloc::vec::Vec;
 use core::fmt;
 
+use crate::indexmap::hash::HashFunction;
+
 /// Linux specific code,
 #[cfg(target_os = "linux")]
 #[path = "linux/mod.rs"]
@@ -33,13 +35,31 @@ pub struct CmdArgs {
     pub capacity: usize,
 
     /// Transport layer
-    transport: Transport,
+    pub transport: Transport,
 
     /// In case of TCP transport, how many connections we expect per port
-    tcp_connections_per_port: usize,
+    pub tcp_connections_per_port: usize,
 
     /// Start port address
-    port: usize,
+    pub port: usize,
+
+    /// Hash function of the key space, keyed ones come with their key
+    pub hasher: HashFunction,
+}
+
+impl Default for CmdArgs {
+    /// The defaults of the command-line flags.
+    fn default() -> CmdArgs {
+        CmdArgs {
+            threads: 1,
+            numa_strategy: NumaTopology::Interleave,
+            capacity: 10000,
+            transport: Transport::Udp,
+            tcp_connections_per_port: 1,
+            port: 6666,
+            hasher: HashFunction::Fnv,
+        }
+    }
 }
 
 pub trait PlatformSupport {
@@ -1,6 +1,7 @@
 use alloc::vec::Vec;
 
 use super::{CmdArgs, CpuId, NumaTopology, PlatformSupport, ThreadId, Transport};
+use crate::indexmap::hash::HashFunction;
 use crate::SashStore;
 
 pub struct Platform;
@@ -28,6 +29,7 @@ impl PlatformSupport for Platform {
             transport: Transport::Udp,
             tcp_connections_per_port: 1,
             port: 6666,
+            hasher: HashFunction::Fnv,
         }
     }
 
@@ -50,13 +52,63 @@ impl PlatformSupport for Platform {
     }
 }
 
+/// Returns the current unix time in milliseconds.
+///
+/// Used to expire keys, doesn't need to be more precise than that.
+///
+/// Stub: there is no clock yet, time stands still at 0 so keys given a TTL
+/// never expire.
+pub fn unix_time_ms() -> u64 {
+    0
+}
+
+/// Returns the bytes allocated by the application and the bytes of
+/// physical memory the allocator holds on to.
+///
+/// Stub: the allocator doesn't keep statistics, INFO reports 0 for both.
+pub fn memory_usage() -> (usize, usize) {
+    (0, 0)
+}
+
+/// Connects the server loop of a worker thread to the loops of all other
+/// threads, messages published with Pub/Sub travel through it.
+///
+/// Stub: it isn't connected to anything yet.
+pub struct Mailbox;
+
+/// Creates connected mailboxes for `threads` worker threads.
+///
+/// Every mailbox needs to be able to send a `Publication` to all the others
+/// and to wake up their server loop when it does.
+///
+/// Stub: returns one unconnected `Mailbox` per thread, so that `main` starts
+/// all of them.
+pub fn mailboxes(threads: usize) -> Vec<Mailbox> {
+    (0..threads).map(|_| Mailbox).collect()
+}
+
 /// The per-core server loop it's called on every spawned thread.
 ///
 /// 1. It should open a connection on port `cmd.port` + `tid`
 /// 2. Listen for incoming messages
 /// 3. Give message to `SashStore::handle_network_request`
 /// 4. Send result of `SashStore::handle_network_request` back to client
+/// 5. Call `SashStore::active_expire_cycle` and `SashStore::sample_stats`
+///    every 100 ms or so
+/// 6. Send the replies from `SashStore::unblock_clients` (wake up within
+///    `SashStore::next_block_timeout_ms` for blocked clients that time out)
+/// 7. Forward `SashStore::take_published` to the other threads through
+///    `mailbox`, hand what they sent to `SashStore::deliver` and send
+///    `SashStore::take_messages` to the subscribed connections
+/// 8. Announce TCP connections with `SashStore::accept_client` and close
+///    the ones `SashStore::take_killed` returns
 #[allow(unused)]
-pub fn server_loop(core: CpuId, tid: ThreadId, config: &CmdArgs, kvstore: &mut SashStore) {
+pub fn server_loop(
+    core: CpuId,
+    tid: ThreadId,
+    config: &CmdArgs,
+    mailbox: Mailbox,
+    kvstore: &mut SashStore,
+) {
     unimplemented!("server_loop")
 }
@@ -0,0 +1,268 @@
+//! Module implementing the entry API of an [`Index`] hash table:
+//! in-place manipulation of a single bucket found with one lookup.
+//!
+//! [`Index`]: struct.Index.html
+
+use super::{Bucket, Index};
+
+use core::mem;
+
+/// A view into a single bucket of an [`Index`], which is either occupied
+/// or vacant.
+///
+/// This is constructed by the [`entry`] method of an `Index`.
+///
+/// [`Index`]: struct.Index.html
+/// [`entry`]: struct.Index.html#method.entry
+pub enum Entry<'a, K, V, S> {
+    /// The key is present.
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    /// The key is missing.
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+/// A view into a bucket holding the key looked up, part of an [`Entry`].
+///
+/// [`Entry`]: enum.Entry.html
+pub struct OccupiedEntry<'a, K, V, S> {
+    index: &'a mut Index<K, V, S>,
+    bucket: usize,
+}
+
+/// A view into the bucket where a missing key would be inserted, part of
+/// an [`Entry`].
+///
+/// [`Entry`]: enum.Entry.html
+pub struct VacantEntry<'a, K, V, S> {
+    index: &'a mut Index<K, V, S>,
+    key: K,
+    hash: usize,
+    bucket: usize,
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S> {
+    pub(super) fn occupied(index: &'a mut Index<K, V, S>, bucket: usize) -> Self {
+        Entry::Occupied(OccupiedEntry { index, bucket })
+    }
+
+    pub(super) fn vacant(
+        index: &'a mut Index<K, V, S>,
+        key: K,
+        hash: usize,
+        bucket: usize,
+    ) -> Self {
+        Entry::Vacant(VacantEntry {
+            index,
+            key,
+            hash,
+            bucket,
+        })
+    }
+
+    /// Inserts `default` if the entry is vacant and returns a mutable
+    /// reference to the value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index: Index<&str, i32> = Index::new();
+    ///
+    /// *index.entry("hits").or_insert(0) += 1;
+    /// *index.entry("hits").or_insert(0) += 1;
+    ///
+    /// assert_eq!(*index.get("hits").unwrap(), 2);
+    /// ```
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Inserts the result of `default` if the entry is vacant and returns a
+    /// mutable reference to the value. `default` is only called if needed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index: Index<&str, Vec<i32>> = Index::new();
+    ///
+    /// index.entry("primes").or_insert_with(Vec::new).push(2);
+    /// index.entry("primes").or_insert_with(Vec::new).push(3);
+    ///
+    /// assert_eq!(*index.get("primes").unwrap(), vec![2, 3]);
+    /// ```
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Calls `f` on the value if the entry is occupied.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index: Index<&str, i32> = Index::new();
+    ///
+    /// index.entry("counter").and_modify(|v| *v += 1).or_insert(41);
+    /// index.entry("counter").and_modify(|v| *v += 1).or_insert(41);
+    ///
+    /// assert_eq!(*index.get("counter").unwrap(), 42);
+    /// ```
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+
+    /// Sets the value of the entry, whether it is occupied or not, and
+    /// returns the now occupied entry.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index: Index<&str, &str> = Index::new();
+    ///
+    /// let entry = index.entry("key").insert("value");
+    ///
+    /// assert_eq!(*entry.get(), "value");
+    /// ```
+    pub fn insert(self, value: V) -> OccupiedEntry<'a, K, V, S> {
+        match self {
+            Entry::Occupied(mut entry) => {
+                entry.insert(value);
+                entry
+            }
+            Entry::Vacant(entry) => {
+                let bucket = entry.bucket;
+                let index = entry.index;
+                index.fill(bucket, entry.hash, entry.key, value);
+                OccupiedEntry { index, bucket }
+            }
+        }
+    }
+}
+
+impl<'a, K, V: Default, S> Entry<'a, K, V, S> {
+    /// Inserts the default value if the entry is vacant and returns a
+    /// mutable reference to the value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index: Index<&str, u32> = Index::new();
+    ///
+    /// *index.entry("hits").or_default() += 1;
+    ///
+    /// assert_eq!(*index.get("hits").unwrap(), 1);
+    /// ```
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
+    /// Returns a reference to the key of the entry.
+    pub fn key(&self) -> &K {
+        &self.pair().0
+    }
+
+    /// Returns a reference to the value of the entry.
+    pub fn get(&self) -> &V {
+        &self.pair().1
+    }
+
+    /// Returns a mutable reference to the value of the entry.
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.index.pair_mut(self.bucket).1
+    }
+
+    /// Converts the entry into a mutable reference to its value, which
+    /// lives as long as the borrow of the `Index`.
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.index.pair_mut(self.bucket).1
+    }
+
+    /// Sets the value of the entry and returns the previous one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::{Entry, Index};
+    ///
+    /// let mut index = Index::new();
+    /// index.insert("key", 1);
+    ///
+    /// if let Entry::Occupied(mut entry) = index.entry("key") {
+    ///     assert_eq!(entry.insert(2), 1);
+    /// }
+    /// assert_eq!(*index.get("key").unwrap(), 2);
+    /// ```
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the `Index` and returns its key-value pair.
+    pub fn remove_entry(self) -> (K, V) {
+        self.index
+            .remove_at(self.bucket)
+            .expect("an occupied entry points to a full bucket")
+    }
+
+    /// Removes the entry from the `Index` and returns its value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::{Entry, Index};
+    ///
+    /// let mut index = Index::new();
+    /// index.insert("key", 1);
+    ///
+    /// if let Entry::Occupied(entry) = index.entry("key") {
+    ///     assert_eq!(entry.remove(), 1);
+    /// }
+    /// assert!(index.is_empty());
+    /// ```
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    fn pair(&self) -> &(K, V) {
+        self.index
+            .get_at(self.bucket)
+            .expect("an occupied entry points to a full bucket")
+    }
+}
+
+impl<'a, K, V, S> VacantEntry<'a, K, V, S> {
+    /// Returns a reference to the key that would be inserted.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes back the key.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts `value` under the key of the entry and returns a mutable
+    /// reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.index.fill(self.bucket, self.hash, self.key, value);
+        &mut self.index.pair_mut(self.bucket).1
+    }
+}
@@ -0,0 +1,118 @@
+//! Module implementing the control bytes of the `Layout::Group` layout of
+//! an [`Index`] hash table.
+//!
+//! Every bucket has a control byte: `EMPTY`, `DELETED` or, for a full
+//! bucket, a 7-bit tag taken from the top of the hash of its key. Lookups
+//! load the control bytes of a group of `GROUP_WIDTH` buckets into a `u64`
+//! and compare all of them at once with bit tricks (SWAR, "SIMD within a
+//! register"), keys only get compared for buckets whose tag matches.
+//!
+//! [`Index`]: struct.Index.html
+
+use core::convert::TryInto;
+
+/// Number of buckets in a group, the control bytes of a group fit a `u64`.
+pub const GROUP_WIDTH: usize = 8;
+
+/// Control byte of a bucket that never held an entry.
+pub const EMPTY: u8 = 0b1111_1111;
+
+/// Control byte of a bucket whose entry got removed.
+pub const DELETED: u8 = 0b1000_0000;
+
+/// `0x01` in every byte.
+const LSB: u64 = 0x0101_0101_0101_0101;
+
+/// `0x80` in every byte.
+const MSB: u64 = 0x8080_8080_8080_8080;
+
+/// The tag stored in the control byte of a full bucket: the top 7 bits of
+/// `hash` (the bucket itself is picked with the low bits).
+pub fn tag(hash: usize) -> u8 {
+    (hash >> (8 * core::mem::size_of::<usize>() - 7)) as u8
+}
+
+/// The control bytes of a group of buckets.
+#[derive(Debug, Clone, Copy)]
+pub struct Group(u64);
+
+impl Group {
+    /// Loads the `GROUP_WIDTH` control bytes at the start of `control`.
+    pub fn load(control: &[u8]) -> Group {
+        let bytes = control[..GROUP_WIDTH]
+            .try_into()
+            .expect("a group has GROUP_WIDTH control bytes");
+        Group(u64::from_le_bytes(bytes))
+    }
+
+    /// Buckets whose control byte is `tag`.
+    ///
+    /// May report a false positive right after a real match, so the keys
+    /// still have to be compared.
+    pub fn match_tag(self, tag: u8) -> BitMask {
+        // Bytes equal to the tag become zero, find the zero bytes
+        let cmp = self.0 ^ (LSB * u64::from(tag));
+        BitMask(cmp.wrapping_sub(LSB) & !cmp & MSB)
+    }
+
+    /// Buckets that are `EMPTY`.
+    pub fn match_empty(self) -> BitMask {
+        // Only `EMPTY` has both of the two high bits set
+        BitMask(self.0 & (self.0 << 1) & MSB)
+    }
+
+    /// Buckets that are `EMPTY` or `DELETED`.
+    pub fn match_free(self) -> BitMask {
+        // Tags have the high bit cleared
+        BitMask(self.0 & MSB)
+    }
+}
+
+/// A set of buckets of a group, the high bit of a byte is set for every
+/// bucket in it. Iterating gives the positions in the group.
+#[derive(Debug, Clone, Copy)]
+pub struct BitMask(u64);
+
+impl BitMask {
+    /// Returns `true` if the set contains any bucket.
+    pub fn any(self) -> bool {
+        self.0 != 0
+    }
+
+    /// The first bucket of the set.
+    pub fn lowest(self) -> Option<usize> {
+        if self.any() {
+            Some(self.0.trailing_zeros() as usize / 8)
+        } else {
+            None
+        }
+    }
+}
+
+impl Iterator for BitMask {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let bit = self.lowest()?;
+        self.0 &= self.0 - 1;
+        Some(bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_bytes() {
+        let control = [0x12, EMPTY, DELETED, 0x7f, 0x12, EMPTY, 0x00, 0x13];
+        let group = Group::load(&control);
+
+        assert_eq!(group.match_tag(0x12).collect::<Vec<_>>(), vec![0, 4]);
+        assert_eq!(group.match_tag(0x00).collect::<Vec<_>>(), vec![6]);
+        assert_eq!(group.match_empty().collect::<Vec<_>>(), vec![1, 5]);
+        assert_eq!(group.match_free().collect::<Vec<_>>(), vec![1, 2, 5]);
+        assert_eq!(group.match_free().lowest(), Some(1));
+        assert!(!Group::load(&[0; GROUP_WIDTH]).match_empty().any());
+    }
+}
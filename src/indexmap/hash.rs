@@ -1,9 +1,10 @@
-//! Module implementing a simple FNV Hasher
-//! and related utilities needed for handling hashing
+//! Module implementing a simple FNV Hasher, a word-at-a-time hasher, a
+//! keyed SipHash-1-3 hasher and related utilities needed for handling hashing
 //! in an [`Index`] hash table.
 //!
 //! [`Index`]: struct.Index.html
 
+use core::convert::TryInto;
 use core::hash::{BuildHasher, Hash, Hasher};
 
 /// Hashes a `value` using a specified `hasher_builder`.
@@ -70,7 +71,7 @@ impl Clone for IndexHasher {
 /// Builder for [`IndexHasher`].
 ///
 /// [`IndexHasher`]: struct.IndexHasher.html
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct IndexHasherBuilder;
 
 impl BuildHasher for IndexHasherBuilder {
@@ -81,6 +82,279 @@ impl BuildHasher for IndexHasherBuilder {
     }
 }
 
+/// Multiplier of the PCG random number generator, mixes the bits of a word
+/// well.
+const MULTIPLE: u64 = 6_364_136_223_846_793_005;
+
+/// Multiplies `a` and `b` to 128 bits and folds the halves together with
+/// xor, every bit of the result depends on every bit of the inputs.
+fn folded_multiply(a: u64, b: u64) -> u64 {
+    let product = u128::from(a) * u128::from(b);
+    product as u64 ^ (product >> 64) as u64
+}
+
+/// Fast hasher consuming 8 bytes per step, after the fallback hasher of
+/// [aHash](https://github.com/tkaitchuck/aHash): every word is mixed into
+/// the state with a folded multiply.
+///
+/// Unlike [`IndexHasher`] (one multiply per byte) a 32 byte key takes five
+/// multiplies. It isn't keyed, so it is no better than FNV at resisting
+/// collision attacks, see [`SipHasher13`] for that.
+///
+/// [`IndexHasher`]: struct.IndexHasher.html
+/// [`SipHasher13`]: struct.SipHasher13.html
+#[derive(Debug, Clone)]
+pub struct FoldHasher {
+    state: u64,
+}
+
+impl FoldHasher {
+    pub fn new() -> FoldHasher {
+        FoldHasher {
+            state: 0x243f_6a88_85a3_08d3,
+        }
+    }
+
+    fn update(&mut self, word: u64) {
+        self.state = folded_multiply(self.state ^ word, MULTIPLE);
+    }
+}
+
+impl Hasher for FoldHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        // Keeps the zero padding of the last word from colliding with
+        // actual zero bytes
+        self.update(bytes.len() as u64);
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            self.update(u64::from_le_bytes(
+                word.try_into().expect("chunks of 8 bytes"),
+            ));
+        }
+        if !words.remainder().is_empty() {
+            self.update(read_partial(words.remainder()));
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        let rotation = (self.state & 63) as u32;
+        folded_multiply(self.state, 0x1319_8a2e_0370_7344).rotate_left(rotation)
+    }
+}
+
+impl Default for FoldHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builder for [`FoldHasher`].
+///
+/// [`FoldHasher`]: struct.FoldHasher.html
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FoldHasherBuilder;
+
+impl BuildHasher for FoldHasherBuilder {
+    type Hasher = FoldHasher;
+
+    fn build_hasher(&self) -> FoldHasher {
+        FoldHasher::new()
+    }
+}
+
+/// Hasher using [SipHash-1-3](https://en.wikipedia.org/wiki/SipHash), the
+/// hash function of the `std` `HashMap`, keyed with a 128-bit key.
+///
+/// Unlike [`IndexHasher`] its hashes can't be predicted without the key, so
+/// with a secret random key nobody can pick keys that all collide.
+///
+/// [`IndexHasher`]: struct.IndexHasher.html
+#[derive(Debug, Clone)]
+pub struct SipHasher13 {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    /// Bytes written so far
+    length: usize,
+    /// Bytes of an incomplete word, little endian
+    tail: u64,
+    /// Number of bytes in `tail`
+    ntail: usize,
+}
+
+impl SipHasher13 {
+    /// Creates a hasher keyed with `k0` and `k1`.
+    pub fn new_with_keys(k0: u64, k1: u64) -> SipHasher13 {
+        SipHasher13 {
+            v0: k0 ^ 0x736f_6d65_7073_6575,
+            v1: k1 ^ 0x646f_7261_6e64_6f6d,
+            v2: k0 ^ 0x6c79_6765_6e65_7261,
+            v3: k1 ^ 0x7465_6462_7974_6573,
+            length: 0,
+            tail: 0,
+            ntail: 0,
+        }
+    }
+
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13) ^ self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16) ^ self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21) ^ self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17) ^ self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    /// Mixes a full word of the message into the state (one compression
+    /// round, the "1" of SipHash-1-3).
+    fn compress(&mut self, word: u64) {
+        self.v3 ^= word;
+        self.round();
+        self.v0 ^= word;
+    }
+}
+
+/// The up to 8 `bytes` as a little endian word.
+fn read_partial(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |word, &byte| word << 8 | u64::from(byte))
+}
+
+impl Hasher for SipHasher13 {
+    fn write(&mut self, bytes: &[u8]) {
+        self.length += bytes.len();
+        let mut bytes = bytes;
+
+        if self.ntail != 0 {
+            let needed = 8 - self.ntail;
+            let (head, rest) = bytes.split_at(needed.min(bytes.len()));
+            self.tail |= read_partial(head) << (8 * self.ntail);
+            if head.len() < needed {
+                self.ntail += head.len();
+                return;
+            }
+            self.compress(self.tail);
+            self.ntail = 0;
+            bytes = rest;
+        }
+
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            self.compress(u64::from_le_bytes(
+                word.try_into().expect("chunks of 8 bytes"),
+            ));
+        }
+        let rest = words.remainder();
+        self.tail = read_partial(rest);
+        self.ntail = rest.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = self.clone();
+        state.compress((self.length as u64 & 0xff) << 56 | self.tail);
+        state.v2 ^= 0xff;
+        for _ in 0..3 {
+            state.round();
+        }
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+}
+
+/// Builder for [`SipHasher13`] with a fixed key.
+///
+/// [`SipHasher13`]: struct.SipHasher13.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SipHasherBuilder {
+    k0: u64,
+    k1: u64,
+}
+
+impl SipHasherBuilder {
+    /// Creates a builder whose hashers are keyed with `k0` and `k1`, they
+    /// should be random and secret.
+    pub fn new(k0: u64, k1: u64) -> SipHasherBuilder {
+        SipHasherBuilder { k0, k1 }
+    }
+}
+
+impl BuildHasher for SipHasherBuilder {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.k0, self.k1)
+    }
+}
+
+/// A hash function picked at run time, builds an [`AnyHasher`].
+///
+/// Lets a program decide between hash functions (for example from its
+/// command-line flags) without changing the type of its `Index`.
+///
+/// [`AnyHasher`]: enum.AnyHasher.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashFunction {
+    /// FNV-1, see [`IndexHasher`]: fast but easy to attack.
+    ///
+    /// [`IndexHasher`]: struct.IndexHasher.html
+    #[default]
+    Fnv,
+    /// Word-at-a-time, see [`FoldHasher`]: faster but as easy to attack.
+    ///
+    /// [`FoldHasher`]: struct.FoldHasher.html
+    Fold,
+    /// Keyed SipHash-1-3, see [`SipHasher13`].
+    ///
+    /// [`SipHasher13`]: struct.SipHasher13.html
+    SipHash13(SipHasherBuilder),
+}
+
+impl BuildHasher for HashFunction {
+    type Hasher = AnyHasher;
+
+    fn build_hasher(&self) -> AnyHasher {
+        match self {
+            HashFunction::Fnv => AnyHasher::Fnv(IndexHasher::new()),
+            HashFunction::Fold => AnyHasher::Fold(FoldHasher::new()),
+            HashFunction::SipHash13(builder) => AnyHasher::SipHash13(builder.build_hasher()),
+        }
+    }
+}
+
+/// The hasher of a [`HashFunction`].
+///
+/// [`HashFunction`]: enum.HashFunction.html
+#[derive(Debug, Clone)]
+pub enum AnyHasher {
+    Fnv(IndexHasher),
+    Fold(FoldHasher),
+    SipHash13(SipHasher13),
+}
+
+impl Hasher for AnyHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            AnyHasher::Fnv(hasher) => hasher.write(bytes),
+            AnyHasher::Fold(hasher) => hasher.write(bytes),
+            AnyHasher::SipHash13(hasher) => hasher.write(bytes),
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        match self {
+            AnyHasher::Fnv(hasher) => hasher.finish(),
+            AnyHasher::Fold(hasher) => hasher.finish(),
+            AnyHasher::SipHash13(hasher) => hasher.finish(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -94,4 +368,63 @@ mod tests {
 
         assert_eq!(hashed, 0xf1b59cbd9867ed1);
     }
+
+    #[test]
+    fn fold_hash_spreads_keys() {
+        // Keys sharing a prefix and differing in a single byte of a word
+        let keys: Vec<Vec<u8>> = (0..1024)
+            .map(|i| format!("user:{:011}", i).into_bytes())
+            .collect();
+        let hashes: Vec<u64> = keys
+            .iter()
+            .map(|key| make_hash(&FoldHasherBuilder, key))
+            .collect();
+
+        // The low bits pick the bucket, 1024 keys in 1024 buckets leave
+        // about 1/e of them empty
+        let mut buckets = vec![false; 1024];
+        for hash in &hashes {
+            buckets[*hash as usize % 1024] = true;
+        }
+        assert!(buckets.iter().filter(|&&full| full).count() > 550);
+
+        // Trailing zero bytes change the hash
+        assert_ne!(
+            make_hash(&FoldHasherBuilder, &b"abc"[..]),
+            make_hash(&FoldHasherBuilder, &b"abc\0"[..])
+        );
+        assert_eq!(make_hash(&HashFunction::Fold, &keys[7]), hashes[7]);
+    }
+
+    #[test]
+    fn siphash_matches_std() {
+        use std::collections::hash_map::DefaultHasher;
+
+        // `DefaultHasher::new` is SipHash-1-3 with both keys 0
+        let builder = SipHasherBuilder::new(0, 0);
+        let message: Vec<u8> = (0..64).collect();
+        for len in 0..message.len() {
+            let mut expected = DefaultHasher::new();
+            expected.write(&message[..len]);
+            let mut hasher = builder.build_hasher();
+            hasher.write(&message[..len]);
+            assert_eq!(hasher.finish(), expected.finish(), "{} bytes", len);
+        }
+
+        // Writes split at any point hash like a single write
+        let mut split = builder.build_hasher();
+        split.write(&message[..3]);
+        split.write(&message[3..13]);
+        split.write(&message[13..]);
+        let mut whole = builder.build_hasher();
+        whole.write(&message);
+        assert_eq!(split.finish(), whole.finish());
+
+        let keyed = SipHasherBuilder::new(1, 2);
+        assert_ne!(make_hash(&keyed, "key"), make_hash(&builder, "key"));
+        assert_eq!(
+            make_hash(&HashFunction::SipHash13(keyed), "key"),
+            make_hash(&keyed, "key")
+        );
+    }
 }
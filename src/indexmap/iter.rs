@@ -3,244 +3,225 @@
 //!
 //! [`Index`]: struct.Index.html
 
+use super::group::EMPTY;
 use super::Bucket;
 
-use core::cell::{Ref, RefMut};
+use alloc::vec::{self, Vec};
+use core::iter::Chain;
+use core::slice;
+
+/// The buckets of the table followed by the ones of the old table.
+type Buckets<'a, K, V> = Chain<slice::Iter<'a, Bucket<K, V>>, slice::Iter<'a, Bucket<K, V>>>;
+
+/// The buckets of the table followed by the ones of the old table.
+type BucketsMut<'a, K, V> =
+    Chain<slice::IterMut<'a, Bucket<K, V>>, slice::IterMut<'a, Bucket<K, V>>>;
+
+/// The owned buckets of the table followed by the ones of the old table.
+type IntoBuckets<K, V> = Chain<vec::IntoIter<Bucket<K, V>>, vec::IntoIter<Bucket<K, V>>>;
 
 /// Iterator over the entries of an [`Index`] hash table.
 ///
-/// The iterator ignores `Bucket::None` options and returns
-/// immutable references to the key-value pairs contained in `Bucket:Some(_)` options.
+/// The iterator ignores empty buckets and tombstones and returns
+/// references to the keys and values contained in `Bucket::Full(_)` buckets.
 ///
 /// [`Index`]: struct.Index.html
 pub struct Iter<'a, K, V> {
-    buckets: &'a [Bucket<K, V>],
-    buckets_len: usize,
-    counter: usize,
+    buckets: Buckets<'a, K, V>,
 }
 
-impl<K, V> Iter<'_, K, V> {
-    /// Creates a new iterator over the buckets in the
-    /// slice passed as an immutable reference.
-    pub fn new(buckets: &[Bucket<K, V>]) -> Iter<K, V> {
+impl<'a, K, V> Iter<'a, K, V> {
+    /// Creates a new iterator over the buckets in the slices passed as
+    /// immutable references: the table and the old table (empty unless the
+    /// [`Index`] is resizing).
+    ///
+    /// [`Index`]: struct.Index.html
+    pub fn new(buckets: &'a [Bucket<K, V>], old: &'a [Bucket<K, V>]) -> Iter<'a, K, V> {
         Iter {
-            buckets,
-            buckets_len: buckets.len(),
-            counter: 0,
+            buckets: buckets.iter().chain(old),
         }
     }
 }
 
 impl<'a, K, V> Iterator for Iter<'a, K, V> {
-    type Item = Ref<'a, (K, V)>; // immutable reference from a RefCell
+    type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.counter < self.buckets_len {
-            match &self.buckets[self.counter] {
-                Bucket::Some(pair) => {
-                    // returns borrowed pair
-                    self.counter += 1;
-                    Some(pair.borrow())
-                }
-
-                Bucket::None => {
-                    // ignores empty bucket
-                    self.counter += 1;
-                    self.next()
-                }
-            }
-        } else {
-            // end of iterator
-            None
-        }
+        // ignores empty buckets and tombstones
+        self.buckets.find_map(|bucket| match bucket {
+            Bucket::Full((key, value)) => Some((key, value)),
+            _ => None,
+        })
     }
 }
 
 /// Mutable iterator over the entries of an [`Index`] hash table.
 ///
-/// The iterator ignores `Bucket::None` options and returns
-/// mutable references to the key-value pairs contained in `Bucket:Some(_)` options.
+/// The iterator ignores empty buckets and tombstones and returns
+/// references to the keys and mutable references to the values contained in
+/// `Bucket::Full(_)` buckets. Keys stay immutable, changing them would
+/// move them to another bucket.
 ///
 /// [`Index`]: struct.Index.html
 pub struct IterMut<'a, K, V> {
-    buckets: &'a [Bucket<K, V>],
-    buckets_len: usize,
-    counter: usize,
+    buckets: BucketsMut<'a, K, V>,
 }
 
-impl<K, V> IterMut<'_, K, V> {
-    /// Creates a new iterator over the buckets in the
-    /// slice passed as an immutable reference. The interior mutability
-    /// of the `RefCell`s is taken advantage of inside the `next` method.
-    pub fn new(buckets: &[Bucket<K, V>]) -> IterMut<K, V> {
+impl<'a, K, V> IterMut<'a, K, V> {
+    /// Creates a new iterator over the buckets in the slices passed as
+    /// mutable references: the table and the old table (empty unless the
+    /// [`Index`] is resizing).
+    ///
+    /// [`Index`]: struct.Index.html
+    pub fn new(buckets: &'a mut [Bucket<K, V>], old: &'a mut [Bucket<K, V>]) -> IterMut<'a, K, V> {
         IterMut {
-            buckets,
-            buckets_len: buckets.len(),
-            counter: 0,
+            buckets: buckets.iter_mut().chain(old),
         }
     }
 }
 
 impl<'a, K, V> Iterator for IterMut<'a, K, V> {
-    type Item = RefMut<'a, (K, V)>; // mutable reference from a RefCell
+    type Item = (&'a K, &'a mut V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.counter < self.buckets_len {
-            match &self.buckets[self.counter] {
-                Bucket::Some(pair) => {
-                    // returns mutably borrowed pair
-                    self.counter += 1;
-                    Some(pair.borrow_mut())
-                }
-
-                Bucket::None => {
-                    // ignoring empty bucket
-                    self.counter += 1;
-                    self.next()
-                }
-            }
-        } else {
-            // end of iterator
-            None
-        }
+        // ignores empty buckets and tombstones
+        self.buckets.find_map(|bucket| match bucket {
+            Bucket::Full((key, value)) => Some((&*key, value)),
+            _ => None,
+        })
     }
 }
 
 /// Iterator over the keys of an [`Index`] hash table.
 ///
-/// The iterator ignores `Bucket::None` options and returns
-/// immutable references to the keys contained in `Bucket:Some(_)` options.
+/// The iterator ignores empty buckets and tombstones and returns
+/// references to the keys contained in `Bucket::Full(_)` buckets.
 ///
 /// [`Index`]: struct.Index.html
 pub struct Keys<'a, K, V> {
     inner: Iter<'a, K, V>,
 }
 
-impl<K, V> Keys<'_, K, V> {
+impl<'a, K, V> Keys<'a, K, V> {
     /// Creates a new iterator over the keys in the
-    /// slice passed as an immutable reference.
-    pub fn new(buckets: &[Bucket<K, V>]) -> Keys<K, V> {
+    /// slices passed as immutable references (see [`Iter::new`]).
+    ///
+    /// [`Iter::new`]: struct.Iter.html#method.new
+    pub fn new(buckets: &'a [Bucket<K, V>], old: &'a [Bucket<K, V>]) -> Keys<'a, K, V> {
         Keys {
-            inner: Iter::new(buckets),
+            inner: Iter::new(buckets, old),
         }
     }
 }
 
 impl<'a, K, V> Iterator for Keys<'a, K, V> {
-    type Item = Ref<'a, K>; // immutable reference from a RefCell
+    type Item = &'a K;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(pair) = self.inner.next() {
-            // returns borrowed key
-            Some(Ref::map(pair, |t| &t.0))
-        } else {
-            // end of iterator
-            None
-        }
+        self.inner.next().map(|(key, _)| key)
     }
 }
 
 /// Iterator over the values of an [`Index`] hash table.
 ///
-/// The iterator ignores `Bucket::None` options and returns
-/// immutable references to the values contained in `Bucket:Some(_)` options.
+/// The iterator ignores empty buckets and tombstones and returns
+/// references to the values contained in `Bucket::Full(_)` buckets.
 ///
 /// [`Index`]: struct.Index.html
 pub struct Values<'a, K, V> {
     inner: Iter<'a, K, V>,
 }
 
-impl<K, V> Values<'_, K, V> {
+impl<'a, K, V> Values<'a, K, V> {
     /// Creates a new iterator over the values in the
-    /// slice passed as an immutable reference.
-    pub fn new(buckets: &[Bucket<K, V>]) -> Values<K, V> {
+    /// slices passed as immutable references (see [`Iter::new`]).
+    ///
+    /// [`Iter::new`]: struct.Iter.html#method.new
+    pub fn new(buckets: &'a [Bucket<K, V>], old: &'a [Bucket<K, V>]) -> Values<'a, K, V> {
         Values {
-            inner: Iter::new(buckets),
+            inner: Iter::new(buckets, old),
         }
     }
 }
 
 impl<'a, K, V> Iterator for Values<'a, K, V> {
-    type Item = Ref<'a, V>; // immutable reference from a RefCell
+    type Item = &'a V;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(pair) = self.inner.next() {
-            // returns borrowed value
-            Some(Ref::map(pair, |t| &t.1))
-        } else {
-            // end of iterator
-            None
-        }
+        self.inner.next().map(|(_, value)| value)
     }
 }
 
 /// Mutable iterator over the values of an [`Index`] hash table.
 ///
-/// The iterator ignores `Bucket::None` options and returns
-/// mutable references to the values contained in `Bucket:Some(_)` options
-/// before returning them as simple key-value pairs.
+/// The iterator ignores empty buckets and tombstones and returns
+/// mutable references to the values contained in `Bucket::Full(_)` buckets.
 ///
 /// [`Index`]: struct.Index.html
 pub struct ValuesMut<'a, K, V> {
     inner: IterMut<'a, K, V>,
 }
 
-impl<K, V> ValuesMut<'_, K, V> {
+impl<'a, K, V> ValuesMut<'a, K, V> {
     /// Creates a new iterator over the values in the
-    /// slice passed as an immutable reference. The interior mutability
-    /// of the `RefCell`s is taken advantage of inside the `next` method.
-    pub fn new(buckets: &[Bucket<K, V>]) -> ValuesMut<K, V> {
+    /// slices passed as mutable references (see [`IterMut::new`]).
+    ///
+    /// [`IterMut::new`]: struct.IterMut.html#method.new
+    pub fn new(
+        buckets: &'a mut [Bucket<K, V>],
+        old: &'a mut [Bucket<K, V>],
+    ) -> ValuesMut<'a, K, V> {
         ValuesMut {
-            inner: IterMut::new(buckets),
+            inner: IterMut::new(buckets, old),
         }
     }
 }
 
 impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
-    type Item = RefMut<'a, V>; // mutable reference from a RefCell
+    type Item = &'a mut V;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(pair) = self.inner.next() {
-            // returns mutably borrowed value
-            Some(RefMut::map(pair, |t| &mut t.1))
-        } else {
-            // end of iterator
-            None
-        }
+        self.inner.next().map(|(_, value)| value)
     }
 }
 
 /// Iterator taking ownership of the entries of an [`Index`] hash table.
 ///
-/// The iterator ignores `Bucket::None` options and moves entries
-/// out of their `Bucket:Some(_)` options and `RefCell`s.
+/// The iterator ignores empty buckets and tombstones and moves entries
+/// out of their `Bucket::Full(_)` buckets.
 ///
-/// The `Drain` also updates the `len` field of the [`Index`] as it moves
-/// out it's content.
+/// The `Drain` also updates the `len` field and the control bytes (if
+/// any) of the [`Index`] as it moves out it's content. The entries of the
+/// old table (if the `Index` was resizing) come last.
 ///
 /// [`Index`]: struct.Index.html
 pub struct Drain<'a, K, V> {
-    buckets: &'a mut [Bucket<K, V>],
-    buckets_len: usize,
+    buckets: core::iter::Enumerate<slice::IterMut<'a, Bucket<K, V>>>,
+    control: &'a mut [u8],
     index_len: &'a mut usize,
-    counter: usize,
+    old: vec::IntoIter<Bucket<K, V>>,
 }
 
 impl<K, V> Drain<'_, K, V> {
     /// Creates a new iterator over the values in the
     /// slice passed as a mutable reference (since it will be moving out the entries
-    /// and replacing them with empty buckets). It also takes a mutable reference to the
-    /// `len` field of the associated [`Index`] since it needs to update it when removing
-    /// entries.
+    /// and replacing them with empty buckets). It also takes mutable references to the
+    /// control bytes (empty unless the layout uses them) and the `len` field of the
+    /// associated [`Index`] since it needs to update them when removing entries.
+    /// The old table, taken from the `Index`, is moved in.
     ///
     /// [`Index`]: struct.Index.html
-    pub fn new<'a>(buckets: &'a mut [Bucket<K, V>], index_len: &'a mut usize) -> Drain<'a, K, V> {
-        let buckets_len = buckets.len();
+    pub fn new<'a>(
+        buckets: &'a mut [Bucket<K, V>],
+        control: &'a mut [u8],
+        index_len: &'a mut usize,
+        old: Vec<Bucket<K, V>>,
+    ) -> Drain<'a, K, V> {
         Drain {
-            buckets,
-            buckets_len,
+            buckets: buckets.iter_mut().enumerate(),
+            control,
             index_len,
-            counter: 0,
+            old: old.into_iter(),
         }
     }
 }
@@ -249,26 +230,65 @@ impl<'a, K, V> Iterator for Drain<'a, K, V> {
     type Item = (K, V); // moved out key-value pair
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.counter < self.buckets_len {
-            match &self.buckets[self.counter] {
-                Bucket::Some(_) => {
-                    // returns moved out entry
-                    let removed = core::mem::replace(&mut self.buckets[self.counter], Bucket::None); // replacing with empty bucket
-                    let removed = removed.unwrap(); // safe because we know from match that it's a Some option
-                    self.counter += 1;
-                    *self.index_len -= 1; // updating len field of index
-                    Some(removed.into_inner()) // moving pair out of the RefCell
+        let index_len = &mut self.index_len;
+        let control = &mut self.control;
+        let drained = self.buckets.find_map(|(i, bucket)| {
+            if let Bucket::Full(_) = bucket {
+                // replacing with empty bucket and updating len field of index
+                **index_len -= 1;
+                if let Some(control) = control.get_mut(i) {
+                    *control = EMPTY;
                 }
-
-                Bucket::None => {
-                    // ignores empty bucket
-                    self.counter += 1;
-                    self.next()
+                match core::mem::replace(bucket, Bucket::Empty) {
+                    Bucket::Full(pair) => Some(pair),
+                    _ => unreachable!("we know from match that it's a full bucket"),
                 }
+            } else {
+                None
             }
-        } else {
-            // end of iterator
-            None
+        });
+        drained.or_else(|| {
+            self.old.find_map(|bucket| match bucket {
+                Bucket::Full(pair) => Some(pair),
+                _ => None,
+            })
+        })
+    }
+}
+
+/// Iterator taking ownership of the entries of an [`Index`] hash table,
+/// which it consumes (see `Index::into_iter`).
+///
+/// The iterator ignores empty buckets and tombstones and moves entries
+/// out of their `Bucket::Full(_)` buckets. The entries of the old table
+/// (if the `Index` was resizing) come last.
+///
+/// [`Index`]: struct.Index.html
+pub struct IntoIter<K, V> {
+    buckets: IntoBuckets<K, V>,
+}
+
+impl<K, V> IntoIter<K, V> {
+    /// Creates a new iterator over the buckets in the vectors passed by
+    /// value: the table and the old table (empty unless the [`Index`] is
+    /// resizing).
+    ///
+    /// [`Index`]: struct.Index.html
+    pub fn new(buckets: Vec<Bucket<K, V>>, old: Vec<Bucket<K, V>>) -> IntoIter<K, V> {
+        IntoIter {
+            buckets: buckets.into_iter().chain(old),
         }
     }
 }
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V); // moved out key-value pair
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // ignores empty buckets and tombstones
+        self.buckets.find_map(|bucket| match bucket {
+            Bucket::Full(pair) => Some(pair),
+            _ => None,
+        })
+    }
+}
@@ -5,28 +5,99 @@
 
 #![allow(unused)] // For now
 
+pub mod entry;
+mod group;
 pub mod hash;
 pub mod iter;
+mod stats;
 
+pub use self::entry::{Entry, OccupiedEntry, VacantEntry};
+use self::group::{Group, DELETED, EMPTY, GROUP_WIDTH};
 use self::hash::*;
 use self::iter::*;
+pub use self::stats::Stats;
 
+use alloc::boxed::Box;
 use core::borrow::Borrow;
-use core::cell::{Ref, RefCell, RefMut};
 use core::fmt;
 use core::hash::{BuildHasher, Hash};
+use core::iter::FromIterator;
 
 const DEFAULT_MAX_LOAD: f64 = 0.7;
+const DEFAULT_MIN_LOAD: f64 = 0.0;
 const DEFAULT_GROWTH_POLICY: f64 = 2.0;
 const DEFAULT_PROBING: fn(usize, usize) -> usize = |hash, i| hash + i + i * i;
 
 const DEFAULT_INITIAL_CAPACITY: usize = 1; // not handling zero sized
 
-/// Alias for handling buckets.
-pub type Bucket<K, V> = Option<RefCell<(K, V)>>;
+/// Tables of at least this capacity are resized incrementally (see `grow`).
+const INCREMENTAL_RESIZE_CAPACITY: usize = 1024;
+
+/// Buckets of the old table visited by every operation that moves entries
+/// while the `Index` is resizing (see `migrate`).
+const MIGRATE_BUCKETS: usize = 64;
+
+/// A slot of the table.
+#[derive(Debug, Clone)]
+pub enum Bucket<K, V> {
+    /// Never held an entry, terminates a lookup.
+    Empty,
+    /// Held an entry that got removed, lookups have to probe past it.
+    Tombstone,
+    /// Holds a key-value pair.
+    Full((K, V)),
+}
+
+/// How an [`Index`] places its entries in the table.
+///
+/// [`Index`]: struct.Index.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Open addressing with the probing function of the [`Parameters`],
+    /// removed entries leave tombstones behind. A lookup for a missing key
+    /// only stops at an empty bucket.
+    ///
+    /// [`Parameters`]: struct.Parameters.html
+    Probing,
+    /// Robin Hood hashing: linear probing where an insertion takes the bucket
+    /// of any entry closer to its home bucket than the new one would be.
+    /// Every entry stores its probe distance, so a lookup for a missing key
+    /// stops as soon as it passes an entry with a shorter distance, and
+    /// removals shift the following entries back instead of leaving
+    /// tombstones. Probe lengths stay short even at a high `max_load`.
+    ///
+    /// The probing function of the [`Parameters`] isn't used.
+    ///
+    /// [`Parameters`]: struct.Parameters.html
+    RobinHood,
+    /// Group probing like in SwissTable: a separate array holds a control
+    /// byte with a 7-bit tag of the hash for every bucket. Lookups check
+    /// the tags of a group of 8 buckets at once and only compare the keys
+    /// of buckets with a matching tag, moving on to the next group
+    /// (linearly) while the group is full. The capacity is rounded up to a
+    /// multiple of 8.
+    ///
+    /// The probing function of the [`Parameters`] isn't used.
+    ///
+    /// [`Parameters`]: struct.Parameters.html
+    Group,
+}
+
+/// The capacity of a table of `layout` that holds at least `capacity` buckets.
+fn table_capacity(capacity: usize, layout: Layout) -> usize {
+    let capacity = if capacity == 0 {
+        DEFAULT_INITIAL_CAPACITY
+    } else {
+        capacity
+    };
+    match (layout, capacity % GROUP_WIDTH) {
+        (Layout::Group, 0) | (Layout::Probing, _) | (Layout::RobinHood, _) => capacity,
+        (Layout::Group, rest) => capacity + GROUP_WIDTH - rest,
+    }
+}
 
 /// Alias for handling results of a lookup with the `find` method.
-type Find<'a, K, V> = (Option<&'a RefCell<(K, V)>>, Option<usize>);
+type Find<'a, K, V> = (Option<&'a (K, V)>, Option<usize>);
 
 /// Parameters needed in the configuration
 /// of an [`Index`] hash table.
@@ -35,13 +106,15 @@ type Find<'a, K, V> = (Option<&'a RefCell<(K, V)>>, Option<usize>);
 ///
 /// ```
 /// use std::collections::hash_map::RandomState;
-/// use index::{Index, Parameters};
+/// use index::{Index, Layout, Parameters};
 ///
 /// let params = Parameters {
 ///     max_load: 0.7,
+///     min_load: 0.1,
 ///     growth_policy: 2.0,
 ///     hasher_builder: RandomState::new(),
 ///     probe: |hash, i| (hash as f64 + (i as f64 / 2.0) + ((i*i) as f64 / 2.0)) as usize,
+///     layout: Layout::Probing,
 /// };
 ///
 /// let mut index = Index::with_capacity_and_parameters(10, params);
@@ -55,6 +128,11 @@ pub struct Parameters<S> {
     /// Maximum load factor accepted before the table is resized. Default is `0.7`.
     pub max_load: f64,
 
+    /// Load factor below which removing an entry shrinks the table, halving
+    /// it until the load is at most halfway between `min_load` and
+    /// `max_load`. Default is `0.0`, the table never shrinks on its own.
+    pub min_load: f64,
+
     /// Ratio by which the table's capacity is grown. Default is `2`.
     pub growth_policy: f64,
 
@@ -66,6 +144,48 @@ pub struct Parameters<S> {
 
     /// Open addressing probing policy. Default is quadratic probing: `hash + i + i*i`
     pub probe: fn(hash: usize, i: usize) -> usize,
+
+    /// Placement of the entries (see [`Layout`]). Default is `Layout::Probing`.
+    ///
+    /// [`Layout`]: enum.Layout.html
+    pub layout: Layout,
+}
+
+impl Default for Parameters<IndexHasherBuilder> {
+    /// The parameters of `Index::new`, see the fields for the defaults.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::{Index, Layout, Parameters};
+    ///
+    /// let params = Parameters {
+    ///     layout: Layout::RobinHood,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let mut index: Index<&str, &str> = Index::with_capacity_and_parameters(10, params);
+    /// ```
+    fn default() -> Self {
+        Parameters::with_hasher(IndexHasherBuilder)
+    }
+}
+
+impl<S> Parameters<S> {
+    /// The default parameters with `hasher_builder` instead of
+    /// [`IndexHasherBuilder`].
+    ///
+    /// [`IndexHasherBuilder`]: hash/struct.IndexHasherBuilder.html
+    pub fn with_hasher(hasher_builder: S) -> Parameters<S> {
+        Parameters {
+            max_load: DEFAULT_MAX_LOAD,
+            min_load: DEFAULT_MIN_LOAD,
+            growth_policy: DEFAULT_GROWTH_POLICY,
+            hasher_builder,
+            probe: DEFAULT_PROBING,
+            layout: Layout::Probing,
+        }
+    }
 }
 
 /// Simple implementation of a hash table using safe-rust.
@@ -99,7 +219,24 @@ pub struct Index<K, V, S = IndexHasherBuilder> {
     params: Parameters<S>,
     capacity: usize,
     len: usize,
+    tombstones: usize,
     table: Vec<Bucket<K, V>>,
+    /// Probe distance of the entry in every full bucket, only used by
+    /// `Layout::RobinHood`
+    distances: Vec<u32>,
+    /// Control byte of every bucket, only used by `Layout::Group`
+    control: Vec<u8>,
+    /// The table the entries are being moved out of while the `Index` is
+    /// resizing
+    old: Option<Box<Index<K, V, S>>>,
+    /// Buckets of `old` before this one are free
+    migrated: usize,
+    /// Empty buckets for the table of the next resize, initialized ahead
+    /// of time (see `prepare`)
+    spare: Vec<Bucket<K, V>>,
+    /// The capacity the `Index` was created with, removals never shrink it
+    /// below
+    min_capacity: usize,
 }
 
 impl<K, V> Index<K, V, IndexHasherBuilder>
@@ -129,15 +266,7 @@ where
     /// let mut index: Index<String, Vec<i32>> = Index::with_capacity(1312);
     /// ```
     pub fn with_capacity(capacity: usize) -> Index<K, V, IndexHasherBuilder> {
-        Index::with_capacity_and_parameters(
-            capacity,
-            Parameters {
-                max_load: DEFAULT_MAX_LOAD,
-                growth_policy: DEFAULT_GROWTH_POLICY,
-                hasher_builder: IndexHasherBuilder {},
-                probe: DEFAULT_PROBING,
-            },
-        )
+        Index::with_capacity_and_parameters(capacity, Parameters::default())
     }
 }
 
@@ -172,6 +301,93 @@ impl<K, V, S> Index<K, V, S> {
         self.params.growth_policy
     }
 
+    /// Changes the maximum load factor, the table is resized on the next
+    /// insertion that finds it above the new limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_load` isn't between 0 and 1 (exclusive).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index: Index<&str, i32> = Index::new();
+    ///
+    /// index.set_max_load(0.5);
+    /// assert_eq!(index.max_load(), 0.5);
+    /// ```
+    pub fn set_max_load(&mut self, max_load: f64) {
+        assert!(
+            max_load > 0.0 && max_load < 1.0,
+            "max load must be between 0 and 1"
+        );
+        self.params.max_load = max_load;
+    }
+
+    /// Returns the load factor below which removals shrink the `Index`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index: Index<String, Vec<i32>> = Index::new();
+    ///
+    /// assert_eq!(index.min_load(), 0.0); // never shrinks by default
+    /// ```
+    pub fn min_load(&self) -> f64 {
+        self.params.min_load
+    }
+
+    /// Changes the load factor below which removals shrink the `Index`,
+    /// `0.0` turns shrinking off. It has no effect unless it is smaller
+    /// than the maximum load factor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min_load` isn't between 0 (inclusive) and 1 (exclusive).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index: Index<&str, i32> = Index::new();
+    ///
+    /// index.set_min_load(0.1);
+    /// assert_eq!(index.min_load(), 0.1);
+    /// ```
+    pub fn set_min_load(&mut self, min_load: f64) {
+        assert!(
+            (0.0..1.0).contains(&min_load),
+            "min load must be between 0 and 1"
+        );
+        self.params.min_load = min_load;
+    }
+
+    /// Changes the ratio by which the table's capacity is grown.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `growth_policy` isn't greater than 1.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index: Index<&str, i32> = Index::new();
+    ///
+    /// index.set_growth_policy(1.5);
+    /// assert_eq!(index.growth_policy(), 1.5);
+    /// ```
+    pub fn set_growth_policy(&mut self, growth_policy: f64) {
+        assert!(growth_policy > 1.0, "growth policy must be greater than 1");
+        self.params.growth_policy = growth_policy;
+    }
+
     /// Returns a reference to the hasher builder used in the `Index`.
     ///
     /// # Example
@@ -189,6 +405,14 @@ impl<K, V, S> Index<K, V, S> {
         &self.params.hasher_builder
     }
 
+    /// Returns `true` while an incremental resize moves entries from the
+    /// old table (see [`migrate`]).
+    ///
+    /// [`migrate`]: struct.Index.html#method.migrate
+    pub fn is_resizing(&self) -> bool {
+        self.old.is_some()
+    }
+
     /// Returns the probing function pointer of the `Index`.
     ///
     /// # Example
@@ -206,6 +430,21 @@ impl<K, V, S> Index<K, V, S> {
         self.params.probe
     }
 
+    /// Returns the layout of the `Index`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::{Index, Layout};
+    ///
+    /// let mut index: Index<String, Vec<i32>> = Index::new();
+    ///
+    /// assert_eq!(index.layout(), Layout::Probing); // default layout
+    /// ```
+    pub fn layout(&self) -> Layout {
+        self.params.layout
+    }
+
     /// Returns the capacity of the `Index`.
     ///
     /// # Example
@@ -239,7 +478,7 @@ impl<K, V, S> Index<K, V, S> {
     /// assert_eq!(index.capacity(), 6);
     /// ```
     pub fn len(&self) -> usize {
-        self.len
+        self.len + self.old.as_ref().map_or(0, |old| old.len)
     }
 
     /// Returns `true` if the `Index` contains no elements.
@@ -254,7 +493,7 @@ impl<K, V, S> Index<K, V, S> {
     /// assert!(index.is_empty());
     /// ```
     pub fn is_empty(&self) -> bool {
-        self.len == 0
+        self.len() == 0
     }
 
     /// Returns the current load factor of the `Index`.
@@ -273,7 +512,7 @@ impl<K, V, S> Index<K, V, S> {
     /// assert_eq!(index.load(), 0.5);
     /// ```
     pub fn load(&self) -> f64 {
-        (self.len as f64) / (self.capacity as f64)
+        (self.len() as f64) / (self.capacity as f64)
     }
 
     /// Clear the `Index`, replacing all entries with empty buckets.
@@ -298,13 +537,20 @@ impl<K, V, S> Index<K, V, S> {
     /// ```
     pub fn clear(&mut self) {
         for entry in self.table.iter_mut() {
-            *entry = Bucket::None;
+            *entry = Bucket::Empty;
+        }
+        for control in self.control.iter_mut() {
+            *control = EMPTY;
         }
         self.len = 0;
+        self.tombstones = 0;
+        self.old = None;
+        self.migrated = 0;
+        self.spare = Vec::new();
     }
 
     /// Returns an iterator over the keys of the `Index`.
-    /// The iterator's associated type is `Ref<'a, K>`.
+    /// The iterator's associated type is `&'a K`.
     ///
     /// # Example
     ///
@@ -324,11 +570,11 @@ impl<K, V, S> Index<K, V, S> {
     /// assert_eq!(index.len(), index.keys().count());
     /// ```
     pub fn keys(&self) -> Keys<K, V> {
-        Keys::new(&self.table)
+        Keys::new(&self.table, self.old_table())
     }
 
     /// Returns an iterator over the values of the `Index`.
-    /// The iterator's associated type is `Ref<'a, V>`.
+    /// The iterator's associated type is `&'a V`.
     ///
     /// # Example
     ///
@@ -348,11 +594,11 @@ impl<K, V, S> Index<K, V, S> {
     /// assert_eq!(index.len(), index.values().count());
     /// ```
     pub fn values(&self) -> Values<K, V> {
-        Values::new(&self.table)
+        Values::new(&self.table, self.old_table())
     }
 
     /// Returns a mutable iterator over the values of the `Index`.
-    /// The iterator's associated type is `RefMut<'a, V>`.
+    /// The iterator's associated type is `&'a mut V`.
     ///
     /// # Example
     ///
@@ -365,19 +611,22 @@ impl<K, V, S> Index<K, V, S> {
     /// index.insert("ferris", "https://www.rustacean.net/more-crabby-things/dancing-ferris.gif");
     /// index.insert("did you know ?", "Rust is kinda cool !");
     ///
-    /// for mut value in index.values_mut() {
+    /// for value in index.values_mut() {
     ///     *value = "overwritten!";
     /// }
     ///
     /// assert_eq!(*index.get("ferris").unwrap(), "overwritten!");
     ///
     /// ```
-    pub fn values_mut(&self) -> ValuesMut<K, V> {
-        ValuesMut::new(&self.table)
+    pub fn values_mut(&mut self) -> ValuesMut<K, V> {
+        match &mut self.old {
+            Some(old) => ValuesMut::new(&mut self.table, &mut old.table),
+            None => ValuesMut::new(&mut self.table, &mut []),
+        }
     }
 
     /// Return an iterator over the key-value pairs of the `Index`.
-    /// The iterator's associated type is `Ref<'a, (K, V)>`.
+    /// The iterator's associated type is `(&'a K, &'a V)`.
     ///
     /// # Example
     ///
@@ -397,11 +646,11 @@ impl<K, V, S> Index<K, V, S> {
     /// assert_eq!(index.len(), index.iter().count());
     /// ```
     pub fn iter(&self) -> Iter<K, V> {
-        Iter::new(&self.table)
+        Iter::new(&self.table, self.old_table())
     }
 
     /// Return a mutable iterator over the key-value pairs of the `Index`.
-    /// The iterator's associated type is `RefMut<'a, (K, V)>`.
+    /// The iterator's associated type is `(&'a K, &'a mut V)`.
     ///
     /// # Example
     ///
@@ -414,14 +663,17 @@ impl<K, V, S> Index<K, V, S> {
     /// index.insert("ferris", "https://www.rustacean.net/more-crabby-things/dancing-ferris.gif");
     /// index.insert("did you know ?", "Rust is kinda cool !");
     ///
-    /// for mut entry in index.iter_mut() {
-    ///     entry.1 = entry.0.clone();
+    /// for (key, value) in index.iter_mut() {
+    ///     *value = *key;
     /// }
     ///
     /// assert_eq!(*index.get("ferris").unwrap(), "ferris");
     /// ```
-    pub fn iter_mut(&self) -> IterMut<K, V> {
-        IterMut::new(&self.table)
+    pub fn iter_mut(&mut self) -> IterMut<K, V> {
+        match &mut self.old {
+            Some(old) => IterMut::new(&mut self.table, &mut old.table),
+            None => IterMut::new(&mut self.table, &mut []),
+        }
     }
 
     /// Returns iterator taking ownership and moving out the key-value pairs of the `Index`.
@@ -444,7 +696,198 @@ impl<K, V, S> Index<K, V, S> {
     /// assert!(v.contains(&("salutation", "Hello, world!")));
     /// ```
     pub fn drain(&mut self) -> Drain<K, V> {
-        Drain::new(&mut self.table, &mut self.len)
+        if self.tombstones > 0 {
+            for entry in self.table.iter_mut() {
+                if let Bucket::Tombstone = entry {
+                    *entry = Bucket::Empty;
+                }
+            }
+            for control in self.control.iter_mut() {
+                if *control == DELETED {
+                    *control = EMPTY;
+                }
+            }
+            self.tombstones = 0;
+        }
+        let old = self.old.take().map_or(Vec::new(), |old| old.table);
+        self.migrated = 0;
+        Drain::new(&mut self.table, &mut self.control, &mut self.len, old)
+    }
+
+    /// Returns the number of buckets [`get_at`] and [`remove_at`] accept:
+    /// the capacity, plus the capacity of the old table while the `Index`
+    /// is resizing (its buckets come after the ones of the table).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let index: Index<&str, &str> = Index::with_capacity(10);
+    ///
+    /// assert_eq!(index.buckets(), 10);
+    /// ```
+    ///
+    /// [`get_at`]: struct.Index.html#method.get_at
+    /// [`remove_at`]: struct.Index.html#method.remove_at
+    pub fn buckets(&self) -> usize {
+        self.capacity + self.old.as_ref().map_or(0, |old| old.capacity)
+    }
+
+    /// The buckets of the old table, none unless the `Index` is resizing.
+    fn old_table(&self) -> &[Bucket<K, V>] {
+        self.old.as_ref().map_or(&[], |old| &old.table)
+    }
+
+    /// Returns a reference to the key-value pair stored in bucket `i`,
+    /// or `None` if the bucket is empty.
+    ///
+    /// Together with [`remove_at`] this allows walking the table
+    /// bucket by bucket (`i` must be smaller than [`buckets`]).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index = Index::with_capacity(10);
+    ///
+    /// index.insert("salutation", "Hello, world!");
+    ///
+    /// let found: Vec<usize> = (0..index.buckets()).filter(|i| index.get_at(*i).is_some()).collect();
+    /// assert_eq!(found.len(), 1);
+    /// ```
+    ///
+    /// [`remove_at`]: struct.Index.html#method.remove_at
+    /// [`buckets`]: struct.Index.html#method.buckets
+    pub fn get_at(&self, i: usize) -> Option<&(K, V)> {
+        if i >= self.capacity {
+            return self.old.as_ref()?.get_at(i - self.capacity);
+        }
+        match &self.table[i] {
+            Bucket::Full(pair) => Some(pair),
+            _ => None,
+        }
+    }
+
+    /// Removes the key-value pair stored in bucket `i` and returns it,
+    /// or `None` if the bucket is empty.
+    ///
+    /// With `Layout::RobinHood` the following entries of the cluster move
+    /// back by one bucket, so bucket `i` may hold another entry afterwards.
+    /// The table is never resized here, walks over the buckets stay valid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index = Index::with_capacity(10);
+    ///
+    /// index.insert("salutation", "Hello, world!");
+    ///
+    /// for i in 0..index.buckets() {
+    ///     index.remove_at(i);
+    /// }
+    /// assert!(index.is_empty());
+    /// ```
+    pub fn remove_at(&mut self, i: usize) -> Option<(K, V)> {
+        if i >= self.capacity {
+            return self.old.as_mut()?.remove_at(i - self.capacity);
+        }
+        match &self.table[i] {
+            Bucket::Full(_) => {}
+            _ => return None,
+        }
+
+        self.len -= 1;
+        let removed = match self.params.layout {
+            Layout::Probing => {
+                self.tombstones += 1;
+                core::mem::replace(&mut self.table[i], Bucket::Tombstone)
+            }
+            Layout::RobinHood => {
+                let removed = core::mem::replace(&mut self.table[i], Bucket::Empty);
+                self.shift_back(i);
+                removed
+            }
+            Layout::Group => {
+                // No lookup ever went past a group that still has an empty
+                // bucket, a tombstone is only needed in full groups
+                let start = i - i % GROUP_WIDTH;
+                if Group::load(&self.control[start..]).match_empty().any() {
+                    self.control[i] = EMPTY;
+                    core::mem::replace(&mut self.table[i], Bucket::Empty)
+                } else {
+                    self.control[i] = DELETED;
+                    self.tombstones += 1;
+                    core::mem::replace(&mut self.table[i], Bucket::Tombstone)
+                }
+            }
+        };
+        match removed {
+            Bucket::Full(pair) => Some(pair),
+            _ => unreachable!("we know from match that it's a full bucket"),
+        }
+    }
+
+    /// Backward-shift deletion: moves the entries following the emptied
+    /// bucket `hole` one bucket closer to their home, up to the next empty
+    /// bucket or entry already in its home bucket.
+    fn shift_back(&mut self, mut hole: usize) {
+        loop {
+            let next = (hole + 1) % self.capacity;
+            match self.table[next] {
+                Bucket::Full(_) if self.distances[next] > 0 => {
+                    self.table.swap(hole, next);
+                    self.distances[hole] = self.distances[next] - 1;
+                    hole = next;
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Returns the key-value pair stored in the full bucket `i`.
+    fn pair_mut(&mut self, i: usize) -> &mut (K, V) {
+        match &mut self.table[i] {
+            Bucket::Full(pair) => pair,
+            _ => panic!("bucket {} isn't full", i),
+        }
+    }
+
+    /// Stores a new key-value pair with hash `hash` in bucket `i`, the free
+    /// bucket returned by `find`.
+    ///
+    /// With `Layout::RobinHood` bucket `i` may be taken, its entry and the
+    /// rest of the cluster then move forward by one bucket.
+    fn fill(&mut self, i: usize, hash: usize, key: K, value: V) {
+        if self.params.layout == Layout::Group {
+            self.control[i] = group::tag(hash);
+        }
+        let mut carried = Bucket::Full((key, value));
+        let mut distance = ((i + self.capacity - hash % self.capacity) % self.capacity) as u32;
+        let mut i = i;
+        loop {
+            carried = core::mem::replace(&mut self.table[i], carried);
+            match carried {
+                Bucket::Full(_) => {
+                    distance = core::mem::replace(&mut self.distances[i], distance) + 1;
+                    i = (i + 1) % self.capacity;
+                }
+                Bucket::Tombstone => {
+                    self.tombstones -= 1;
+                    break;
+                }
+                Bucket::Empty => {
+                    if self.params.layout == Layout::RobinHood {
+                        self.distances[i] = distance;
+                    }
+                    break;
+                }
+            }
+        }
+        self.len += 1;
     }
 }
 
@@ -463,13 +906,15 @@ where
     ///
     /// ```
     /// use std::collections::hash_map::RandomState;
-    /// use index::{Index, Parameters};
+    /// use index::{Index, Layout, Parameters};
     ///
     /// let params = Parameters {
-    ///     max_load: 0.7,
+    ///     max_load: 0.9,
+    ///     min_load: 0.0,
     ///     growth_policy: 2.0,
     ///     hasher_builder: RandomState::new(),
-    ///     probe: |hash, i| (hash as f64 + (i as f64 / 2.0) + ((i*i) as f64 / 2.0)) as usize,
+    ///     probe: |hash, i| hash + i, // not used by Robin Hood
+    ///     layout: Layout::RobinHood,
     /// };
     ///
     /// let mut index = Index::with_capacity_and_parameters(10, params);
@@ -479,16 +924,46 @@ where
     ///
     /// [`Parameters`]: struct.Parameters.html
     pub fn with_capacity_and_parameters(capacity: usize, params: Parameters<S>) -> Index<K, V, S> {
-        let capacity = if capacity == 0 {
-            DEFAULT_INITIAL_CAPACITY
-        } else {
-            capacity
+        Self::with_buckets(capacity, params, Vec::new())
+    }
+
+    /// Creates an empty `Index` with default initial capacity and default
+    /// parameters, hashing keys with `hasher_builder`.
+    pub fn with_hasher(hasher_builder: S) -> Index<K, V, S> {
+        Index::with_capacity_and_parameters(
+            DEFAULT_INITIAL_CAPACITY,
+            Parameters::with_hasher(hasher_builder),
+        )
+    }
+
+    /// Creates an empty `Index` like `with_capacity_and_parameters`, reusing
+    /// the empty buckets in `table`.
+    fn with_buckets(
+        capacity: usize,
+        params: Parameters<S>,
+        table: Vec<Bucket<K, V>>,
+    ) -> Index<K, V, S> {
+        let capacity = table_capacity(capacity, params.layout);
+        let distances = match params.layout {
+            Layout::RobinHood => vec![0; capacity],
+            _ => Vec::new(),
+        };
+        let control = match params.layout {
+            Layout::Group => vec![EMPTY; capacity],
+            _ => Vec::new(),
         };
         let mut index = Index {
             params,
             capacity,
             len: 0,
-            table: Vec::with_capacity(capacity),
+            tombstones: 0,
+            table,
+            distances,
+            control,
+            old: None,
+            migrated: 0,
+            spare: Vec::new(),
+            min_capacity: capacity,
         };
 
         Self::init_table(&mut index.table, index.capacity);
@@ -498,8 +973,9 @@ where
 
     /// Initializes inner table with empty buckets according to specified capacity.
     fn init_table(table: &mut Vec<Bucket<K, V>>, capacity: usize) {
-        for _ in 0..capacity {
-            table.push(Bucket::None);
+        table.reserve_exact(capacity - table.len());
+        while table.len() < capacity {
+            table.push(Bucket::Empty);
         }
 
         // useless but that paranoia
@@ -515,6 +991,7 @@ where
     /// the new `Index` automatically).
     fn resize(&mut self, new_capacity: usize) {
         let mut new_index = Self::with_capacity_and_parameters(new_capacity, self.params.clone());
+        new_index.min_capacity = self.min_capacity;
 
         for (key, value) in self.drain() {
             new_index.insert(key, value);
@@ -524,107 +1001,626 @@ where
     }
 
     /// Grows `Index` according to growth policy.
+    ///
+    /// If most of the used buckets are tombstones the table is just rehashed
+    /// with the same capacity instead.
+    ///
+    /// Large tables are resized incrementally: the table becomes the old
+    /// table and the operations that follow move its entries over a few
+    /// buckets at a time (see `migrate`), lookups check both tables
+    /// meanwhile. No single insertion pays for rehashing millions of
+    /// entries. If the new table has to grow again before the old one is
+    /// empty, the rest of the old table is moved over first.
     fn grow(&mut self) {
-        let new_cap = (self.capacity as f64 * self.params.growth_policy) as usize;
-        self.resize(new_cap);
+        self.rehash(self.next_capacity());
     }
 
-    /// Searches for an entry according to specified hash and discriminating closure.
-    ///
-    /// See alias definition of `Find<'a, K, V>` at the top of this file for more details.
-    fn find<F>(&self, hash: usize, f: F) -> Find<K, V>
-    where
-        F: Fn(Ref<(K, V)>) -> bool,
-    {
-        for i in 0..self.capacity {
-            let probe = (self.params.probe)(hash, i) % self.capacity;
+    /// Resizes the table to `new_cap`, incrementally for large tables (see
+    /// `grow`).
+    fn rehash(&mut self, new_cap: usize) {
+        // Finish the pending resize, which only visits the buckets of the old
+        // table left, rather than rebuilding both tables at once
+        if let Some(old) = &self.old {
+            let buckets = old.buckets();
+            self.migrate_buckets(buckets, false);
+        }
 
-            match &self.table[probe] {
-                Some(pair) if f(pair.borrow()) => return (Some(pair), Some(probe)), // found matching bucket
-                None => return (None, Some(probe)), // found empty bucket
-                Some(_) => continue,
-            }
+        let new_cap = table_capacity(new_cap, self.params.layout);
+        // the spare buckets may be meant for another capacity
+        let mut spare = core::mem::take(&mut self.spare);
+        if spare.capacity() > new_cap {
+            spare = Vec::new();
         }
 
-        (None, None) // found nothing
+        if self.old.is_some() || new_cap < INCREMENTAL_RESIZE_CAPACITY {
+            self.resize(new_cap);
+        } else {
+            let mut table = Self::with_buckets(new_cap, self.params.clone(), spare);
+            table.min_capacity = self.min_capacity;
+            let old = core::mem::replace(self, table);
+            self.old = Some(Box::new(old));
+        }
     }
 
-    /// Inserts key-value pair in the `Index`.
+    /// Shrinks the table if the load dropped below `min_load`, like
+    /// `remove` does after every removal. Call it after removing entries
+    /// with [`remove_at`], which never resizes.
     ///
-    /// If it encounters an occupied bucket with the same key, it will replace the
-    /// entry according to the new value and return the old bucket.
+    /// The capacity is halved until the load is at most halfway between
+    /// `min_load` and `max_load`, so that [`scan`] doesn't miss entries
+    /// (unlike [`shrink_to`]). The table isn't shrunk while it is resizing,
+    /// nor below the capacity the `Index` was created with.
     ///
-    /// The function also verifies before anything else that the load factor is lesser
-    /// than the maximum accepted load, if not it will grow the `Index` before proceeding to the insertion.
+    /// # Example
     ///
-    /// If the lookup returns no valid result, the insertion is considered impossible and
-    /// the function will grow the `Index` and retry to insert the pair.
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index = Index::new();
+    /// index.set_min_load(0.1);
+    ///
+    /// for i in 0..100 {
+    ///     index.insert(i, i);
+    /// }
+    /// for i in 0..index.buckets() {
+    ///     if index.len() > 10 {
+    ///         index.remove_at(i);
+    ///     }
+    /// }
+    /// index.shrink_if_underloaded();
+    ///
+    /// assert!(index.capacity() < 100);
+    /// ```
+    ///
+    /// [`remove_at`]: struct.Index.html#method.remove_at
+    /// [`scan`]: struct.Index.html#method.scan
+    /// [`shrink_to`]: struct.Index.html#method.shrink_to
+    pub fn shrink_if_underloaded(&mut self) {
+        let (min_load, max_load) = (self.params.min_load, self.params.max_load);
+        if self.old.is_some() || min_load >= max_load || self.load() >= min_load {
+            return;
+        }
+        // Only halving keeps the old capacity a multiple of the new one,
+        // which `scan` relies on
+        let target = (min_load + max_load) / 2.0;
+        let mut new_cap = self.capacity;
+        while new_cap % 2 == 0
+            && new_cap / 2 >= self.min_capacity
+            && table_capacity(new_cap / 2, self.params.layout) == new_cap / 2
+            && self.len() as f64 / (new_cap / 2) as f64 <= target
+        {
+            new_cap /= 2;
+        }
+        if new_cap < self.capacity {
+            self.rehash(new_cap);
+        }
+    }
+
+    /// Shrinks the capacity as much as possible while staying below the
+    /// maximum load factor, releasing the memory of the buckets.
     ///
     /// # Example
     ///
     /// ```
     /// use index::Index;
     ///
-    /// let mut index = Index::with_capacity(2);
-    ///
+    /// let mut index = Index::with_capacity(100);
     /// index.insert("key", "value");
     ///
+    /// index.shrink_to_fit();
+    ///
+    /// assert_eq!(index.capacity(), 2);
     /// assert_eq!(*index.get("key").unwrap(), "value");
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.shrink_to(0);
+    }
+
+    /// Shrinks the capacity to `min_capacity`, or as close as the maximum
+    /// load factor allows. Does nothing if the capacity is already lower.
     ///
-    /// index.insert("key", "new value");
+    /// Unlike the shrinking done by `remove`, the table is resized all at
+    /// once: the memory is released when the call returns.
     ///
-    /// assert_eq!(*index.get("key").unwrap(), "new value");
+    /// # Example
     ///
-    /// assert_eq!(index.len(), 1);
-    /// assert_eq!(index.capacity(), 2);
+    /// ```
+    /// use index::Index;
     ///
-    /// index.insert("salutation", "Hello, world!");
-    /// index.insert("ferris", "https://www.rustacean.net/more-crabby-things/dancing-ferris.gif");
-    /// index.insert("did you know ?", "Rust is kinda cool guys !");
+    /// let mut index = Index::with_capacity(100);
+    /// index.insert("key", "value");
     ///
-    /// assert_eq!(index.len(), 4);
-    /// assert_eq!(index.capacity(), 8);
+    /// index.shrink_to(10);
+    /// assert_eq!(index.capacity(), 10);
+    ///
+    /// index.shrink_to(20);
+    /// assert_eq!(index.capacity(), 10);
     /// ```
-    pub fn insert(&mut self, key: K, value: V) -> Bucket<K, V> {
-        let hash = make_hash(&self.params.hasher_builder, &key) as usize;
-
-        if self.load() >= self.params.max_load {
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        let fit = (self.len() as f64 / self.params.max_load) as usize + 1;
+        let new_cap = table_capacity(min_capacity.max(fit), self.params.layout);
+        self.spare = Vec::new();
+        if new_cap < self.capacity {
+            self.resize(new_cap);
+        }
+    }
+
+    /// Grows the capacity so that `additional` more entries fit without
+    /// exceeding the maximum load factor. Does nothing if they already fit.
+    ///
+    /// Like `shrink_to`, the table is resized all at once.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index: Index<u64, u64> = Index::new();
+    /// index.reserve(100);
+    ///
+    /// let capacity = index.capacity();
+    /// for i in 0..100 {
+    ///     index.insert(i, i);
+    /// }
+    /// assert_eq!(index.capacity(), capacity);
+    /// ```
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = ((self.len() + additional) as f64 / self.params.max_load) as usize + 1;
+        if needed > self.capacity {
+            self.resize(needed);
+        }
+    }
+
+    /// The capacity `grow` resizes to.
+    fn next_capacity(&self) -> usize {
+        let new_cap = if self.tombstones > self.len {
+            self.capacity
+        } else {
+            // small tables would stay the same size with a small growth policy
+            let new_cap = (self.capacity as f64 * self.params.growth_policy) as usize;
+            new_cap.max(self.capacity + 1)
+        };
+        table_capacity(new_cap, self.params.layout)
+    }
+
+    /// Initializes `MIGRATE_BUCKETS` of the buckets the next incremental
+    /// resize needs, once the table is close enough to its maximum load
+    /// that they wouldn't be ready in time otherwise.
+    ///
+    /// Initializing (and page faulting) the buckets of a large table takes
+    /// long enough to be noticed, `grow` only has to allocate the
+    /// distances or control bytes.
+    fn prepare(&mut self) {
+        if self.old.is_some() || self.capacity < INCREMENTAL_RESIZE_CAPACITY {
+            return;
+        }
+        let new_cap = self.next_capacity();
+        let missing = new_cap.saturating_sub(self.spare.len());
+        let headroom =
+            self.params.max_load * self.capacity as f64 - (self.len + self.tombstones) as f64;
+        if missing > 0 && headroom * (MIGRATE_BUCKETS as f64) <= missing as f64 {
+            self.spare.reserve_exact(new_cap - self.spare.len());
+            for _ in 0..missing.min(MIGRATE_BUCKETS) {
+                self.spare.push(Bucket::Empty);
+            }
+        }
+    }
+
+    /// Returns `true` if the table reached its maximum load.
+    fn overloaded(&self) -> bool {
+        // tombstones lengthen probe sequences just like entries do
+        (self.len + self.tombstones) as f64 / self.capacity as f64 >= self.params.max_load
+    }
+
+    /// Moves entries of the old table to the table, visiting at most
+    /// `MIGRATE_BUCKETS` buckets of the old table, and drops the old table
+    /// once it is empty.
+    ///
+    /// Stops early if the table reaches its maximum load, the next insertion
+    /// then moves the rest over before it starts another resize.
+    ///
+    /// Every method taking `&mut self` calls this, lookups with [`get`]
+    /// can't. An `Index` that is mostly read from should call it now and
+    /// then, until [`is_resizing`] returns `false`, so that lookups stop
+    /// checking both tables.
+    ///
+    /// [`get`]: struct.Index.html#method.get
+    /// [`is_resizing`]: struct.Index.html#method.is_resizing
+    pub fn migrate(&mut self) {
+        self.migrate_buckets(MIGRATE_BUCKETS, true);
+    }
+
+    /// Moves entries of the old table to the table, visiting at most
+    /// `buckets` buckets of the old table. Stops at the maximum load only
+    /// if `check_load` is set, otherwise once the table has no free bucket.
+    fn migrate_buckets(&mut self, buckets: usize, check_load: bool) {
+        let mut old = match self.old.take() {
+            Some(old) => old,
+            None => return,
+        };
+
+        for _ in 0..buckets {
+            if old.len == 0 || (check_load && self.overloaded()) {
+                break;
+            }
+            let hash = match old.get_at(self.migrated) {
+                Some(pair) => make_hash(&self.params.hasher_builder, &pair.0) as usize,
+                None => {
+                    self.migrated += 1;
+                    continue;
+                }
+            };
+            // The key can't be in the table, any free bucket will do. With
+            // `Layout::RobinHood` the removal may shift another entry into
+            // the bucket, so `migrated` only moves on once it is free.
+            match self.find(hash, |_| false) {
+                (_, Some(i)) => {
+                    let (key, value) = old.remove_at(self.migrated).expect("checked by get_at");
+                    self.fill(i, hash, key, value);
+                }
+                _ => break,
+            }
+        }
+
+        if old.len > 0 {
+            self.old = Some(old);
+        } else {
+            self.migrated = 0;
+        }
+    }
+
+    /// Removes the entry matching `f` from the old table, if any.
+    fn remove_old<F>(&mut self, hash: usize, f: F) -> Option<(K, V)>
+    where
+        F: Fn(&(K, V)) -> bool,
+    {
+        let old = self.old.as_mut()?;
+        match old.find(hash, f) {
+            (Some(_), Some(i)) => old.remove_at(i),
+            _ => None,
+        }
+    }
+
+    /// Looks for the entry matching `f` in the table, then in the old one.
+    fn lookup<F>(&self, hash: usize, f: F) -> Option<&(K, V)>
+    where
+        F: Fn(&(K, V)) -> bool,
+    {
+        match self.find(hash, &f).0 {
+            Some(pair) => Some(pair),
+            None => self.old.as_ref()?.find(hash, f).0,
+        }
+    }
+
+    /// Searches for an entry according to specified hash and discriminating closure.
+    ///
+    /// Tombstones don't end the search, but the first one encountered is
+    /// returned as the free bucket if no match is found. With
+    /// `Layout::RobinHood` the search ends early at the first entry closer
+    /// to its home than the key would be, which is also where the key has
+    /// to be inserted.
+    ///
+    /// See alias definition of `Find<'a, K, V>` at the top of this file for more details.
+    fn find<F>(&self, hash: usize, f: F) -> Find<K, V>
+    where
+        F: Fn(&(K, V)) -> bool,
+    {
+        match self.params.layout {
+            Layout::Probing => {}
+            Layout::RobinHood => return self.find_robin_hood(hash, f),
+            Layout::Group => return self.find_group(hash, f),
+        }
+        let mut tombstone = None;
+
+        for i in 0..self.capacity {
+            let probe = (self.params.probe)(hash, i) % self.capacity;
+
+            match &self.table[probe] {
+                Bucket::Full(pair) if f(pair) => return (Some(pair), Some(probe)), // found matching bucket
+                Bucket::Empty => return (None, tombstone.or(Some(probe))), // found empty bucket
+                Bucket::Tombstone if tombstone.is_none() => tombstone = Some(probe),
+                _ => continue,
+            }
+        }
+
+        (None, tombstone) // found nothing
+    }
+
+    /// `find` for `Layout::RobinHood`, probes linearly.
+    fn find_robin_hood<F>(&self, hash: usize, f: F) -> Find<K, V>
+    where
+        F: Fn(&(K, V)) -> bool,
+    {
+        let home = hash % self.capacity;
+
+        for distance in 0..self.capacity {
+            let probe = (home + distance) % self.capacity;
+
+            match &self.table[probe] {
+                Bucket::Full(_) if (self.distances[probe] as usize) < distance => {
+                    return (None, Some(probe)); // the key would have displaced this entry
+                }
+                Bucket::Full(pair) if f(pair) => return (Some(pair), Some(probe)),
+                Bucket::Full(_) => continue,
+                _ => return (None, Some(probe)),
+            }
+        }
+
+        (None, None) // found nothing
+    }
+
+    /// `find` for `Layout::Group`, probes group by group.
+    fn find_group<F>(&self, hash: usize, f: F) -> Find<K, V>
+    where
+        F: Fn(&(K, V)) -> bool,
+    {
+        let tag = group::tag(hash);
+        let groups = self.capacity / GROUP_WIDTH;
+        let first = hash % self.capacity / GROUP_WIDTH;
+        let mut free = None;
+
+        for i in 0..groups {
+            let start = (first + i) % groups * GROUP_WIDTH;
+            let group = Group::load(&self.control[start..]);
+
+            for bit in group.match_tag(tag) {
+                match &self.table[start + bit] {
+                    Bucket::Full(pair) if f(pair) => return (Some(pair), Some(start + bit)),
+                    _ => continue, // other key with the same tag
+                }
+            }
+            if free.is_none() {
+                free = group.match_free().lowest().map(|bit| start + bit);
+            }
+            if group.match_empty().any() {
+                return (None, free); // the key would be in this group
+            }
+        }
+
+        (None, free) // found nothing
+    }
+
+    /// Inserts key-value pair in the `Index`.
+    ///
+    /// If it encounters an occupied bucket with the same key, it will replace the
+    /// entry according to the new value and return the old bucket.
+    ///
+    /// The function also verifies before anything else that the load factor is lesser
+    /// than the maximum accepted load, if not it will grow the `Index` before proceeding to the insertion.
+    /// While the `Index` is resizing, the entry is moved to the new table.
+    ///
+    /// If the lookup returns no valid result, the insertion is considered impossible and
+    /// the function will grow the `Index` and retry to insert the pair.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index = Index::with_capacity(2);
+    ///
+    /// index.insert("key", "value");
+    ///
+    /// assert_eq!(*index.get("key").unwrap(), "value");
+    ///
+    /// index.insert("key", "new value");
+    ///
+    /// assert_eq!(*index.get("key").unwrap(), "new value");
+    ///
+    /// assert_eq!(index.len(), 1);
+    /// assert_eq!(index.capacity(), 2);
+    ///
+    /// index.insert("salutation", "Hello, world!");
+    /// index.insert("ferris", "https://www.rustacean.net/more-crabby-things/dancing-ferris.gif");
+    /// index.insert("did you know ?", "Rust is kinda cool guys !");
+    ///
+    /// assert_eq!(index.len(), 4);
+    /// assert_eq!(index.capacity(), 8);
+    /// ```
+    pub fn insert(&mut self, key: K, value: V) -> Bucket<K, V> {
+        let hash = make_hash(&self.params.hasher_builder, &key) as usize;
+
+        self.prepare();
+        if self.overloaded() {
             self.grow();
         }
+        self.migrate();
+        let replaced = match self.remove_old(hash, |p| key.eq(&p.0)) {
+            Some(pair) => Bucket::Full(pair),
+            None => Bucket::Empty,
+        };
 
         match self.find(hash, |p| key.eq(&p.0)) {
             (Some(_), Some(i)) => {
-                std::mem::replace(&mut self.table[i], Bucket::Some(RefCell::new((key, value))))
+                core::mem::replace(&mut self.table[i], Bucket::Full((key, value)))
             }
             (None, Some(i)) => {
-                self.table[i] = Bucket::Some(RefCell::new((key, value)));
-                self.len += 1;
-                Bucket::None
+                self.fill(i, hash, key, value);
+                replaced
             }
             _ => {
                 self.grow();
-                self.insert(key, value)
+                self.insert(key, value);
+                replaced
             }
         }
     }
 
-    // pub fn remove_entry<Q>(&mut self, key: &Q) -> Bucket<K, V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized
-    /*
-        Problem: removing entry can corrupt lookup integrity
-                 (find may encounter empty bucket before searched value)
+    /// Gets the entry of `key` for in-place manipulation, with a single
+    /// lookup.
+    ///
+    /// Like `insert`, the `Index` grows first if the load factor is too
+    /// high, so that a vacant entry can be filled without resizing.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index: Index<char, usize> = Index::new();
+    ///
+    /// for c in "hello world".chars() {
+    ///     *index.entry(c).or_insert(0) += 1;
+    /// }
+    ///
+    /// assert_eq!(*index.get(&'l').unwrap(), 3);
+    /// assert_eq!(*index.get(&'o').unwrap(), 2);
+    /// ```
+    pub fn entry(&mut self, key: K) -> Entry<K, V, S> {
+        let hash = make_hash(&self.params.hasher_builder, &key) as usize;
 
-        Solutions:
-            - use find_match and find_empty
-                Problem: find_match will always have to be used for remove and get operations
-                         to ensure lookup integrity and will have O(n) complexity if key isnt in table (because wont return first empty bucket found)
-            - use flag array for present, empty, removed values ?
+        self.prepare();
+        if self.overloaded() {
+            self.grow();
+        }
+        self.migrate();
+        // entries only point into the table
+        let moved = self.remove_old(hash, |p| key.eq(&p.0));
 
-        Same problem arises when modifying keys through an IterMut
-    */
+        loop {
+            match self.find(hash, |p| key.eq(&p.0)) {
+                (Some(_), Some(i)) => return Entry::occupied(self, i),
+                (None, Some(i)) => match moved {
+                    Some((key, value)) => {
+                        self.fill(i, hash, key, value);
+                        return Entry::occupied(self, i);
+                    }
+                    None => return Entry::vacant(self, key, hash, i),
+                },
+                // the probe sequence missed every free bucket
+                _ => self.grow(),
+            }
+        }
+    }
+
+    /// Removes the entry associated with the specified key and returns the
+    /// key-value pair if the lookup found a match, else it returns `None`.
+    ///
+    /// The bucket is replaced by a tombstone so lookups for keys further
+    /// down the same probe sequence still find their entry. The table
+    /// shrinks if the load drops below the minimum load factor.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index = Index::with_capacity(10);
+    ///
+    /// index.insert("salutation", "Hello, world!");
+    ///
+    /// assert_eq!(index.remove_entry("salutation"), Some(("salutation", "Hello, world!")));
+    /// assert_eq!(index.remove_entry("salutation"), None);
+    /// assert_eq!(index.len(), 0);
+    /// ```
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = make_hash(self.hasher(), &key) as usize;
+        self.migrate();
+        let removed = match self.find(hash, |p| key.eq(p.0.borrow())) {
+            (Some(_), Some(i)) => self.remove_at(i),
+            _ => self.remove_old(hash, |p| key.eq(p.0.borrow())),
+        };
+        if removed.is_some() {
+            self.shrink_if_underloaded();
+        }
+        removed
+    }
+
+    /// Removes the entry associated with the specified key and returns the
+    /// value if the lookup found a match, else it returns `None`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index = Index::with_capacity(10);
+    ///
+    /// index.insert("salutation", "Hello, world!");
+    ///
+    /// assert_eq!(index.remove("salutation"), Some("Hello, world!"));
+    /// assert!(index.get("salutation").is_none());
+    /// ```
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_k, v)| v)
+    }
+
+    /// Keeps only the entries for which `f` returns `true`, visiting every
+    /// entry once. The table shrinks afterwards if the load dropped below
+    /// the minimum load factor.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index = Index::new();
+    /// for i in 0..10 {
+    ///     index.insert(i, i * 10);
+    /// }
+    ///
+    /// index.retain(|k, v| {
+    ///     *v += 1;
+    ///     k % 2 == 0
+    /// });
+    ///
+    /// assert_eq!(index.len(), 5);
+    /// assert_eq!(index[&4], 41);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.retain_table(&mut f);
+        if let Some(old) = self.old.as_mut() {
+            old.retain_table(&mut f);
+        }
+        self.shrink_if_underloaded();
+    }
+
+    /// Removes the entries of the table, without the old one, for which
+    /// `f` returns `false`.
+    fn retain_table<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        // With `Layout::RobinHood` a removal moves the following entries of
+        // the cluster back. Starting after an empty bucket, which stays
+        // empty, they are never moved past the start to be visited twice.
+        let start = self
+            .table
+            .iter()
+            .position(|bucket| matches!(bucket, Bucket::Empty))
+            .unwrap_or(0);
+        let mut n = 0;
+        while n < self.capacity {
+            let i = (start + n) % self.capacity;
+            let keep = match &mut self.table[i] {
+                Bucket::Full((key, value)) => f(key, value),
+                _ => true,
+            };
+            if keep {
+                n += 1;
+            } else {
+                // bucket `i` may hold another entry now, look at it again
+                self.remove_at(i);
+            }
+        }
+    }
 
     /// Returns a reference to the value associated with the specified key
     /// if the lookup found a match, else it returns `None`.
     ///
+    /// Doesn't move an incremental resize along (see `migrate`).
+    ///
     /// # Example
     ///  
     /// ```
@@ -638,15 +1634,13 @@ where
     ///
     /// assert_eq!(*index.get("salutation").unwrap(), "Hello, world!");
     /// ```
-    pub fn get<Q>(&self, key: &Q) -> Option<Ref<V>>
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
         let hash = make_hash(self.hasher(), &key) as usize;
-        self.find(hash, |p| key.borrow().eq(p.0.borrow()))
-            .0
-            .map(|pair| Ref::map(pair.borrow(), |p| &p.1))
+        self.lookup(hash, |p| key.eq(p.0.borrow())).map(|p| &p.1)
     }
 
     /// Returns a mutable reference to the value associated with the specified key
@@ -667,15 +1661,21 @@ where
     ///
     /// assert_eq!(*index.get("salutation").unwrap(), "Hello, rust!");
     /// ```
-    pub fn get_mut<Q>(&self, key: &Q) -> Option<RefMut<V>>
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
         let hash = make_hash(self.hasher(), &key) as usize;
-        self.find(hash, |p| key.eq(p.0.borrow()))
-            .0
-            .map(|pair| RefMut::map(pair.borrow_mut(), |p| &mut p.1))
+        self.migrate();
+        if let (Some(_), Some(i)) = self.find(hash, |p| key.eq(p.0.borrow())) {
+            return Some(&mut self.pair_mut(i).1);
+        }
+        let old = self.old.as_mut()?;
+        match old.find(hash, |p| key.eq(p.0.borrow())) {
+            (Some(_), Some(i)) => Some(&mut old.pair_mut(i).1),
+            _ => None,
+        }
     }
 
     /// Returns a reference to the key-value pair associated with the specified key
@@ -694,15 +1694,185 @@ where
     ///
     /// assert_eq!(*index.get_pair("did you know ?").unwrap(), ("did you know ?", "Rust is kinda cool !"));
     /// ```
-    pub fn get_pair<Q>(&self, key: &Q) -> Option<Ref<(K, V)>>
+    pub fn get_pair<Q>(&self, key: &Q) -> Option<&(K, V)>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
         let hash = make_hash(self.hasher(), &key) as usize;
-        self.find(hash, |p| key.eq(p.0.borrow()))
-            .0
-            .map(|pair| pair.borrow())
+        self.lookup(hash, |p| key.eq(p.0.borrow()))
+    }
+
+    /// Returns `true` if the `Index` holds an entry for the specified key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index = Index::with_capacity(10);
+    ///
+    /// index.insert("salutation", "Hello, world!");
+    ///
+    /// assert!(index.contains_key("salutation"));
+    /// assert!(!index.contains_key("ferris"));
+    /// ```
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_pair(key).is_some()
+    }
+
+    /// Calls `f` on every entry whose home bucket (`hash % capacity`) is
+    /// the one `cursor` points to, and returns the cursor for the next
+    /// bucket, or `0` once all buckets have been visited. Start with `0`.
+    ///
+    /// Entries present during the whole iteration are visited at least
+    /// once, even if the `Index` grows in between calls. Like Redis, we
+    /// increment the bits of the bucket number in reverse: growing by a
+    /// factor of two splits a bucket in two buckets that are visited right
+    /// after each other. The capacity is split into its odd part, which
+    /// never changes when doubling, and the power of two the reverse
+    /// increment works on. Shrinking halves the capacity (see
+    /// [`shrink_if_underloaded`]), which merges buckets visited right after
+    /// each other. Growth policies other than `2.0` and [`shrink_to`] may
+    /// skip entries on resize. An entry may be visited more than once.
+    ///
+    /// The probe sequence is assumed to depend only on the home bucket,
+    /// which holds for linear and quadratic probing.
+    ///
+    /// While the `Index` is resizing, the entries still in the old table
+    /// are visited with the bucket they will have in the new one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index = Index::with_capacity(10);
+    ///
+    /// index.insert("salutation", "Hello, world!");
+    /// index.insert("ferris", "https://www.rustacean.net/more-crabby-things/dancing-ferris.gif");
+    ///
+    /// let mut keys = Vec::new();
+    /// let mut cursor = 0;
+    /// loop {
+    ///     cursor = index.scan(cursor, |key, _value| keys.push(*key));
+    ///     if cursor == 0 {
+    ///         break;
+    ///     }
+    /// }
+    /// assert_eq!(keys.len(), 2);
+    /// ```
+    ///
+    /// [`shrink_if_underloaded`]: struct.Index.html#method.shrink_if_underloaded
+    /// [`shrink_to`]: struct.Index.html#method.shrink_to
+    pub fn scan<F>(&self, cursor: u64, mut f: F) -> u64
+    where
+        F: FnMut(&K, &V),
+    {
+        let bits = self.capacity.trailing_zeros();
+        let odd = (self.capacity >> bits) as u64;
+        let mask = (1u64 << bits) - 1;
+        let low = cursor % odd;
+        let high = (cursor / odd) & mask;
+        let home = (low + odd * high) as usize;
+
+        self.scan_home(home, self.capacity, home, &mut f);
+        if let Some(old) = &self.old {
+            if old.capacity < self.capacity {
+                // With a growth policy of `2.0` the capacity of the old
+                // table divides the new one, entries of `home` share an
+                // old home
+                old.scan_home(home % old.capacity, self.capacity, home, &mut f);
+            } else {
+                // Shrinking halves the capacity, the entries of `home`
+                // come from every old home that is `home` modulo the
+                // new capacity
+                for old_home in (home..old.capacity).step_by(self.capacity) {
+                    old.scan_home(old_home, self.capacity, home, &mut f);
+                }
+            }
+        }
+
+        // Increment the reversed power of two part, carry into the odd part
+        let high = (high | !mask).reverse_bits().wrapping_add(1).reverse_bits();
+        if high & mask != 0 {
+            low + odd * high
+        } else if low + 1 < odd {
+            low + 1
+        } else {
+            0
+        }
+    }
+
+    /// Computes the probe lengths and occupancy of the table, to compare
+    /// parameters on real key sets. Looks at every bucket and hashes every
+    /// key, it takes a while on large tables.
+    ///
+    /// See [`Stats`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index = Index::with_capacity(10);
+    ///
+    /// index.insert("salutation", "Hello, world!");
+    /// index.insert("ferris", "https://www.rustacean.net/more-crabby-things/dancing-ferris.gif");
+    ///
+    /// let stats = index.stats();
+    /// assert_eq!(stats.len, 2);
+    /// assert_eq!(stats.probe_lengths.iter().sum::<usize>(), 2);
+    /// assert!(stats.average_probe_length() >= 1.0);
+    /// ```
+    ///
+    /// [`Stats`]: struct.Stats.html
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.table_stats();
+        if let Some(old) = &self.old {
+            stats.add_old(old.table_stats());
+        }
+        stats
+    }
+
+    /// Calls `f` on the entries in the probe sequence of bucket `home` whose
+    /// hash modulo `capacity` is `target`.
+    fn scan_home<F>(&self, home: usize, capacity: usize, target: usize, f: &mut F)
+    where
+        F: FnMut(&K, &V),
+    {
+        // Entries with this home bucket are somewhere before the first
+        // empty bucket of its probe sequence (for `Layout::Group`, before
+        // the end of the first group with an empty bucket).
+        let mut empty_group = false;
+        for i in 0..self.capacity {
+            let probe = match self.params.layout {
+                Layout::Probing => (self.params.probe)(home, i) % self.capacity,
+                Layout::RobinHood => (home + i) % self.capacity,
+                Layout::Group => {
+                    if i % GROUP_WIDTH == 0 && empty_group {
+                        break;
+                    }
+                    let groups = self.capacity / GROUP_WIDTH;
+                    (home / GROUP_WIDTH + i / GROUP_WIDTH) % groups * GROUP_WIDTH + i % GROUP_WIDTH
+                }
+            };
+            match &self.table[probe] {
+                Bucket::Full(pair) => {
+                    let hash = make_hash(&self.params.hasher_builder, &pair.0) as usize;
+                    if hash % capacity == target {
+                        f(&pair.0, &pair.1);
+                    }
+                }
+                Bucket::Empty if self.params.layout == Layout::Group => empty_group = true,
+                Bucket::Empty => break,
+                Bucket::Tombstone => continue,
+            }
+        }
     }
 }
 
@@ -723,8 +1893,8 @@ where
                 "{}\n\t\t{} : {:?},",
                 s,
                 i,
-                if let Some(pair) = entry {
-                    Some(pair.borrow())
+                if let Bucket::Full(pair) = entry {
+                    Some(pair)
                 } else {
                     None
                 }
@@ -744,3 +1914,681 @@ where
         Self::new()
     }
 }
+
+impl<K, V, S> PartialEq for Index<K, V, S>
+where
+    K: Hash + Eq,
+    V: PartialEq,
+    S: BuildHasher + Clone,
+{
+    /// Two `Index`es are equal if they hold the same entries, whatever
+    /// their capacity, layout or order of insertion.
+    fn eq(&self, other: &Index<K, V, S>) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key).map_or(false, |v| *value == *v))
+    }
+}
+
+impl<K, V, S> Eq for Index<K, V, S>
+where
+    K: Hash + Eq,
+    V: Eq,
+    S: BuildHasher + Clone,
+{
+}
+
+impl<K, Q, V, S> core::ops::Index<&Q> for Index<K, V, S>
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    S: BuildHasher + Clone,
+{
+    type Output = V;
+
+    /// Returns a reference to the value of `key`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Index` holds no entry for `key`.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for Index<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone + Default,
+{
+    /// Collects the pairs into an `Index` with default parameters (and
+    /// hasher builder), later pairs replace earlier ones with the same key.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut index = Index::with_capacity_and_parameters(
+            DEFAULT_INITIAL_CAPACITY,
+            Parameters::with_hasher(S::default()),
+        );
+        index.extend(iter);
+        index
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for Index<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        // keys may repeat or already be there, only count on half of them
+        // being new unless the `Index` is empty
+        let (lower, _) = iter.size_hint();
+        self.reserve(if self.is_empty() {
+            lower
+        } else {
+            lower.div_ceil(2)
+        });
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K, V, S> Extend<(&'a K, &'a V)> for Index<K, V, S>
+where
+    K: Hash + Eq + Copy,
+    V: Copy,
+    S: BuildHasher + Clone,
+{
+    fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(key, value)| (*key, *value)));
+    }
+}
+
+impl<K, V, S> IntoIterator for Index<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    /// Moves the entries out of the `Index`, see [`IntoIter`].
+    ///
+    /// [`IntoIter`]: iter/struct.IntoIter.html
+    fn into_iter(mut self) -> IntoIter<K, V> {
+        let old = self.old.take().map_or(Vec::new(), |old| old.table);
+        IntoIter::new(self.table, old)
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a Index<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut Index<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn remove_keeps_probe_sequences_intact() {
+        // Linear probing makes every key collide with its neighbours
+        let params = Parameters {
+            max_load: 0.7,
+            min_load: 0.0,
+            growth_policy: 2.0,
+            hasher_builder: IndexHasherBuilder,
+            probe: |hash, i| hash + i,
+            layout: Layout::Probing,
+        };
+        let mut index = Index::with_capacity_and_parameters(16, params);
+        let mut oracle = HashMap::new();
+
+        for round in 0..8u64 {
+            for k in 0..200u64 {
+                index.insert(k, k + round);
+                oracle.insert(k, k + round);
+            }
+            for k in (round..200).step_by(3) {
+                assert_eq!(index.remove(&k), oracle.remove(&k));
+            }
+            assert_eq!(index.len(), oracle.len());
+            for k in 0..200u64 {
+                assert_eq!(index.get(&k).copied(), oracle.get(&k).copied());
+            }
+        }
+
+        assert_eq!(index.drain().count(), oracle.len());
+        assert_eq!(index.tombstones, 0);
+    }
+
+    #[test]
+    fn scan_survives_growth() {
+        let mut index = Index::with_capacity(12);
+        for k in 0..100u64 {
+            index.insert(k, ());
+        }
+
+        // Grow the table twice while scanning, nothing may be skipped
+        let mut seen = HashMap::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            cursor = index.scan(cursor, |k, _| *seen.entry(*k).or_insert(0) += 1);
+            calls += 1;
+            if calls == 20 || calls == 200 {
+                for k in 1000..1000 + index.capacity() as u64 {
+                    index.insert(k, ());
+                }
+            }
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert!((0..100u64).all(|k| seen.contains_key(&k)));
+
+        // Without resizes every entry is visited exactly once
+        let mut visited = 0;
+        let mut cursor = 0;
+        loop {
+            cursor = index.scan(cursor, |_, _| visited += 1);
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(visited, index.len());
+    }
+
+    #[test]
+    fn scan_survives_shrinking() {
+        let mut index = Index::with_capacity_and_parameters(12, params_with_min_load());
+        for k in 0..100u64 {
+            index.insert(k, ());
+        }
+        for k in 1000..20_000u64 {
+            index.insert(k, ());
+        }
+
+        // Shrink the table (incrementally) while scanning, nothing may be
+        // skipped
+        let mut seen = HashMap::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        let mut shrunk = false;
+        loop {
+            cursor = index.scan(cursor, |k, _| *seen.entry(*k).or_insert(0) += 1);
+            calls += 1;
+            if calls == 100 || calls == 3000 {
+                let capacity = index.capacity();
+                for k in 1000..20_000u64 {
+                    index.remove(&k);
+                }
+                shrunk |= index.capacity() < capacity;
+            }
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert!(shrunk);
+        assert!((0..100u64).all(|k| seen.contains_key(&k)));
+
+        // A scan in the middle of an incremental shrink sees every entry
+        let mut index = Index::with_capacity_and_parameters(12, params_with_min_load());
+        for k in 0..20_000u64 {
+            index.insert(k, ());
+        }
+        let mut k = 0;
+        while index.old.is_none() {
+            index.remove(&k);
+            k += 1;
+        }
+        assert!(index.old.as_ref().unwrap().capacity > index.capacity());
+        let mut visited = HashMap::new();
+        let mut cursor = 0;
+        loop {
+            cursor = index.scan(cursor, |k, _| *visited.entry(*k).or_insert(0) += 1);
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(visited.len(), index.len());
+    }
+
+    /// Default parameters that shrink the table below a load of `0.1`.
+    fn params_with_min_load() -> Parameters<IndexHasherBuilder> {
+        Parameters {
+            min_load: 0.1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn incremental_resize_matches_hashmap() {
+        let mut index = Index::with_capacity(INCREMENTAL_RESIZE_CAPACITY);
+        let mut oracle = HashMap::new();
+        let mut resizes = 0;
+
+        for k in 0..20_000u64 {
+            let old_len = index.old.as_ref().map(|old| old.len);
+            index.insert(k, k);
+            oracle.insert(k, k);
+            match (old_len, index.old.as_ref().map(|old| old.len)) {
+                (None, Some(_)) => resizes += 1,
+                // every insertion only moves the entries of a few buckets
+                (Some(before), Some(after)) => assert!(before - after <= MIGRATE_BUCKETS),
+                _ => {}
+            }
+
+            if index.old.is_some() && k % 7 == 0 {
+                // hits both tables while entries are moved
+                assert_eq!(index.remove(&(k / 2)), oracle.remove(&(k / 2)));
+                *index.entry(k / 3).or_insert(0) += 1;
+                *oracle.entry(k / 3).or_insert(0) += 1;
+                if let Some(v) = index.get_mut(&(k / 5)) {
+                    *v += 1;
+                }
+                if let Some(v) = oracle.get_mut(&(k / 5)) {
+                    *v += 1;
+                }
+                assert_eq!(index.len(), oracle.len());
+                assert_eq!(index.iter().count(), oracle.len());
+            }
+        }
+        assert!(resizes >= 3);
+
+        // Catch the index in the middle of a resize
+        while index.old.is_none() {
+            let k = oracle.len() as u64 * 3 + 1_000_000;
+            index.insert(k, k);
+            oracle.insert(k, k);
+        }
+        for (k, v) in &oracle {
+            assert_eq!(index.get(k), Some(v));
+        }
+        let stats = index.stats();
+        assert_eq!(stats.len, oracle.len());
+        assert_eq!(stats.probe_lengths.iter().sum::<usize>(), oracle.len());
+        let mut visited = 0;
+        let mut cursor = 0;
+        loop {
+            cursor = index.scan(cursor, |_, _| visited += 1);
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(visited, oracle.len());
+        let full = (0..index.buckets()).filter(|i| index.get_at(*i).is_some());
+        assert_eq!(full.count(), oracle.len());
+
+        // Lookups don't move entries, `migrate` finishes the resize
+        assert!(index.is_resizing());
+        while index.is_resizing() {
+            index.migrate();
+        }
+        for (k, v) in &oracle {
+            assert_eq!(index.get(k), Some(v));
+        }
+        assert_eq!(index.drain().count(), oracle.len());
+        assert!(index.old.is_none() && index.is_empty());
+    }
+
+    #[test]
+    fn shrink_matches_hashmap() {
+        let params = Parameters {
+            min_load: 0.1,
+            layout: Layout::Group,
+            ..Default::default()
+        };
+        let mut index = Index::with_capacity_and_parameters(4, params);
+        let mut oracle = HashMap::new();
+
+        for k in 0..20_000u64 {
+            index.insert(k, k);
+            oracle.insert(k, k);
+        }
+        let grown = index.capacity();
+        for k in 0..19_900u64 {
+            assert_eq!(index.remove(&k), oracle.remove(&k));
+            assert!(index.load() >= 0.1 || index.old.is_some());
+        }
+        // every removal moved a few more entries to the smaller table
+        while index.old.is_some() {
+            index.get_mut(&0);
+        }
+        assert!(index.capacity() < grown / 8);
+        for k in 0..20_000u64 {
+            assert_eq!(index.get(&k), oracle.get(&k));
+        }
+
+        let capacity = index.capacity();
+        index.shrink_to(capacity * 2);
+        assert_eq!(index.capacity(), capacity);
+        index.shrink_to(capacity - 100);
+        assert_eq!(
+            index.capacity(),
+            table_capacity(capacity - 100, Layout::Group)
+        );
+        index.shrink_to_fit();
+        assert!(index.capacity() < 150 && index.load() < index.max_load());
+        assert_eq!(index.capacity() % GROUP_WIDTH, 0);
+        assert_eq!(index.len(), oracle.len());
+        for (k, v) in &oracle {
+            assert_eq!(index.get(k), Some(v));
+        }
+
+        // Without a minimum load the table keeps its size
+        let mut index = Index::with_capacity(4);
+        for k in 0..1000u64 {
+            index.insert(k, ());
+        }
+        let grown = index.capacity();
+        for k in 0..1000u64 {
+            index.remove(&k);
+        }
+        assert_eq!(index.capacity(), grown);
+    }
+
+    #[test]
+    fn entry_matches_hashmap() {
+        let mut index = Index::with_capacity(4);
+        let mut oracle = HashMap::new();
+
+        for k in 0..500u64 {
+            let key = k % 97;
+            *index.entry(key).or_insert(0) += k;
+            *oracle.entry(key).or_insert(0) += k;
+            index.entry(key * 3).and_modify(|v| *v *= 2).or_default();
+            oracle.entry(key * 3).and_modify(|v| *v *= 2).or_default();
+            if k % 5 == 0 {
+                if let Entry::Occupied(entry) = index.entry(key + 1) {
+                    assert_eq!(Some(entry.remove()), oracle.remove(&(key + 1)));
+                }
+            }
+        }
+
+        assert_eq!(index.len(), oracle.len());
+        for (k, v) in &oracle {
+            assert_eq!(*index.get(k).unwrap(), *v);
+        }
+        assert_eq!(*index.entry(1000).insert(7).get(), 7);
+        assert_eq!(index.len(), oracle.len() + 1);
+    }
+
+    #[test]
+    fn robin_hood_matches_hashmap() {
+        let params = Parameters {
+            max_load: 0.95,
+            layout: Layout::RobinHood,
+            ..Default::default()
+        };
+        let mut index = Index::with_capacity_and_parameters(8, params);
+        let mut oracle = HashMap::new();
+
+        for round in 0..6u64 {
+            for k in 0..300u64 {
+                index.insert(k * 7, k + round);
+                oracle.insert(k * 7, k + round);
+                *index.entry(k % 13).or_insert(0) += 1;
+                *oracle.entry(k % 13).or_insert(0) += 1;
+            }
+            for k in (round..300).step_by(4) {
+                assert_eq!(index.remove(&(k * 7)), oracle.remove(&(k * 7)));
+            }
+            assert_eq!(index.len(), oracle.len());
+            assert_eq!(index.tombstones, 0);
+            for k in 0..2100u64 {
+                assert_eq!(index.get(&k), oracle.get(&k));
+            }
+        }
+
+        // Distances and the cluster invariant survive the shifting
+        for i in 0..index.capacity() {
+            if let Some((key, _)) = index.get_at(i) {
+                let home = make_hash(index.hasher(), key) as usize % index.capacity();
+                let distance = (i + index.capacity() - home) % index.capacity();
+                assert_eq!(index.distances[i] as usize, distance);
+            }
+        }
+    }
+
+    #[test]
+    fn group_matches_hashmap() {
+        let params = Parameters {
+            max_load: 0.875,
+            layout: Layout::Group,
+            ..Default::default()
+        };
+        let mut index = Index::with_capacity_and_parameters(3, params);
+        assert_eq!(index.capacity(), GROUP_WIDTH);
+        let mut oracle = HashMap::new();
+
+        for round in 0..6u64 {
+            for k in 0..400u64 {
+                index.insert(k * 3, k + round);
+                oracle.insert(k * 3, k + round);
+                *index.entry(k % 11).or_insert(0) += 1;
+                *oracle.entry(k % 11).or_insert(0) += 1;
+            }
+            for k in (round..400).step_by(3) {
+                assert_eq!(index.remove(&(k * 3)), oracle.remove(&(k * 3)));
+            }
+            assert_eq!(index.len(), oracle.len());
+            for k in 0..1200u64 {
+                assert_eq!(index.get(&k), oracle.get(&k));
+            }
+        }
+
+        let mut visited = 0;
+        let mut cursor = 0;
+        loop {
+            cursor = index.scan(cursor, |_, _| visited += 1);
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(visited, index.len());
+        assert_eq!(index.drain().count(), oracle.len());
+        assert!(index.control.iter().all(|&c| c == EMPTY));
+    }
+
+    /// An `Index` with `layout` in the middle of an incremental resize, and
+    /// a `HashMap` with the same entries.
+    fn resizing(layout: Layout) -> (Index<u64, u64>, HashMap<u64, u64>) {
+        let params = Parameters {
+            layout,
+            ..Default::default()
+        };
+        let mut index = Index::with_capacity_and_parameters(INCREMENTAL_RESIZE_CAPACITY, params);
+        let mut oracle = HashMap::new();
+        let mut k = 0;
+        while index.old.is_none() || oracle.len() < 2 * INCREMENTAL_RESIZE_CAPACITY {
+            index.insert(k * 7, k);
+            oracle.insert(k * 7, k);
+            k += 1;
+        }
+        assert!(index.old.is_some());
+        (index, oracle)
+    }
+
+    #[test]
+    fn resize_while_resizing_stays_incremental() {
+        for &layout in &[Layout::Probing, Layout::RobinHood, Layout::Group] {
+            let (mut index, mut oracle) = resizing(layout);
+            let capacity = index.capacity();
+            // The table is overloaded before the old one is empty
+            index.set_max_load(index.len as f64 / capacity as f64);
+            index.insert(u64::MAX, 0);
+            oracle.insert(u64::MAX, 0);
+
+            // The old table was moved over, then another resize started
+            // instead of rebuilding both tables at once
+            assert!(index.old.as_ref().unwrap().capacity >= capacity);
+            assert_eq!(index.len(), oracle.len());
+            for (k, v) in &oracle {
+                assert_eq!(index.get(k), Some(v));
+            }
+        }
+    }
+
+    #[test]
+    fn retain_matches_hashmap() {
+        for &layout in &[Layout::Probing, Layout::RobinHood, Layout::Group] {
+            let (mut index, mut oracle) = resizing(layout);
+            index.set_min_load(0.2);
+
+            for round in 0..4u64 {
+                let len = index.len();
+                let mut visits = HashMap::new();
+                index.retain(|k, v| {
+                    *visits.entry(*k).or_insert(0) += 1;
+                    *v += 1;
+                    (k + round) % 3 != 0
+                });
+                oracle.retain(|k, v| {
+                    *v += 1;
+                    (k + round) % 3 != 0
+                });
+
+                // every entry is visited exactly once, even when Robin Hood
+                // moves entries back
+                assert_eq!(visits.len(), len);
+                assert!(visits.values().all(|&n| n == 1));
+                assert_eq!(index.len(), oracle.len());
+                for k in 0..oracle.len() as u64 * 30 {
+                    assert_eq!(index.get(&k), oracle.get(&k));
+                }
+            }
+        }
+
+        // Shrinks once the load drops below `min_load`
+        let mut index = Index::with_capacity_and_parameters(8, params_with_min_load());
+        for k in 0..300u64 {
+            index.insert(k, k);
+        }
+        let capacity = index.capacity();
+        index.retain(|k, _| *k < 10);
+        assert!(index.capacity() < capacity);
+        assert_eq!(index.len(), 10);
+    }
+
+    #[test]
+    fn collect_and_iterate_match_hashmap() {
+        let pairs: Vec<(u64, u64)> = (0..3000u64).map(|k| (k % 2000, k)).collect();
+        let mut index: Index<u64, u64> = pairs.iter().copied().collect();
+        let mut oracle: HashMap<u64, u64> = pairs.iter().copied().collect();
+        assert_eq!(index.len(), oracle.len());
+        assert_eq!(
+            index.iter().collect::<HashMap<_, _>>(),
+            oracle.iter().collect()
+        );
+
+        index.extend((1500..2500u64).map(|k| (k, k * 2)));
+        oracle.extend((1500..2500u64).map(|k| (k, k * 2)));
+        index.extend(
+            &[(7, 7), (5000, 1)]
+                .iter()
+                .copied()
+                .collect::<HashMap<_, _>>(),
+        );
+        oracle.extend(
+            &[(7, 7), (5000, 1)]
+                .iter()
+                .copied()
+                .collect::<HashMap<_, _>>(),
+        );
+        for (k, v) in &mut index {
+            *v += k;
+        }
+        for (k, v) in &mut oracle {
+            *v += k;
+        }
+        assert_eq!((&index).into_iter().count(), oracle.len());
+        assert_eq!(index.into_iter().collect::<HashMap<_, _>>(), oracle);
+
+        // Owning iteration also takes the entries of the old table
+        let (index, oracle) = resizing(Layout::RobinHood);
+        assert_eq!(index.into_iter().collect::<HashMap<_, _>>(), oracle);
+
+        let index: Index<&str, u8, HashFunction> = vec![("a", 1)].into_iter().collect();
+        assert_eq!(index.hasher(), &HashFunction::Fnv);
+    }
+
+    #[test]
+    fn lookups_and_eq_match_hashmap() {
+        let (mut index, mut oracle) = resizing(Layout::Group);
+        for k in 0..oracle.len() as u64 * 10 {
+            assert_eq!(index.contains_key(&k), oracle.contains_key(&k));
+            if oracle.contains_key(&k) {
+                assert_eq!(index[&k], oracle[&k]);
+            }
+        }
+
+        // Equal whatever the layout, capacity or order of insertion
+        let mut other: Index<u64, u64> = Index::with_capacity(3);
+        let mut other_oracle = HashMap::new();
+        let mut keys: Vec<_> = oracle.keys().copied().collect();
+        keys.sort_by_key(|k| core::cmp::Reverse(*k));
+        for k in keys {
+            other.insert(k, oracle[&k]);
+            other_oracle.insert(k, oracle[&k]);
+        }
+        assert_eq!(index == other, oracle == other_oracle);
+        assert!(index == other);
+
+        *other.get_mut(&7).unwrap() += 1;
+        *other_oracle.get_mut(&7).unwrap() += 1;
+        assert_eq!(index == other, oracle == other_oracle);
+        assert!(index != other);
+        other.remove(&7);
+        other_oracle.remove(&7);
+        index.remove(&7);
+        oracle.remove(&7);
+        assert_eq!(index == other, oracle == other_oracle);
+        other.insert(1, 1);
+        other_oracle.insert(1, 1);
+        assert_eq!(index == other, oracle == other_oracle);
+    }
+
+    #[test]
+    #[should_panic(expected = "no entry found for key")]
+    fn index_panics_on_missing_key() {
+        let index: Index<u64, u64> = (0..10).map(|k| (k, k)).collect();
+        let _ = index[&10];
+    }
+
+    #[test]
+    fn reserve_matches_hashmap() {
+        let (mut index, mut oracle) = resizing(Layout::Probing);
+        index.reserve(10_000);
+        oracle.reserve(10_000);
+        // the tables are merged right away
+        assert!(index.old.is_none());
+
+        // already fits
+        let capacity = index.capacity();
+        index.reserve(5_000);
+        assert_eq!(index.capacity(), capacity);
+
+        for k in 0..10_000u64 {
+            index.insert(k * 7 + 1, k);
+            oracle.insert(k * 7 + 1, k);
+        }
+        assert_eq!(index.capacity(), capacity);
+        assert_eq!(index.len(), oracle.len());
+        for (k, v) in &oracle {
+            assert_eq!(index.get(k), Some(v));
+        }
+    }
+}
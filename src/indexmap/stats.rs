@@ -0,0 +1,253 @@
+//! Module computing the probe length and occupancy statistics of an
+//! [`Index`] hash table, see [`Index::stats`].
+//!
+//! [`Index`]: struct.Index.html
+//! [`Index::stats`]: struct.Index.html#method.stats
+
+use super::group::{Group, GROUP_WIDTH};
+use super::hash::make_hash;
+use super::{Bucket, Index, Layout};
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::mem;
+
+/// Probe length and occupancy statistics of an [`Index`].
+///
+/// Probes count buckets, except with `Layout::Group` where they count the
+/// groups a lookup loads. While the `Index` is resizing, the entries of the
+/// old table count with their probe length there and misses probe both
+/// tables.
+///
+/// [`Index`]: struct.Index.html
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// Number of entries.
+    pub len: usize,
+    /// Number of buckets of the table.
+    pub capacity: usize,
+    /// Number of tombstones.
+    pub tombstones: usize,
+    /// `probe_lengths[n]` is the number of entries a lookup finds with
+    /// `n + 1` probes.
+    pub probe_lengths: Vec<usize>,
+    /// Average number of probes of a lookup for a missing key, assuming its
+    /// home bucket is equally likely to be any bucket.
+    pub miss_cost: f64,
+    /// Longest run of consecutive buckets that aren't empty.
+    pub longest_cluster: usize,
+    /// Bytes allocated by the tables, not counting what keys and values
+    /// point to.
+    pub bytes: usize,
+}
+
+impl Stats {
+    /// Average number of probes of a lookup for a present key.
+    pub fn average_probe_length(&self) -> f64 {
+        let probes: usize = self
+            .probe_lengths
+            .iter()
+            .enumerate()
+            .map(|(n, count)| (n + 1) * count)
+            .sum();
+        probes as f64 / self.len.max(1) as f64
+    }
+
+    /// Adds the statistics of the old table of a resizing `Index`.
+    pub(super) fn add_old(&mut self, old: Stats) {
+        self.len += old.len;
+        self.tombstones += old.tombstones;
+        if self.probe_lengths.len() < old.probe_lengths.len() {
+            self.probe_lengths.resize(old.probe_lengths.len(), 0);
+        }
+        for (n, count) in old.probe_lengths.into_iter().enumerate() {
+            self.probe_lengths[n] += count;
+        }
+        self.miss_cost += old.miss_cost;
+        self.longest_cluster = self.longest_cluster.max(old.longest_cluster);
+        self.bytes += old.bytes;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "len={} capacity={} tombstones={} avg_probes={:.3} miss_cost={:.3} longest_cluster={} bytes={} probe_lengths=[",
+            self.len,
+            self.capacity,
+            self.tombstones,
+            self.average_probe_length(),
+            self.miss_cost,
+            self.longest_cluster,
+            self.bytes
+        )?;
+        for (n, count) in self.probe_lengths.iter().enumerate() {
+            if n > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}:{}", n + 1, count)?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl<K, V, S> Index<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Statistics of the table, without the old one.
+    pub(super) fn table_stats(&self) -> Stats {
+        let mut stats = Stats {
+            len: self.len,
+            capacity: self.capacity,
+            tombstones: self.tombstones,
+            bytes: (self.table.capacity() + self.spare.capacity()) * mem::size_of::<Bucket<K, V>>()
+                + self.distances.capacity() * mem::size_of::<u32>()
+                + self.control.capacity(),
+            ..Default::default()
+        };
+
+        let mut cluster = 0;
+        for (i, bucket) in self.table.iter().enumerate() {
+            match bucket {
+                Bucket::Full((key, _)) => {
+                    let hash = make_hash(&self.params.hasher_builder, key) as usize;
+                    let probes = self.probes_to(hash, i);
+                    if stats.probe_lengths.len() < probes {
+                        stats.probe_lengths.resize(probes, 0);
+                    }
+                    stats.probe_lengths[probes - 1] += 1;
+                    cluster += 1;
+                }
+                Bucket::Tombstone => cluster += 1,
+                Bucket::Empty => cluster = 0,
+            }
+            stats.longest_cluster = stats.longest_cluster.max(cluster);
+        }
+        // The last cluster goes on at the start of the table
+        let wrapped = self
+            .table
+            .iter()
+            .take_while(|bucket| !matches!(bucket, Bucket::Empty))
+            .count();
+        stats.longest_cluster = self
+            .capacity
+            .min(stats.longest_cluster.max(cluster + wrapped));
+
+        let misses: usize = (0..self.capacity).map(|home| self.miss_probes(home)).sum();
+        stats.miss_cost = misses as f64 / self.capacity as f64;
+        stats
+    }
+
+    /// Number of probes a lookup for a key with `hash` needs to reach
+    /// bucket `i`.
+    fn probes_to(&self, hash: usize, i: usize) -> usize {
+        match self.params.layout {
+            Layout::Probing => (0..self.capacity)
+                .position(|n| (self.params.probe)(hash, n) % self.capacity == i)
+                .map_or(self.capacity, |n| n + 1),
+            Layout::RobinHood => self.distances[i] as usize + 1,
+            Layout::Group => {
+                let groups = self.capacity / GROUP_WIDTH;
+                let first = hash % self.capacity / GROUP_WIDTH;
+                (i / GROUP_WIDTH + groups - first) % groups + 1
+            }
+        }
+    }
+
+    /// Number of probes a lookup for a missing key whose home bucket is
+    /// `home` needs.
+    fn miss_probes(&self, home: usize) -> usize {
+        match self.params.layout {
+            Layout::Probing => (0..self.capacity)
+                .position(|n| {
+                    let probe = (self.params.probe)(home, n) % self.capacity;
+                    matches!(self.table[probe], Bucket::Empty)
+                })
+                .map_or(self.capacity, |n| n + 1),
+            Layout::RobinHood => (0..self.capacity)
+                .position(|distance| {
+                    let probe = (home + distance) % self.capacity;
+                    match self.table[probe] {
+                        Bucket::Full(_) => (self.distances[probe] as usize) < distance,
+                        _ => true,
+                    }
+                })
+                .map_or(self.capacity, |distance| distance + 1),
+            Layout::Group => {
+                let groups = self.capacity / GROUP_WIDTH;
+                let first = home / GROUP_WIDTH;
+                (0..groups)
+                    .position(|n| {
+                        let start = (first + n) % groups * GROUP_WIDTH;
+                        Group::load(&self.control[start..]).match_empty().any()
+                    })
+                    .map_or(groups, |n| n + 1)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Parameters;
+    use super::*;
+    use core::hash::Hasher;
+
+    /// Hashes a `u64` to itself.
+    #[derive(Clone)]
+    struct Identity(u64);
+
+    impl Hasher for Identity {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &byte in bytes.iter().rev() {
+                self.0 = self.0 << 8 | u64::from(byte);
+            }
+        }
+    }
+
+    impl BuildHasher for Identity {
+        type Hasher = Identity;
+
+        fn build_hasher(&self) -> Identity {
+            Identity(0)
+        }
+    }
+
+    #[test]
+    fn counts_probes() {
+        let params = Parameters {
+            max_load: 0.9,
+            min_load: 0.0,
+            growth_policy: 2.0,
+            hasher_builder: Identity(0),
+            probe: |hash, i| hash + i,
+            layout: Layout::Probing,
+        };
+        let mut index = Index::with_capacity_and_parameters(8, params);
+        for &k in &[0u64, 8, 16, 3] {
+            index.insert(k, ());
+        }
+
+        // 0, 8 and 16 share bucket 0, 3 is at home: buckets 0 to 3 are full
+        let stats = index.stats();
+        assert_eq!(stats.probe_lengths, vec![2, 1, 1]);
+        assert_eq!(stats.average_probe_length(), 7.0 / 4.0);
+        assert_eq!(stats.longest_cluster, 4);
+        assert_eq!(stats.miss_cost, (5 + 4 + 3 + 2 + 4) as f64 / 8.0);
+        assert!(stats.bytes >= 8 * mem::size_of::<Bucket<u64, ()>>());
+
+        index.remove(&8);
+        let stats = index.stats();
+        assert_eq!((stats.len, stats.tombstones), (3, 1));
+        assert_eq!(stats.probe_lengths, vec![2, 0, 1]);
+        assert_eq!(stats.longest_cluster, 4);
+    }
+}
@@ -9,6 +9,8 @@ extern crate alloc;
 extern crate test;
 
 use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::cell::Cell;
 
 use log::trace;
 
@@ -16,8 +18,11 @@ mod arch;
 mod indexmap;
 
 mod memb;
+mod redis;
+mod resp;
 
 use arch::PlatformSupport;
+use indexmap::hash::HashFunction;
 use memb::{serialize::encode_with_buf, serialize::Decoder, Value};
 
 #[cfg(target_os = "linux")]
@@ -27,21 +32,352 @@ use jemallocator::Jemalloc;
 #[global_allocator]
 static GLOBAL: Jemalloc = Jemalloc;
 
+/// The different data types a key can hold.
+#[derive(Debug, Clone)]
+pub enum Data {
+    /// A binary string, memcached only knows about those.
+    String(Vec<u8>),
+    /// A map of fields to values.
+    Hash(indexmap::Index<Vec<u8>, Vec<u8>, HashFunction>),
+    /// A sequence of strings.
+    List(VecDeque<Vec<u8>>),
+    /// An unordered collection of unique strings.
+    Set(indexmap::Index<Vec<u8>, (), HashFunction>),
+    /// Unique strings ordered by a score.
+    SortedSet(redis::SortedSet),
+}
+
+impl Data {
+    pub fn as_string(&self) -> Option<&Vec<u8>> {
+        match self {
+            Data::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_string_mut(&mut self) -> Option<&mut Vec<u8>> {
+        match self {
+            Data::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_hash(&self) -> Option<&indexmap::Index<Vec<u8>, Vec<u8>, HashFunction>> {
+        match self {
+            Data::Hash(h) => Some(h),
+            _ => None,
+        }
+    }
+
+    pub fn as_hash_mut(&mut self) -> Option<&mut indexmap::Index<Vec<u8>, Vec<u8>, HashFunction>> {
+        match self {
+            Data::Hash(h) => Some(h),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&VecDeque<Vec<u8>>> {
+        match self {
+            Data::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Option<&mut VecDeque<Vec<u8>>> {
+        match self {
+            Data::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn as_set(&self) -> Option<&indexmap::Index<Vec<u8>, (), HashFunction>> {
+        match self {
+            Data::Set(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_set_mut(&mut self) -> Option<&mut indexmap::Index<Vec<u8>, (), HashFunction>> {
+        match self {
+            Data::Set(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_zset(&self) -> Option<&redis::SortedSet> {
+        match self {
+            Data::SortedSet(z) => Some(z),
+            _ => None,
+        }
+    }
+
+    pub fn as_zset_mut(&mut self) -> Option<&mut redis::SortedSet> {
+        match self {
+            Data::SortedSet(z) => Some(z),
+            _ => None,
+        }
+    }
+}
+
+/// Initial value of the access frequency counter of an item, so new items
+/// aren't the first to go.
+const LFU_INIT_VAL: u8 = 5;
+
+/// How slowly the access frequency counter grows (same as Redis'
+/// `lfu-log-factor`), 10 gets it to 255 after about a million accesses.
+const LFU_LOG_FACTOR: f64 = 10.0;
+
+/// Load factor below which the keyspace shrinks, Redis shrinks its
+/// dictionaries once they are less than 10% full.
+const DEFAULT_MIN_LOAD: f64 = 0.1;
+
+/// A value stored in the `SashStore`.
+#[derive(Debug, Clone)]
+pub struct Item {
+    /// Opaque flags (set by memcached clients).
+    flags: u32,
+    /// The stored value.
+    value: Data,
+    /// Unix time (in ms) at which the item expires.
+    expires_at: Option<u64>,
+    /// Unix time (in ms) of the last access.
+    accessed_at: Cell<u64>,
+    /// Logarithmic access frequency counter (see `Item::touch`).
+    freq: Cell<u8>,
+}
+
+impl Item {
+    /// Creates a new string item that never expires.
+    fn new(flags: u32, value: Vec<u8>) -> Item {
+        Item::with_data(flags, Data::String(value))
+    }
+
+    /// Creates a new item of any type that never expires.
+    fn with_data(flags: u32, value: Data) -> Item {
+        Item {
+            flags,
+            value,
+            expires_at: None,
+            accessed_at: Cell::new(0),
+            freq: Cell::new(LFU_INIT_VAL),
+        }
+    }
+
+    /// Returns `true` if the item is no longer valid at time `now` (unix ms).
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.map_or(false, |deadline| deadline <= now)
+    }
+
+    /// Records an access at time `now` (unix ms).
+    ///
+    /// The frequency counter works like the LFU counter of Redis: it
+    /// decays by one for every minute without access and grows with a
+    /// probability that shrinks as it gets bigger, `random` decides.
+    fn touch(&self, now: u64, random: u64) {
+        let mut freq = self.frequency(now);
+        let base = freq.saturating_sub(LFU_INIT_VAL) as f64;
+        if freq < 255 && (random as f64 / u64::MAX as f64) < 1.0 / (base * LFU_LOG_FACTOR + 1.0) {
+            freq += 1;
+        }
+        self.freq.set(freq);
+        self.accessed_at.set(now);
+    }
+
+    /// The access frequency counter at time `now` (unix ms), without
+    /// counting an access.
+    fn frequency(&self, now: u64) -> u8 {
+        let idle_minutes = now.saturating_sub(self.accessed_at.get()) / 60_000;
+        self.freq.get().saturating_sub(idle_minutes.min(255) as u8)
+    }
+}
+
 pub struct SashStore {
-    /// Maps key -> (flags, value)
-    map: indexmap::Index<Vec<u8>, (u32, Vec<u8>)>,
+    /// Maps key -> item
+    map: indexmap::Index<Vec<u8>, Item, HashFunction>,
+    /// Number of items with a deadline, expired ones included
+    expires: usize,
+    /// Sum of the deadlines (unix time in ms) of these items
+    deadline_sum: u128,
+    /// Unix time (in ms) at which the current command started executing
+    clock_ms: u64,
+    /// Next bucket to look at in the active expiry cycle
+    expire_cursor: usize,
+    /// Connection that sent the command currently executing
+    client: usize,
+    /// Clients waiting in a blocking list command, oldest first
+    blocked: Vec<redis::BlockedClient>,
+    /// Keys that received elements while clients were blocked
+    ready_keys: Vec<Vec<u8>>,
+    /// Replies for clients that got unblocked, per connection
+    unblocked: Vec<(usize, Vec<u8>)>,
+    /// State of the pseudo random generator used by commands like SPOP
+    rng: Cell<u64>,
+    /// Number of partitions (worker threads) the key space is split into
+    partitions: usize,
+    /// Per-connection state, indexed by connection
+    clients: Vec<redis::Client>,
+    /// Maps key -> connections watching it
+    watching: indexmap::Index<Vec<u8>, Vec<usize>, HashFunction>,
+    /// Maps channel -> connections subscribed to it
+    subscribers: indexmap::Index<Vec<u8>, Vec<usize>, HashFunction>,
+    /// Pattern subscriptions with their connection, oldest first
+    pattern_subscribers: Vec<(Vec<u8>, usize)>,
+    /// Encoded messages for subscribed connections, per connection
+    messages: Vec<(usize, Vec<u8>)>,
+    /// Messages published here that still have to reach the other partitions
+    published: Vec<redis::Publication>,
+    /// Connections killed with CLIENT KILL the server loop has to close
+    killed: Vec<usize>,
+    /// The settings the server was started with
+    config: arch::CmdArgs,
+    /// Which of the `partitions` partitions we are
+    partition: usize,
+    /// Statistics of every partition, indexed by partition
+    partition_stats: Arc<[redis::Stats]>,
+    /// Computes the commands per second of the partition
+    ops_sampler: redis::OpsSampler,
+    /// Unix time (in ms) at which the server started
+    started_at: u64,
+    /// Maps the lowercase name of every command to its description
+    commands: indexmap::Index<Vec<u8>, &'static redis::Command>,
 }
 
 impl SashStore {
-    /// Initialize a new SashStore instance.
+    /// Initialize a new SashStore instance that holds all keys.
+    #[cfg(test)]
     fn with_capacity(cap: usize) -> Self {
+        SashStore::with_partitions(cap, 1)
+    }
+
+    /// Initialize a new SashStore instance serving one of `partitions`
+    /// partitions.
+    #[cfg(test)]
+    fn with_partitions(cap: usize, partitions: usize) -> Self {
+        let config = arch::CmdArgs {
+            threads: partitions,
+            capacity: cap,
+            ..Default::default()
+        };
+        let stats = (0..partitions).map(|_| Default::default()).collect();
+        SashStore::with_config(config, 0, stats)
+    }
+
+    /// Initialize a new SashStore instance serving `partition`, one of the
+    /// partitions of the server `config` describes.
+    ///
+    /// `partition_stats` holds the statistics of every partition and is
+    /// shared by all of them.
+    fn with_config(
+        config: arch::CmdArgs,
+        partition: usize,
+        partition_stats: Arc<[redis::Stats]>,
+    ) -> Self {
         SashStore {
-            map: indexmap::Index::with_capacity(cap),
+            map: indexmap::Index::with_capacity_and_parameters(
+                config.capacity,
+                indexmap::Parameters {
+                    min_load: DEFAULT_MIN_LOAD,
+                    ..indexmap::Parameters::with_hasher(config.hasher)
+                },
+            ),
+            expires: 0,
+            deadline_sum: 0,
+            clock_ms: arch::arch::unix_time_ms(),
+            expire_cursor: 0,
+            client: 0,
+            blocked: Vec::new(),
+            ready_keys: Vec::new(),
+            unblocked: Vec::new(),
+            rng: Cell::new(arch::arch::unix_time_ms() | 1),
+            partitions: config.threads,
+            clients: Vec::new(),
+            watching: indexmap::Index::with_hasher(config.hasher),
+            subscribers: indexmap::Index::with_hasher(config.hasher),
+            pattern_subscribers: Vec::new(),
+            messages: Vec::new(),
+            published: Vec::new(),
+            killed: Vec::new(),
+            config,
+            partition,
+            partition_stats,
+            ops_sampler: Default::default(),
+            started_at: arch::arch::unix_time_ms(),
+            commands: redis::command_table(),
+        }
+    }
+
+    /// Returns the item stored under `key` unless it has expired.
+    ///
+    /// Expired items are left in place, they get overwritten by the next
+    /// write or reclaimed by the active expiry cycle. Lookups can't move an
+    /// incremental resize of the keyspace along, `execute_args` takes a
+    /// step of it for every command instead.
+    fn lookup(&self, key: &[u8]) -> Option<&Item> {
+        let now = self.clock_ms;
+        let item = self.map.get(key).filter(|item| !item.is_expired(now));
+        self.stats().count_lookup(item.is_some());
+        let item = item?;
+        item.touch(now, self.random());
+        Some(item)
+    }
+
+    /// Returns the item stored under `key` for modification unless it has expired.
+    ///
+    /// WATCH doesn't notice the modification, commands flagged `write`
+    /// signal it for their keys once they succeeded (see
+    /// `signal_modified_key`).
+    fn lookup_mut(&mut self, key: &[u8]) -> Option<&mut Item> {
+        let now = self.clock_ms;
+        let random = self.random();
+        let item = self.map.get_mut(key).filter(|item| !item.is_expired(now))?;
+        item.touch(now, random);
+        Some(item)
+    }
+
+    /// Tells the connections watching `key` that it was written, unless it
+    /// doesn't exist (deleting it already did).
+    fn signal_modified_key(&mut self, key: &[u8]) {
+        if !self.watching.is_empty() && self.map.get(key).is_some() {
+            self.touch_watched_key(key);
+        }
+    }
+
+    /// Stores `item` under `key`, replacing any previous item.
+    fn insert(&mut self, key: Vec<u8>, item: Item) {
+        item.accessed_at.set(self.clock_ms);
+        let deadline = item.expires_at;
+        let replaced = match self.map.insert(key, item) {
+            indexmap::Bucket::Full((_, old)) => old.expires_at,
+            _ => None,
+        };
+        self.count_deadline(replaced, deadline);
+    }
+
+    /// Removes the item stored under `key`, expired or not.
+    fn remove(&mut self, key: &[u8]) -> Option<Item> {
+        let item = self.map.remove(key)?;
+        self.count_deadline(item.expires_at, None);
+        self.touch_watched_key(key);
+        Some(item)
+    }
+
+    /// Keeps `expires` and `deadline_sum` up to date when the deadline of
+    /// an item changes from `old` to `new` (`None` if there is no deadline
+    /// or no item).
+    fn count_deadline(&mut self, old: Option<u64>, new: Option<u64>) {
+        if let Some(deadline) = old {
+            self.expires -= 1;
+            self.deadline_sum -= deadline as u128;
+        }
+        if let Some(deadline) = new {
+            self.expires += 1;
+            self.deadline_sum += deadline as u128;
         }
     }
 
     /// Execute the content of a packet buffer in our KV store.
     pub fn handle_network_request(&mut self, buf: Vec<u8>) -> Vec<u8> {
+        self.clock_ms = arch::arch::unix_time_ms();
         let reader = VecDeque::from(buf);
         let mut decoder = Decoder::new(reader);
         let response = match decoder.decode() {
@@ -59,10 +395,10 @@ impl SashStore {
         match cmd {
             Value::Get(req_id, key) => {
                 trace!("Execute .get for {:?}", key);
-                let r = self.map.get(&key);
-                match r {
-                    Some(value) => Value::Value(req_id, key, value.0, value.1.to_vec()),
-                    None => {
+                let r = self.lookup(&key);
+                match r.as_ref().map(|item| (item.flags, item.value.as_string())) {
+                    Some((flags, Some(value))) => Value::Value(req_id, key, flags, value.to_vec()),
+                    _ => {
                         unreachable!("didn't find value for key {:?}", key);
                         //Value::NoReply
                     }
@@ -70,7 +406,7 @@ impl SashStore {
             }
             Value::Set(req_id, key, flags, value) => {
                 trace!("Set for {:?} {:?}", key, value);
-                self.map.insert(key, (flags, value));
+                self.insert(key, Item::new(flags, value));
                 Value::Stored(req_id)
             }
             _ => unreachable!(),
@@ -86,15 +422,20 @@ fn main() {
     let cmd = platform.parse_args();
     platform.init_logging();
     let cores = platform.allocate_cores(cmd.threads, cmd.numa_strategy);
+    // Lets the threads forward messages published with Pub/Sub to each other
+    let mailboxes = arch::arch::mailboxes(cores.len());
+    // Lets every thread report the statistics of all of them
+    let stats: Arc<[redis::Stats]> = cores.iter().map(|_| Default::default()).collect();
 
     // Spawn threads on cores
     let mut tids = Vec::with_capacity(cmd.threads);
-    for (idx, core) in cores.into_iter().enumerate() {
+    for ((idx, core), mailbox) in cores.into_iter().enumerate().zip(mailboxes) {
+        let stats = stats.clone();
         let tid = platform.spawn(
             move || {
                 trace!("Worker thread says hi from core {}.", core);
-                let mut map: SashStore = SashStore::with_capacity(cmd.capacity);
-                arch::arch::server_loop(core, idx, &cmd, &mut map);
+                let mut map: SashStore = SashStore::with_config(cmd, idx, stats);
+                arch::arch::server_loop(core, idx, &cmd, mailbox, &mut map);
                 0
             },
             core,
@@ -0,0 +1,225 @@
+//! State the server keeps per connection and the CLIENT command.
+//!
+//! Connections are identified by their index in the server loop of the
+//! worker thread serving them, CLIENT only sees the connections of that
+//! worker thread.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::{ok, parse_int, Args, Reply, ERR_NOT_INTEGER, ERR_SYNTAX};
+use crate::arch::arch::unix_time_ms;
+use crate::resp::value::Value;
+use crate::SashStore;
+
+/// Per-connection state.
+#[derive(Debug, Default)]
+pub(crate) struct Client {
+    /// Commands queued since MULTI, `None` outside of a transaction
+    pub multi: Option<Vec<Args>>,
+    /// A command couldn't be queued, EXEC has to discard the transaction
+    pub multi_failed: bool,
+    /// Keys the connection watches, with whether they existed at the time
+    /// of WATCH
+    pub watched: Vec<(Vec<u8>, bool)>,
+    /// A watched key was written to, EXEC has to abort the transaction
+    pub dirty: bool,
+    /// Channels the connection subscribed to
+    pub channels: Vec<Vec<u8>>,
+    /// Patterns the connection subscribed to
+    pub patterns: Vec<Vec<u8>>,
+    /// Set with CLIENT SETNAME, empty if the connection has no name
+    pub name: Vec<u8>,
+    /// Address of the peer
+    pub addr: String,
+    /// Unix time (in ms) at which the connection was accepted
+    pub connected_at: u64,
+    /// Unix time (in ms) at which the last command started executing
+    pub last_active: u64,
+    /// Name of the last command, in lowercase
+    pub last_command: Vec<u8>,
+    /// CLIENT KILL closed the connection
+    pub killed: bool,
+}
+
+impl Client {
+    /// Number of channels and patterns the connection subscribed to.
+    pub fn subscriptions(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+}
+
+impl SashStore {
+    /// CLIENT ID|GETNAME|SETNAME|LIST|KILL [arguments]
+    pub(super) fn redis_client(&mut self, args: Args) -> Reply {
+        let subcommand = args[1].to_ascii_uppercase();
+        match (subcommand.as_slice(), args.len()) {
+            (b"ID", 2) => Ok(Value::Integer(self.client as i64)),
+            (b"GETNAME", 2) => Ok(match &self.current_client().name {
+                name if name.is_empty() => Value::Null,
+                name => Value::BufBulk(name.clone()),
+            }),
+            (b"SETNAME", 3) => {
+                if args[2].iter().any(|&c| c <= b' ' || c > b'~') {
+                    return Err(Value::StaticError(
+                        "ERR Client names cannot contain spaces, newlines or special characters.",
+                    ));
+                }
+                self.current_client().name = args[2].clone();
+                Ok(ok())
+            }
+            (b"LIST", 2) => Ok(Value::BufBulk(self.client_list())),
+            (b"KILL", 3) => match self.find_client(&args[2]) {
+                Some(victim) => {
+                    self.kill_client(victim);
+                    Ok(ok())
+                }
+                None => Err(Value::StaticError("ERR No such client")),
+            },
+            (b"KILL", _) if args.len() > 3 => self.client_kill_filtered(&args[2..]),
+            _ => Err(Value::Error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try CLIENT HELP.",
+                String::from_utf8_lossy(&args[1])
+            ))),
+        }
+    }
+
+    /// Records a new connection from `addr`.
+    pub fn accept_client(&mut self, client: usize, addr: String) {
+        let now = unix_time_ms();
+        let state = self.client_state(client);
+        state.addr = addr;
+        state.connected_at = now;
+        state.last_active = now;
+    }
+
+    /// Returns the connections killed with CLIENT KILL since the last call,
+    /// the caller has to close them.
+    pub fn take_killed(&mut self) -> Vec<usize> {
+        core::mem::take(&mut self.killed)
+    }
+
+    /// Returns `true` if `client` got killed with CLIENT KILL.
+    pub(super) fn is_killed(&self, client: usize) -> bool {
+        self.clients.get(client).map_or(false, |state| state.killed)
+    }
+
+    /// One line per connection, in the format of Redis.
+    fn client_list(&self) -> Vec<u8> {
+        let now = self.clock_ms;
+        let mut list = Vec::new();
+        for (id, client) in self.clients.iter().enumerate() {
+            if client.killed {
+                continue;
+            }
+            let mut flags = String::new();
+            if client.multi.is_some() {
+                flags.push('x');
+            }
+            if self.is_blocked(id) {
+                flags.push('b');
+            }
+            if client.subscriptions() > 0 {
+                flags.push('P');
+            }
+            if flags.is_empty() {
+                flags.push('N');
+            }
+            list.extend(
+                format!(
+                    "id={} addr={} name={} age={} idle={} flags={} sub={} psub={} multi={} cmd={}\n",
+                    id,
+                    client.addr,
+                    String::from_utf8_lossy(&client.name),
+                    now.saturating_sub(client.connected_at) / 1000,
+                    now.saturating_sub(client.last_active) / 1000,
+                    flags,
+                    client.channels.len(),
+                    client.patterns.len(),
+                    client.multi.as_ref().map_or(-1, |queued| queued.len() as i64),
+                    String::from_utf8_lossy(&client.last_command),
+                )
+                .into_bytes(),
+            );
+        }
+        list
+    }
+
+    /// The live connection from `addr`.
+    fn find_client(&self, addr: &[u8]) -> Option<usize> {
+        self.clients
+            .iter()
+            .position(|client| !client.killed && client.addr.as_bytes() == addr)
+    }
+
+    /// CLIENT KILL [ID client-id] [ADDR ip:port] [SKIPME yes|no]
+    ///
+    /// Kills all connections matching the filters, but not the calling one
+    /// unless SKIPME is `no`.
+    fn client_kill_filtered(&mut self, filters: &[Vec<u8>]) -> Reply {
+        let (mut id, mut addr, mut skip_me) = (None, None, true);
+        for filter in filters.chunks(2) {
+            if filter.len() != 2 {
+                return Err(Value::StaticError(ERR_SYNTAX));
+            }
+            match filter[0].to_ascii_uppercase().as_slice() {
+                b"ID" => match parse_int(&filter[1]) {
+                    Some(client) if client >= 0 => id = Some(client as usize),
+                    Some(_) => return Err(Value::StaticError("ERR No such client")),
+                    None => return Err(Value::StaticError(ERR_NOT_INTEGER)),
+                },
+                b"ADDR" => addr = Some(&filter[1]),
+                b"SKIPME" => match filter[1].to_ascii_uppercase().as_slice() {
+                    b"YES" => skip_me = true,
+                    b"NO" => skip_me = false,
+                    _ => return Err(Value::StaticError(ERR_SYNTAX)),
+                },
+                _ => return Err(Value::StaticError(ERR_SYNTAX)),
+            }
+        }
+
+        let victims: Vec<usize> = (0..self.clients.len())
+            .filter(|&client| {
+                let state = &self.clients[client];
+                !state.killed
+                    && id.map_or(true, |id| id == client)
+                    && addr.map_or(true, |addr| state.addr.as_bytes() == addr.as_slice())
+                    && !(skip_me && client == self.client)
+            })
+            .collect();
+        for &victim in &victims {
+            self.kill_client(victim);
+        }
+        Ok(Value::Integer(victims.len() as i64))
+    }
+
+    /// Forgets everything about `victim` and queues it for `take_killed`.
+    fn kill_client(&mut self, victim: usize) {
+        self.unwatch_all(victim);
+        let state = core::mem::take(&mut self.clients[victim]);
+        for channel in &state.channels {
+            self.leave_channel(victim, channel);
+        }
+        self.pattern_subscribers
+            .retain(|(_, client)| *client != victim);
+        self.cancel_blocking(victim);
+        self.messages.retain(|(client, _)| *client != victim);
+        self.unblocked.retain(|(client, _)| *client != victim);
+        self.clients[victim].killed = true;
+        self.killed.push(victim);
+    }
+
+    /// State of the connection that sent the currently executing command.
+    pub(super) fn current_client(&mut self) -> &mut Client {
+        self.client_state(self.client)
+    }
+
+    /// State of the connection `client`.
+    pub(super) fn client_state(&mut self, client: usize) -> &mut Client {
+        if self.clients.len() <= client {
+            self.clients.resize_with(client + 1, Default::default);
+        }
+        &mut self.clients[client]
+    }
+}
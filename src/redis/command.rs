@@ -0,0 +1,408 @@
+//! The command table: arity, flags and key positions of every command.
+//!
+//! The table drives dispatching commands, checking their number of
+//! arguments and finding the keys they touch. COMMAND reports it to
+//! clients, many of them ask for it when they connect.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{parse_int, Args, Handler, Reply};
+use crate::indexmap::Index;
+use crate::resp::value::Value;
+use crate::SashStore;
+
+/// A command, described like COMMAND reports it.
+#[derive(Debug)]
+pub(crate) struct Command {
+    /// Name of the command, in lowercase
+    pub name: &'static str,
+    /// Number of arguments, including the command name. Follows the Redis
+    /// convention: a negative number is the minimum.
+    pub arity: isize,
+    pub handler: Handler,
+    /// Flags as Redis defines them, like `write` or `noscript`
+    pub flags: &'static [&'static str],
+    /// Position of the first key, 0 if the command takes none
+    pub first_key: usize,
+    /// Position of the last key, negative positions count from the end
+    /// (-1 is the last argument)
+    pub last_key: isize,
+    /// Distance between two keys
+    pub key_step: usize,
+    /// Group COMMAND DOCS reports
+    pub group: &'static str,
+    /// What the command does, in one sentence
+    pub summary: &'static str,
+}
+
+/// `command!(name, arity, handler, [flags], (first, last, step), group, summary)`
+macro_rules! command {
+    ($name:expr, $arity:expr, $handler:ident, [$($flag:expr),*], ($first:expr, $last:expr, $step:expr), $group:expr, $summary:expr) => {
+        Command {
+            name: $name,
+            arity: $arity,
+            handler: SashStore::$handler,
+            flags: &[$($flag),*],
+            first_key: $first,
+            last_key: $last,
+            key_step: $step,
+            group: $group,
+            summary: $summary,
+        }
+    };
+}
+
+/// All commands we know.
+#[rustfmt::skip]
+static COMMANDS: &[Command] = &[
+    command!("get", 2, redis_get, ["readonly", "fast"], (1, 1, 1), "string",
+        "Returns the string value of a key."),
+    command!("set", -3, redis_set, ["write", "denyoom"], (1, 1, 1), "string",
+        "Sets the string value of a key, optionally with an expiration."),
+    command!("getset", 3, redis_getset, ["write", "denyoom", "fast"], (1, 1, 1), "string",
+        "Sets the string value of a key and returns its previous value."),
+    command!("getdel", 2, redis_getdel, ["write", "fast"], (1, 1, 1), "string",
+        "Returns the string value of a key after deleting the key."),
+    command!("setnx", 3, redis_setnx, ["write", "denyoom", "fast"], (1, 1, 1), "string",
+        "Sets the string value of a key only if the key doesn't exist."),
+    command!("mget", -2, redis_mget, ["readonly", "fast"], (1, -1, 1), "string",
+        "Returns the string values of one or more keys."),
+    command!("mset", -3, redis_mset, ["write", "denyoom"], (1, -1, 2), "string",
+        "Sets the string values of one or more keys."),
+    command!("msetnx", -3, redis_msetnx, ["write", "denyoom"], (1, -1, 2), "string",
+        "Sets the string values of one or more keys only if none of them exist."),
+    command!("incr", 2, redis_incr, ["write", "denyoom", "fast"], (1, 1, 1), "string",
+        "Increments the integer value of a key by one."),
+    command!("decr", 2, redis_decr, ["write", "denyoom", "fast"], (1, 1, 1), "string",
+        "Decrements the integer value of a key by one."),
+    command!("incrby", 3, redis_incrby, ["write", "denyoom", "fast"], (1, 1, 1), "string",
+        "Increments the integer value of a key by a number."),
+    command!("decrby", 3, redis_decrby, ["write", "denyoom", "fast"], (1, 1, 1), "string",
+        "Decrements the integer value of a key by a number."),
+    command!("incrbyfloat", 3, redis_incrbyfloat, ["write", "denyoom", "fast"], (1, 1, 1), "string",
+        "Increments the floating point value of a key by a number."),
+    command!("append", 3, redis_append, ["write", "denyoom", "fast"], (1, 1, 1), "string",
+        "Appends a string to the value of a key."),
+    command!("strlen", 2, redis_strlen, ["readonly", "fast"], (1, 1, 1), "string",
+        "Returns the length of a string value."),
+    command!("getrange", 4, redis_getrange, ["readonly"], (1, 1, 1), "string",
+        "Returns a substring of the string stored at a key."),
+    command!("setrange", 4, redis_setrange, ["write", "denyoom"], (1, 1, 1), "string",
+        "Overwrites part of a string value with another by an offset."),
+    command!("expire", 3, redis_expire, ["write", "fast"], (1, 1, 1), "generic",
+        "Sets the expiration time of a key in seconds."),
+    command!("pexpire", 3, redis_pexpire, ["write", "fast"], (1, 1, 1), "generic",
+        "Sets the expiration time of a key in milliseconds."),
+    command!("expireat", 3, redis_expireat, ["write", "fast"], (1, 1, 1), "generic",
+        "Sets the expiration time of a key to a Unix timestamp."),
+    command!("pexpireat", 3, redis_pexpireat, ["write", "fast"], (1, 1, 1), "generic",
+        "Sets the expiration time of a key to a Unix milliseconds timestamp."),
+    command!("ttl", 2, redis_ttl, ["readonly", "random", "fast"], (1, 1, 1), "generic",
+        "Returns the expiration time in seconds of a key."),
+    command!("pttl", 2, redis_pttl, ["readonly", "random", "fast"], (1, 1, 1), "generic",
+        "Returns the expiration time in milliseconds of a key."),
+    command!("persist", 2, redis_persist, ["write", "fast"], (1, 1, 1), "generic",
+        "Removes the expiration time of a key."),
+    command!("del", -2, redis_del, ["write"], (1, -1, 1), "generic",
+        "Deletes one or more keys."),
+    command!("unlink", -2, redis_del, ["write", "fast"], (1, -1, 1), "generic",
+        "Deletes one or more keys."),
+    command!("rename", 3, redis_rename, ["write"], (1, 2, 1), "generic",
+        "Renames a key and overwrites the destination."),
+    command!("renamenx", 3, redis_renamenx, ["write", "fast"], (1, 2, 1), "generic",
+        "Renames a key only when the target key name doesn't exist."),
+    command!("copy", -3, redis_copy, ["write", "denyoom"], (1, 2, 1), "generic",
+        "Copies the value of a key to a new key."),
+    command!("touch", -2, redis_touch, ["readonly", "fast"], (1, -1, 1), "generic",
+        "Returns the number of existing keys out of those specified after updating the time they were last accessed."),
+    command!("object", -2, redis_object, ["readonly", "random"], (2, 2, 1), "generic",
+        "Returns the internal encoding, idle time or access frequency of a key."),
+    command!("dump", 2, redis_dump, ["readonly", "random"], (1, 1, 1), "generic",
+        "Returns a serialized representation of the value stored at a key."),
+    command!("restore", -4, redis_restore, ["write", "denyoom"], (1, 1, 1), "generic",
+        "Creates a key from the serialized representation of a value."),
+    command!("scan", -2, redis_scan, ["readonly", "random"], (0, 0, 0), "generic",
+        "Iterates over the key names in the partition."),
+    command!("keys", 2, redis_keys, ["readonly"], (0, 0, 0), "generic",
+        "Returns all key names of the partition that match a pattern."),
+    command!("randomkey", 1, redis_randomkey, ["readonly", "random"], (0, 0, 0), "generic",
+        "Returns a random key name from the partition."),
+    command!("dbsize", 1, redis_dbsize, ["readonly", "fast"], (0, 0, 0), "server",
+        "Returns the number of keys in the partition."),
+    command!("type", 2, redis_type, ["readonly", "fast"], (1, 1, 1), "generic",
+        "Determines the type of value stored at a key."),
+    command!("hset", -4, redis_hset, ["write", "denyoom", "fast"], (1, 1, 1), "hash",
+        "Creates or modifies the value of a field in a hash."),
+    command!("hget", 3, redis_hget, ["readonly", "fast"], (1, 1, 1), "hash",
+        "Returns the value of a field in a hash."),
+    command!("hmget", -3, redis_hmget, ["readonly", "fast"], (1, 1, 1), "hash",
+        "Returns the values of the given fields in a hash."),
+    command!("hdel", -3, redis_hdel, ["write", "fast"], (1, 1, 1), "hash",
+        "Deletes one or more fields and their values from a hash."),
+    command!("hexists", 3, redis_hexists, ["readonly", "fast"], (1, 1, 1), "hash",
+        "Determines whether a field exists in a hash."),
+    command!("hlen", 2, redis_hlen, ["readonly", "fast"], (1, 1, 1), "hash",
+        "Returns the number of fields in a hash."),
+    command!("hkeys", 2, redis_hkeys, ["readonly"], (1, 1, 1), "hash",
+        "Returns all fields in a hash."),
+    command!("hvals", 2, redis_hvals, ["readonly"], (1, 1, 1), "hash",
+        "Returns all values in a hash."),
+    command!("hgetall", 2, redis_hgetall, ["readonly", "random"], (1, 1, 1), "hash",
+        "Returns all fields and values in a hash."),
+    command!("hincrby", 4, redis_hincrby, ["write", "denyoom", "fast"], (1, 1, 1), "hash",
+        "Increments the integer value of a field in a hash by a number."),
+    command!("hscan", -3, redis_hscan, ["readonly", "random"], (1, 1, 1), "hash",
+        "Iterates over fields and values of a hash."),
+    command!("lpush", -3, redis_lpush, ["write", "denyoom", "fast"], (1, 1, 1), "list",
+        "Prepends one or more elements to a list."),
+    command!("rpush", -3, redis_rpush, ["write", "denyoom", "fast"], (1, 1, 1), "list",
+        "Appends one or more elements to a list."),
+    command!("lpop", -2, redis_lpop, ["write", "fast"], (1, 1, 1), "list",
+        "Returns the first elements in a list after removing them."),
+    command!("rpop", -2, redis_rpop, ["write", "fast"], (1, 1, 1), "list",
+        "Returns and removes the last elements of a list."),
+    command!("llen", 2, redis_llen, ["readonly", "fast"], (1, 1, 1), "list",
+        "Returns the length of a list."),
+    command!("lrange", 4, redis_lrange, ["readonly"], (1, 1, 1), "list",
+        "Returns a range of elements from a list."),
+    command!("lindex", 3, redis_lindex, ["readonly"], (1, 1, 1), "list",
+        "Returns an element from a list by its index."),
+    command!("lset", 4, redis_lset, ["write", "denyoom"], (1, 1, 1), "list",
+        "Sets the value of an element in a list by its index."),
+    command!("lrem", 4, redis_lrem, ["write"], (1, 1, 1), "list",
+        "Removes elements from a list."),
+    command!("ltrim", 4, redis_ltrim, ["write"], (1, 1, 1), "list",
+        "Removes elements from both ends of a list."),
+    command!("linsert", 5, redis_linsert, ["write", "denyoom"], (1, 1, 1), "list",
+        "Inserts an element before or after another element in a list."),
+    command!("blpop", -3, redis_blpop, ["write", "noscript"], (1, -2, 1), "list",
+        "Removes and returns the first element in a list, blocks until an element is available otherwise."),
+    command!("brpop", -3, redis_brpop, ["write", "noscript"], (1, -2, 1), "list",
+        "Removes and returns the last element in a list, blocks until an element is available otherwise."),
+    command!("sadd", -3, redis_sadd, ["write", "denyoom", "fast"], (1, 1, 1), "set",
+        "Adds one or more members to a set."),
+    command!("srem", -3, redis_srem, ["write", "fast"], (1, 1, 1), "set",
+        "Removes one or more members from a set."),
+    command!("smembers", 2, redis_smembers, ["readonly"], (1, 1, 1), "set",
+        "Returns all members of a set."),
+    command!("sismember", 3, redis_sismember, ["readonly", "fast"], (1, 1, 1), "set",
+        "Determines whether a member belongs to a set."),
+    command!("smismember", -3, redis_smismember, ["readonly", "fast"], (1, 1, 1), "set",
+        "Determines whether multiple members belong to a set."),
+    command!("scard", 2, redis_scard, ["readonly", "fast"], (1, 1, 1), "set",
+        "Returns the number of members in a set."),
+    command!("spop", -2, redis_spop, ["write", "random", "fast"], (1, 1, 1), "set",
+        "Returns one or more random members from a set after removing them."),
+    command!("srandmember", -2, redis_srandmember, ["readonly", "random"], (1, 1, 1), "set",
+        "Returns one or more random members from a set."),
+    command!("sinter", -2, redis_sinter, ["readonly"], (1, -1, 1), "set",
+        "Returns the intersection of multiple sets."),
+    command!("sunion", -2, redis_sunion, ["readonly"], (1, -1, 1), "set",
+        "Returns the union of multiple sets."),
+    command!("sdiff", -2, redis_sdiff, ["readonly"], (1, -1, 1), "set",
+        "Returns the difference of multiple sets."),
+    command!("sinterstore", -3, redis_sinterstore, ["write", "denyoom"], (1, -1, 1), "set",
+        "Stores the intersection of multiple sets in a key."),
+    command!("sunionstore", -3, redis_sunionstore, ["write", "denyoom"], (1, -1, 1), "set",
+        "Stores the union of multiple sets in a key."),
+    command!("sdiffstore", -3, redis_sdiffstore, ["write", "denyoom"], (1, -1, 1), "set",
+        "Stores the difference of multiple sets in a key."),
+    command!("sscan", -3, redis_sscan, ["readonly", "random"], (1, 1, 1), "set",
+        "Iterates over members of a set."),
+    command!("zadd", -4, redis_zadd, ["write", "denyoom", "fast"], (1, 1, 1), "sorted_set",
+        "Adds one or more members to a sorted set, or updates their scores."),
+    command!("zincrby", 4, redis_zincrby, ["write", "denyoom", "fast"], (1, 1, 1), "sorted_set",
+        "Increments the score of a member in a sorted set."),
+    command!("zrem", -3, redis_zrem, ["write", "fast"], (1, 1, 1), "sorted_set",
+        "Removes one or more members from a sorted set."),
+    command!("zscore", 3, redis_zscore, ["readonly", "fast"], (1, 1, 1), "sorted_set",
+        "Returns the score of a member in a sorted set."),
+    command!("zcard", 2, redis_zcard, ["readonly", "fast"], (1, 1, 1), "sorted_set",
+        "Returns the number of members in a sorted set."),
+    command!("zcount", 4, redis_zcount, ["readonly", "fast"], (1, 1, 1), "sorted_set",
+        "Returns the count of members in a sorted set that have scores within a range."),
+    command!("zrank", -3, redis_zrank, ["readonly", "fast"], (1, 1, 1), "sorted_set",
+        "Returns the index of a member in a sorted set ordered by ascending scores."),
+    command!("zrevrank", -3, redis_zrevrank, ["readonly", "fast"], (1, 1, 1), "sorted_set",
+        "Returns the index of a member in a sorted set ordered by descending scores."),
+    command!("zrange", -4, redis_zrange, ["readonly"], (1, 1, 1), "sorted_set",
+        "Returns members in a sorted set within a range of indexes or scores."),
+    command!("zpopmin", -2, redis_zpopmin, ["write", "fast"], (1, 1, 1), "sorted_set",
+        "Returns the lowest-scoring members from a sorted set after removing them."),
+    command!("zpopmax", -2, redis_zpopmax, ["write", "fast"], (1, 1, 1), "sorted_set",
+        "Returns the highest-scoring members from a sorted set after removing them."),
+    command!("multi", 1, redis_multi, ["noscript", "fast"], (0, 0, 0), "transactions",
+        "Starts a transaction."),
+    command!("exec", 1, redis_exec, ["noscript"], (0, 0, 0), "transactions",
+        "Executes all commands in a transaction."),
+    command!("discard", 1, redis_discard, ["noscript", "fast"], (0, 0, 0), "transactions",
+        "Discards a transaction."),
+    command!("watch", -2, redis_watch, ["noscript", "fast"], (1, -1, 1), "transactions",
+        "Monitors changes to keys to determine the execution of a transaction."),
+    command!("unwatch", 1, redis_unwatch, ["noscript", "fast"], (0, 0, 0), "transactions",
+        "Forgets about watched keys of a transaction."),
+    command!("subscribe", -2, redis_subscribe, ["pubsub", "noscript"], (0, 0, 0), "pubsub",
+        "Listens for messages published to channels."),
+    command!("unsubscribe", -1, redis_unsubscribe, ["pubsub", "noscript"], (0, 0, 0), "pubsub",
+        "Stops listening to messages posted to channels."),
+    command!("psubscribe", -2, redis_psubscribe, ["pubsub", "noscript"], (0, 0, 0), "pubsub",
+        "Listens for messages published to channels that match one or more patterns."),
+    command!("punsubscribe", -1, redis_punsubscribe, ["pubsub", "noscript"], (0, 0, 0), "pubsub",
+        "Stops listening to messages published to channels that match one or more patterns."),
+    command!("publish", 3, redis_publish, ["pubsub", "fast"], (0, 0, 0), "pubsub",
+        "Posts a message to a channel."),
+    command!("pubsub", -2, redis_pubsub, ["pubsub", "random"], (0, 0, 0), "pubsub",
+        "Returns the active channels or the number of subscribers of channels."),
+    command!("eval", -3, redis_eval, ["noscript", "movablekeys"], (0, 0, 0), "scripting",
+        "Executes a server-side script."),
+    command!("client", -2, redis_client, ["admin", "noscript", "random"], (0, 0, 0), "connection",
+        "Inspects, names and kills connections."),
+    command!("config", -2, redis_config, ["admin", "noscript"], (0, 0, 0), "server",
+        "Returns or changes the configuration parameters."),
+    command!("info", -1, redis_info, ["random"], (0, 0, 0), "server",
+        "Returns information and statistics about the server."),
+    command!("command", -1, redis_command, ["random"], (0, 0, 0), "server",
+        "Returns detailed information about all commands."),
+];
+
+/// Maps the name of every command to its description.
+pub(crate) fn command_table() -> Index<Vec<u8>, &'static Command> {
+    let mut table = Index::with_capacity(COMMANDS.len() * 2);
+    for command in COMMANDS {
+        table.insert(command.name.as_bytes().to_vec(), command);
+    }
+    table
+}
+
+impl Command {
+    /// Returns `true` if the command has the flag `flag`.
+    pub(super) fn has_flag(&self, flag: &str) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    /// Returns `true` if the command can be called with `argc` arguments
+    /// (including its name).
+    pub(super) fn accepts(&self, argc: usize) -> bool {
+        let argc = argc as isize;
+        if self.arity < 0 {
+            argc >= -self.arity
+        } else {
+            argc == self.arity
+        }
+    }
+
+    /// Returns the keys in `args`, the arguments of a call of the command.
+    pub(super) fn keys<'a>(&self, args: &'a [Vec<u8>]) -> impl Iterator<Item = &'a Vec<u8>> {
+        let (first, last) = if self.has_flag("movablekeys") {
+            // EVAL is the only one: the keys follow their count
+            let numkeys = args.get(2).and_then(|n| parse_int(n)).unwrap_or(0);
+            (3, 2 + numkeys.max(0) as usize)
+        } else if self.first_key == 0 {
+            (1, 0)
+        } else if self.last_key < 0 {
+            let last = args.len() as isize + self.last_key;
+            (self.first_key, last.max(0) as usize)
+        } else {
+            (self.first_key, self.last_key as usize)
+        };
+        let last = last.min(args.len().saturating_sub(1));
+        let first = first.min(last + 1);
+        args[first..=last].iter().step_by(self.key_step.max(1))
+    }
+
+    /// The reply of COMMAND INFO for the command.
+    fn info(&self) -> Value {
+        Value::Array(vec![
+            Value::BufBulk(self.name.as_bytes().to_vec()),
+            Value::Integer(self.arity as i64),
+            Value::Array(
+                self.flags
+                    .iter()
+                    .map(|flag| Value::String(String::from(*flag)))
+                    .collect(),
+            ),
+            Value::Integer(self.first_key as i64),
+            Value::Integer(self.last_key as i64),
+            Value::Integer(self.key_step as i64),
+        ])
+    }
+
+    /// The documentation COMMAND DOCS reports for the command.
+    fn docs(&self) -> Value {
+        Value::Array(vec![
+            Value::BufBulk(b"summary".to_vec()),
+            Value::BufBulk(self.summary.as_bytes().to_vec()),
+            Value::BufBulk(b"group".to_vec()),
+            Value::BufBulk(self.group.as_bytes().to_vec()),
+        ])
+    }
+}
+
+impl SashStore {
+    /// COMMAND [COUNT | INFO [command ...] | DOCS [command ...] |
+    /// GETKEYS command [arg ...]]
+    pub(super) fn redis_command(&mut self, args: Args) -> Reply {
+        let subcommand = args.get(1).map(|s| s.to_ascii_uppercase());
+        match subcommand.as_deref() {
+            None => Ok(Value::Array(COMMANDS.iter().map(Command::info).collect())),
+            Some(b"COUNT") if args.len() == 2 => Ok(Value::Integer(COMMANDS.len() as i64)),
+            Some(b"INFO") if args.len() == 2 => {
+                Ok(Value::Array(COMMANDS.iter().map(Command::info).collect()))
+            }
+            Some(b"INFO") => Ok(Value::Array(
+                args[2..]
+                    .iter()
+                    .map(|name| self.command(name).map_or(Value::NullArray, Command::info))
+                    .collect(),
+            )),
+            Some(b"DOCS") => {
+                let commands: Vec<&Command> = if args.len() == 2 {
+                    COMMANDS.iter().collect()
+                } else {
+                    args[2..]
+                        .iter()
+                        .filter_map(|name| self.command(name))
+                        .collect()
+                };
+                let mut docs = Vec::with_capacity(commands.len() * 2);
+                for command in commands {
+                    docs.push(Value::BufBulk(command.name.as_bytes().to_vec()));
+                    docs.push(command.docs());
+                }
+                Ok(Value::Array(docs))
+            }
+            Some(b"GETKEYS") if args.len() > 2 => {
+                let command = self
+                    .command(&args[2])
+                    .ok_or(Value::StaticError("ERR Invalid command specified"))?;
+                if !command.accepts(args.len() - 2) {
+                    return Err(Value::StaticError(
+                        "ERR Invalid number of arguments specified for command",
+                    ));
+                }
+                let keys: Vec<Value> = command
+                    .keys(&args[2..])
+                    .map(|key| Value::BufBulk(key.clone()))
+                    .collect();
+                if keys.is_empty() {
+                    return Err(Value::StaticError("ERR The command has no key arguments"));
+                }
+                Ok(Value::Array(keys))
+            }
+            _ => Err(Value::Error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try COMMAND HELP.",
+                String::from_utf8_lossy(&args[1])
+            ))),
+        }
+    }
+
+    /// Looks up the command called `name` (in any case).
+    pub(super) fn command(&self, name: &[u8]) -> Option<&'static Command> {
+        self.commands
+            .get(name.to_ascii_lowercase().as_slice())
+            .copied()
+    }
+}
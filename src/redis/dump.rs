@@ -0,0 +1,338 @@
+//! DUMP and RESTORE: serializing the value of a single key, used to move
+//! keys between servers.
+//!
+//! A payload is the serialized value followed by the format version (two
+//! bytes) and a CRC-64 checksum (eight bytes, the Jones variant Redis uses)
+//! of everything before it, both little endian. A value starts with its
+//! type (one of the `TYPE_*` bytes), lengths are LEB128 varints and scores
+//! IEEE 754 doubles:
+//!
+//! * string: length, bytes
+//! * list and set: number of elements, then every element as a string
+//! * sorted set: number of members, then every member as a string and its
+//!   score
+//! * hash: number of fields, then every field and its value as strings
+//!
+//! RESTORE refuses payloads written by a newer `DUMP_VERSION`.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use super::zset::SortedSet;
+use super::{ok, parse_int, Args, Reply, ERR_NOT_INTEGER, ERR_SYNTAX};
+use crate::indexmap::hash::HashFunction;
+use crate::indexmap::Index;
+use crate::resp::value::Value;
+use crate::{Data, Item, SashStore};
+
+/// Version of the payload format written by DUMP.
+const DUMP_VERSION: u16 = 1;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+
+/// Reflected form of the CRC-64/Jones polynomial `0xad93d23594c935a9`.
+const CRC64_POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+impl SashStore {
+    /// DUMP key
+    pub(super) fn redis_dump(&mut self, args: Args) -> Reply {
+        Ok(match self.lookup(&args[1]) {
+            Some(item) => Value::BufBulk(dump(&item.value)),
+            None => Value::Null,
+        })
+    }
+
+    /// RESTORE key ttl serialized-value [REPLACE] [ABSTTL] [IDLETIME seconds]
+    /// [FREQ frequency]
+    pub(super) fn redis_restore(&mut self, mut args: Args) -> Reply {
+        let ttl = match parse_int(&args[2]) {
+            Some(ttl) if ttl >= 0 => ttl as u64,
+            Some(_) => return Err(Value::StaticError("ERR Invalid TTL value, must be >= 0")),
+            None => return Err(Value::StaticError(ERR_NOT_INTEGER)),
+        };
+
+        let (mut replace, mut absolute, mut idle, mut freq) = (false, false, None, None);
+        let mut i = 4;
+        while i < args.len() {
+            match args[i].to_ascii_uppercase().as_slice() {
+                b"REPLACE" => replace = true,
+                b"ABSTTL" => absolute = true,
+                b"IDLETIME" if i + 1 < args.len() => {
+                    idle = match parse_int(&args[i + 1]) {
+                        Some(idle) if idle >= 0 => Some(idle as u64),
+                        _ => {
+                            return Err(Value::StaticError(
+                                "ERR Invalid IDLETIME value, must be >= 0",
+                            ))
+                        }
+                    };
+                    i += 1;
+                }
+                b"FREQ" if i + 1 < args.len() => {
+                    freq = match parse_int(&args[i + 1]) {
+                        Some(freq) if (0..=255).contains(&freq) => Some(freq as u8),
+                        _ => {
+                            return Err(Value::StaticError(
+                                "ERR Invalid FREQ value, must be >= 0 and <= 255",
+                            ))
+                        }
+                    };
+                    i += 1;
+                }
+                _ => return Err(Value::StaticError(ERR_SYNTAX)),
+            }
+            i += 1;
+        }
+
+        if !replace && self.lookup(&args[1]).is_some() {
+            return Err(Value::StaticError(
+                "BUSYKEY Target key name already exists.",
+            ));
+        }
+        let body = verify(&args[3]).ok_or(Value::StaticError(
+            "ERR DUMP payload version or checksum are wrong",
+        ))?;
+        let value =
+            restore(body, self.config.hasher).ok_or(Value::StaticError("ERR Bad data format"))?;
+
+        let now = self.clock_ms;
+        let expires_at = match ttl {
+            0 => None,
+            ttl if absolute => Some(ttl),
+            ttl => Some(now.saturating_add(ttl)),
+        };
+        args.truncate(2);
+        let key = args.pop().unwrap();
+        if expires_at.map_or(false, |deadline| deadline <= now) {
+            // Already expired, like restoring and expiring it right away
+            self.remove(&key);
+            return Ok(ok());
+        }
+
+        self.insert_waking(
+            key.clone(),
+            Item {
+                expires_at,
+                ..Item::with_data(0, value)
+            },
+        );
+        if let Some(item) = self.map.get(&key) {
+            if let Some(idle) = idle {
+                item.accessed_at.set(now.saturating_sub(idle * 1000));
+            }
+            if let Some(freq) = freq {
+                item.freq.set(freq);
+            }
+        }
+        Ok(ok())
+    }
+}
+
+/// Serializes `value` into a DUMP payload.
+fn dump(value: &Data) -> Vec<u8> {
+    let mut buf = Vec::new();
+    match value {
+        Data::String(s) => {
+            buf.push(TYPE_STRING);
+            put_bytes(&mut buf, s);
+        }
+        Data::List(list) => {
+            buf.push(TYPE_LIST);
+            put_len(&mut buf, list.len());
+            for element in list {
+                put_bytes(&mut buf, element);
+            }
+        }
+        Data::Set(set) => {
+            buf.push(TYPE_SET);
+            put_len(&mut buf, set.len());
+            for member in set.keys() {
+                put_bytes(&mut buf, member);
+            }
+        }
+        Data::SortedSet(zset) => {
+            buf.push(TYPE_ZSET);
+            put_len(&mut buf, zset.len());
+            for (score, member) in zset.iter() {
+                put_bytes(&mut buf, member);
+                buf.extend_from_slice(&score.to_bits().to_le_bytes());
+            }
+        }
+        Data::Hash(hash) => {
+            buf.push(TYPE_HASH);
+            put_len(&mut buf, hash.len());
+            for pair in hash.iter() {
+                put_bytes(&mut buf, pair.0);
+                put_bytes(&mut buf, pair.1);
+            }
+        }
+    }
+    buf.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+    let checksum = crc64(&buf);
+    buf.extend_from_slice(&checksum.to_le_bytes());
+    buf
+}
+
+/// Checks the version and checksum of a DUMP payload, returns the
+/// serialized value.
+fn verify(payload: &[u8]) -> Option<&[u8]> {
+    if payload.len() < 10 {
+        return None;
+    }
+    let (data, checksum) = payload.split_at(payload.len() - 8);
+    let mut expected = [0u8; 8];
+    expected.copy_from_slice(checksum);
+    if crc64(data) != u64::from_le_bytes(expected) {
+        return None;
+    }
+    let (body, version) = data.split_at(data.len() - 2);
+    if u16::from_le_bytes([version[0], version[1]]) > DUMP_VERSION {
+        return None;
+    }
+    Some(body)
+}
+
+/// Deserializes a value written by `dump`, sets, sorted sets and hashes
+/// hash their elements with `hasher`.
+///
+/// Returns `None` if `body` is malformed, this includes empty lists, sets,
+/// sorted sets and hashes (we never store those) and NaN scores.
+fn restore(body: &[u8], hasher: HashFunction) -> Option<Data> {
+    let mut reader = Reader { buf: body };
+    let value = match reader.byte()? {
+        TYPE_STRING => Data::String(reader.bytes()?),
+        TYPE_LIST => {
+            let mut list = VecDeque::new();
+            for _ in 0..reader.count()? {
+                list.push_back(reader.bytes()?);
+            }
+            Data::List(list)
+        }
+        TYPE_SET => {
+            let mut set = Index::with_hasher(hasher);
+            for _ in 0..reader.count()? {
+                set.insert(reader.bytes()?, ());
+            }
+            Data::Set(set)
+        }
+        TYPE_ZSET => {
+            let mut zset = SortedSet::new(hasher);
+            for _ in 0..reader.count()? {
+                let member = reader.bytes()?;
+                zset.insert(member, reader.score()?);
+            }
+            Data::SortedSet(zset)
+        }
+        TYPE_HASH => {
+            let mut hash = Index::with_hasher(hasher);
+            for _ in 0..reader.count()? {
+                let field = reader.bytes()?;
+                hash.insert(field, reader.bytes()?);
+            }
+            Data::Hash(hash)
+        }
+        _ => return None,
+    };
+    if reader.buf.is_empty() {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Appends `len` as a LEB128 varint.
+fn put_len(buf: &mut Vec<u8>, mut len: usize) {
+    while len >= 0x80 {
+        buf.push((len as u8) | 0x80);
+        len >>= 7;
+    }
+    buf.push(len as u8);
+}
+
+/// Appends a length prefixed byte string.
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_len(buf, bytes.len());
+    buf.extend_from_slice(bytes);
+}
+
+/// Reads the parts of a serialized value, every method returns `None` if
+/// the input ends too early or is invalid.
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.buf.split_first()?;
+        self.buf = rest;
+        Some(byte)
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        let mut len: usize = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            len |= ((byte & 0x7f) as usize).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(len);
+            }
+        }
+        None
+    }
+
+    /// Number of elements of a list, set, sorted set or hash (not zero).
+    fn count(&mut self) -> Option<usize> {
+        self.len().filter(|&count| count > 0)
+    }
+
+    fn bytes(&mut self) -> Option<Vec<u8>> {
+        let len = self.len()?;
+        if len > self.buf.len() {
+            return None;
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Some(bytes.to_vec())
+    }
+
+    fn score(&mut self) -> Option<f64> {
+        if self.buf.len() < 8 {
+            return None;
+        }
+        let mut bits = [0u8; 8];
+        bits.copy_from_slice(&self.buf[..8]);
+        self.buf = &self.buf[8..];
+        Some(f64::from_bits(u64::from_le_bytes(bits))).filter(|score| !score.is_nan())
+    }
+}
+
+/// CRC-64/Jones of `bytes`, the checksum Redis uses for DUMP payloads.
+fn crc64(bytes: &[u8]) -> u64 {
+    let mut crc = 0u64;
+    for &byte in bytes {
+        crc ^= u64::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ CRC64_POLY
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crc64;
+
+    #[test]
+    fn crc64_check_value() {
+        // Same test vector as in the Redis sources
+        assert_eq!(crc64(b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+    }
+}
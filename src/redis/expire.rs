@@ -0,0 +1,222 @@
+//! Key expiry: the EXPIRE/TTL command family and the active expiry cycle.
+//!
+//! Deadlines are stored as unix time in milliseconds in `Item::expires_at`.
+//! Reads never return an expired item (see `SashStore::lookup`), the memory
+//! is reclaimed either when the key is written again or by
+//! `SashStore::active_expire_cycle` which the server loop calls periodically.
+
+use alloc::format;
+use alloc::string::String;
+use core::sync::atomic::Ordering;
+
+use super::{parse_int, Args, Reply, ERR_NOT_INTEGER};
+use crate::arch::arch::unix_time_ms;
+use crate::resp::value::Value;
+use crate::SashStore;
+
+/// Number of keys with a deadline we look at per round of the expiry cycle.
+const ACTIVE_EXPIRE_KEYS_PER_ROUND: usize = 20;
+
+/// Maximum number of buckets visited per round (the table may be sparse).
+const ACTIVE_EXPIRE_BUCKETS_PER_ROUND: usize = 400;
+
+/// Maximum number of rounds per cycle, bounds the time spent in the cycle
+/// if lots of keys expire at the same time.
+const ACTIVE_EXPIRE_MAX_ROUNDS: usize = 16;
+
+/// Steps (see `Index::migrate`) of an incremental resize of the keyspace
+/// taken per cycle, on top of the one every command takes.
+const ACTIVE_MIGRATE_STEPS: usize = 256;
+
+/// How a user supplied expire time is to be interpreted.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(super) enum ExpireTime {
+    /// Relative, in seconds (EX, EXPIRE)
+    Seconds,
+    /// Relative, in milliseconds (PX, PEXPIRE)
+    Millis,
+    /// Absolute unix time in seconds (EXAT, EXPIREAT)
+    UnixSeconds,
+    /// Absolute unix time in milliseconds (PXAT, PEXPIREAT)
+    UnixMillis,
+}
+
+impl ExpireTime {
+    /// Maps a SET option (`EX`, `PX`, `EXAT` or `PXAT`) to its unit.
+    pub(super) fn from_option(option: &[u8]) -> ExpireTime {
+        match option {
+            b"EX" => ExpireTime::Seconds,
+            b"PX" => ExpireTime::Millis,
+            b"EXAT" => ExpireTime::UnixSeconds,
+            b"PXAT" => ExpireTime::UnixMillis,
+            _ => unreachable!("not an expire option"),
+        }
+    }
+
+    /// Converts `time` to an absolute deadline in unix milliseconds.
+    ///
+    /// Returns `None` if the computation overflows.
+    pub(super) fn deadline(self, time: i64, now: u64) -> Option<i64> {
+        match self {
+            ExpireTime::Seconds => time.checked_mul(1000)?.checked_add(now as i64),
+            ExpireTime::Millis => time.checked_add(now as i64),
+            ExpireTime::UnixSeconds => time.checked_mul(1000),
+            ExpireTime::UnixMillis => Some(time),
+        }
+    }
+}
+
+impl SashStore {
+    /// Reclaims expired keys, similar to the active expiry cycle of Redis.
+    ///
+    /// Walks the table from where the last cycle stopped and removes expired
+    /// items among the next `ACTIVE_EXPIRE_KEYS_PER_ROUND` keys with a
+    /// deadline. Another round follows as long as at least a quarter of
+    /// the sampled keys were expired. Afterwards the keyspace shrinks if
+    /// it got emptier than `min-load`.
+    ///
+    /// The cycle also moves an incremental resize of the keyspace along,
+    /// so that it finishes on a partition that gets few commands.
+    ///
+    /// Returns the number of removed keys.
+    pub fn active_expire_cycle(&mut self) -> usize {
+        self.clock_ms = unix_time_ms();
+        let mut reclaimed = 0;
+
+        for _ in 0..ACTIVE_MIGRATE_STEPS {
+            if !self.map.is_resizing() {
+                break;
+            }
+            self.map.migrate();
+        }
+
+        for _round in 0..ACTIVE_EXPIRE_MAX_ROUNDS {
+            let buckets = self.map.buckets();
+            let (mut sampled, mut expired) = (0, 0);
+
+            for _ in 0..ACTIVE_EXPIRE_BUCKETS_PER_ROUND.min(buckets) {
+                let i = self.expire_cursor % buckets;
+                self.expire_cursor = i + 1;
+
+                let (has_deadline, is_expired) = match self.map.get_at(i) {
+                    Some(pair) => (
+                        pair.1.expires_at.is_some(),
+                        pair.1.is_expired(self.clock_ms),
+                    ),
+                    None => (false, false),
+                };
+                if has_deadline {
+                    sampled += 1;
+                    if is_expired {
+                        if let Some((_, item)) = self.map.remove_at(i) {
+                            self.count_deadline(item.expires_at, None);
+                        }
+                        expired += 1;
+                    }
+                    if sampled == ACTIVE_EXPIRE_KEYS_PER_ROUND {
+                        break;
+                    }
+                }
+            }
+
+            reclaimed += expired;
+            if sampled == 0 || expired * 4 < sampled {
+                break;
+            }
+        }
+        // Walking by bucket never shrinks the keyspace, catch up after
+        if reclaimed > 0 {
+            self.map.shrink_if_underloaded();
+        }
+
+        self.stats()
+            .expired_keys
+            .fetch_add(reclaimed as u64, Ordering::Relaxed);
+        reclaimed
+    }
+
+    /// EXPIRE key seconds
+    pub(super) fn redis_expire(&mut self, args: Args) -> Reply {
+        self.expire(args, ExpireTime::Seconds)
+    }
+
+    /// PEXPIRE key milliseconds
+    pub(super) fn redis_pexpire(&mut self, args: Args) -> Reply {
+        self.expire(args, ExpireTime::Millis)
+    }
+
+    /// EXPIREAT key unix-time-seconds
+    pub(super) fn redis_expireat(&mut self, args: Args) -> Reply {
+        self.expire(args, ExpireTime::UnixSeconds)
+    }
+
+    /// PEXPIREAT key unix-time-milliseconds
+    pub(super) fn redis_pexpireat(&mut self, args: Args) -> Reply {
+        self.expire(args, ExpireTime::UnixMillis)
+    }
+
+    /// TTL key
+    pub(super) fn redis_ttl(&mut self, args: Args) -> Reply {
+        match self.ttl_ms(&args[1]) {
+            ttl if ttl < 0 => Ok(Value::Integer(ttl)),
+            ttl => Ok(Value::Integer((ttl + 500) / 1000)),
+        }
+    }
+
+    /// PTTL key
+    pub(super) fn redis_pttl(&mut self, args: Args) -> Reply {
+        Ok(Value::Integer(self.ttl_ms(&args[1])))
+    }
+
+    /// PERSIST key
+    pub(super) fn redis_persist(&mut self, args: Args) -> Reply {
+        match self.lookup_mut(&args[1]) {
+            Some(item) if item.expires_at.is_some() => {
+                let old = item.expires_at.take();
+                self.count_deadline(old, None);
+                Ok(Value::Integer(1))
+            }
+            _ => Ok(Value::Integer(0)),
+        }
+    }
+
+    /// Sets the deadline of the key in `args[1]` to `args[2]` (interpreted
+    /// according to `unit`), a deadline in the past deletes the key.
+    fn expire(&mut self, args: Args, unit: ExpireTime) -> Reply {
+        let time = parse_int(&args[2]).ok_or(Value::StaticError(ERR_NOT_INTEGER))?;
+        let deadline = match unit.deadline(time, self.clock_ms) {
+            Some(deadline) => deadline,
+            None => {
+                return Err(Value::Error(format!(
+                    "ERR invalid expire time in '{}' command",
+                    String::from_utf8_lossy(&args[0]).to_lowercase()
+                )))
+            }
+        };
+
+        let in_past = deadline <= self.clock_ms as i64;
+        match self.lookup_mut(&args[1]) {
+            Some(item) if !in_past => {
+                let old = item.expires_at.replace(deadline as u64);
+                self.count_deadline(old, Some(deadline as u64));
+                return Ok(Value::Integer(1));
+            }
+            Some(_) => {}
+            None => return Ok(Value::Integer(0)),
+        }
+        self.remove(&args[1]);
+        Ok(Value::Integer(1))
+    }
+
+    /// Remaining time to live of `key` in milliseconds, -2 if the key doesn't
+    /// exist and -1 if it doesn't have a deadline.
+    fn ttl_ms(&self, key: &[u8]) -> i64 {
+        match self.lookup(key) {
+            Some(item) => match item.expires_at {
+                Some(deadline) => (deadline - self.clock_ms) as i64,
+                None => -1,
+            },
+            None => -2,
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! Formatting of INCRBYFLOAT results the way Redis does it.
+//!
+//! Redis adds `long double`s and prints the sum with `%.17Lf`, then strips
+//! trailing zeros and the decimal point. The output is never in scientific
+//! notation, and the extra precision of `long double` hides the rounding
+//! error of the addition: `0.1 + 0.2` is `0.3`, not the
+//! `0.30000000000000004` of `f64`. We get the same by adding the decimal
+//! digits of the operands exactly and rounding the sum to 17 digits after
+//! the decimal point. If the sum doesn't fit we use the shortest digits of
+//! the `f64` sum instead, which can differ from Redis in the digits beyond
+//! the precision of `long double`.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+/// Number of digits after the decimal point of `%.17Lf`.
+const FRACTION_DIGITS: u32 = 17;
+
+/// Significant digits of an operand beyond 37 are dropped when parsing,
+/// `long double` only has about 19.
+const MAX_DIGITS: u128 = 10u128.pow(37);
+
+/// A decimal number, `digits * 10^exponent` (negated if `negative`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Decimal {
+    negative: bool,
+    digits: u128,
+    exponent: i32,
+}
+
+impl Decimal {
+    /// Parses a number like `-12.5e3`, `None` if it isn't written like that
+    /// (`inf`, hex floats...). Digits past the 37th significant one are
+    /// dropped.
+    fn parse(bytes: &[u8]) -> Option<Decimal> {
+        let (negative, rest) = match bytes.first() {
+            Some(b'-') => (true, &bytes[1..]),
+            Some(b'+') => (false, &bytes[1..]),
+            _ => (false, bytes),
+        };
+        let (mantissa, exponent) = match rest.iter().position(|&c| c == b'e' || c == b'E') {
+            Some(e) => (&rest[..e], parse_exponent(&rest[e + 1..])?),
+            None => (rest, 0),
+        };
+
+        let mut decimal = Decimal {
+            negative,
+            digits: 0,
+            exponent,
+        };
+        let (mut seen_digit, mut seen_point) = (false, false);
+        for &c in mantissa {
+            match c {
+                b'0'..=b'9' if decimal.digits >= MAX_DIGITS => {
+                    seen_digit = true;
+                    if !seen_point {
+                        decimal.exponent += 1;
+                    }
+                }
+                b'0'..=b'9' => {
+                    seen_digit = true;
+                    decimal.digits = decimal.digits * 10 + u128::from(c - b'0');
+                    if seen_point {
+                        decimal.exponent -= 1;
+                    }
+                }
+                b'.' if !seen_point => seen_point = true,
+                _ => return None,
+            }
+        }
+        if seen_digit {
+            Some(decimal)
+        } else {
+            None
+        }
+    }
+
+    /// The shortest digits that round-trip to `value`, what `{:e}` prints.
+    fn from_f64(value: f64) -> Decimal {
+        let formatted = format!("{:e}", value);
+        Decimal::parse(formatted.as_bytes()).unwrap()
+    }
+
+    /// The exact sum, `None` if it doesn't fit.
+    fn checked_add(self, other: Decimal) -> Option<Decimal> {
+        let exponent = self.exponent.min(other.exponent);
+        let scaled = |d: Decimal| -> Option<i128> {
+            let shift = u32::try_from(d.exponent - exponent).ok()?;
+            let digits = i128::try_from(d.digits)
+                .ok()?
+                .checked_mul(10i128.checked_pow(shift)?)?;
+            Some(if d.negative { -digits } else { digits })
+        };
+        let sum = scaled(self)?.checked_add(scaled(other)?)?;
+        Some(Decimal {
+            negative: sum < 0,
+            digits: sum.unsigned_abs(),
+            exponent,
+        })
+    }
+
+    /// Formats the number like `%.17Lf` followed by stripping trailing
+    /// zeros and the decimal point, `-0` becomes `0` like in Redis.
+    fn to_bytes(mut self) -> Vec<u8> {
+        self.round_to_fraction(FRACTION_DIGITS);
+        if self.digits == 0 {
+            return b"0".to_vec();
+        }
+        while self.exponent < 0 && self.digits % 10 == 0 {
+            self.digits /= 10;
+            self.exponent += 1;
+        }
+
+        let digits = format!("{}", self.digits);
+        let mut out = String::new();
+        if self.negative {
+            out.push('-');
+        }
+        if self.exponent >= 0 {
+            out.push_str(&digits);
+            out.extend((0..self.exponent).map(|_| '0'));
+        } else {
+            let fraction = -self.exponent as usize;
+            if digits.len() > fraction {
+                let point = digits.len() - fraction;
+                out.push_str(&digits[..point]);
+                out.push('.');
+                out.push_str(&digits[point..]);
+            } else {
+                out.push_str("0.");
+                out.extend((digits.len()..fraction).map(|_| '0'));
+                out.push_str(&digits);
+            }
+        }
+        out.into_bytes()
+    }
+
+    /// Rounds to at most `places` digits after the decimal point, half to
+    /// even.
+    fn round_to_fraction(&mut self, places: u32) {
+        let exponent = -(places as i32);
+        if self.exponent >= exponent {
+            return;
+        }
+        let dropped = (exponent - self.exponent) as u32;
+        self.exponent = exponent;
+        // Any u128 is below half of 10^39 and rounds to 0
+        if dropped >= 39 {
+            self.digits = 0;
+            return;
+        }
+        let scale = 10u128.pow(dropped);
+        let (digits, rest) = (self.digits / scale, self.digits % scale);
+        self.digits = digits;
+        if rest > scale / 2 || (rest == scale / 2 && digits % 2 == 1) {
+            self.digits += 1;
+        }
+    }
+}
+
+/// Parses the exponent of a number, small enough that we don't have to
+/// worry about overflows.
+fn parse_exponent(bytes: &[u8]) -> Option<i32> {
+    let exponent: i32 = core::str::from_utf8(bytes).ok()?.parse().ok()?;
+    if exponent.abs() <= 400 {
+        Some(exponent)
+    } else {
+        None
+    }
+}
+
+/// Formats the sum of the numbers `a` and `b` like Redis, `sum` is their
+/// sum as `f64`.
+pub(super) fn format_sum(a: &[u8], b: &[u8], sum: f64) -> Vec<u8> {
+    let exact = match (Decimal::parse(a), Decimal::parse(b)) {
+        (Some(a), Some(b)) => a.checked_add(b),
+        _ => None,
+    };
+    exact.unwrap_or_else(|| Decimal::from_f64(sum)).to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_sum;
+
+    fn sum(a: &str, b: &str) -> String {
+        let float = a.parse::<f64>().unwrap() + b.parse::<f64>().unwrap();
+        String::from_utf8(format_sum(a.as_bytes(), b.as_bytes(), float)).unwrap()
+    }
+
+    #[test]
+    fn formats_like_redis() {
+        assert_eq!(sum("0.1", "0.2"), "0.3");
+        assert_eq!(sum("10.50", "0.1"), "10.6");
+        assert_eq!(sum("5.0e3", "200"), "5200");
+        assert_eq!(sum("3", "-3"), "0");
+        assert_eq!(sum("-7", "0.5"), "-6.5");
+        assert_eq!(sum("1e20", "0"), "100000000000000000000");
+        assert_eq!(sum("0.00001", "0"), "0.00001");
+        assert_eq!(sum("1", "0.00000000000000001"), "1.00000000000000001");
+        assert_eq!(sum("12345678901234567890", "0"), "12345678901234567890");
+        assert_eq!(sum("0.3333333333333333333", "0"), "0.33333333333333333");
+        // Rounded to 17 digits after the point
+        assert_eq!(sum("1e-18", "0"), "0");
+        assert_eq!(sum("-1e-18", "0"), "0");
+        assert_eq!(sum("0.000000000000000015", "0"), "0.00000000000000002");
+        // Digits past the precision of `long double` are dropped
+        assert_eq!(
+            sum("0.1000000000000000000000000000000000000000001", "0.2"),
+            "0.3"
+        );
+        // Too far apart to add exactly, the f64 sum is used
+        assert_eq!(sum("1e300", "1"), format!("1{}", "0".repeat(300)));
+    }
+}
@@ -0,0 +1,111 @@
+//! Glob-style pattern matching as used by the `MATCH` option of the
+//! SCAN command family (and `KEYS`).
+//!
+//! Supported syntax (same as Redis):
+//!
+//! * `*` matches any sequence of bytes, including none
+//! * `?` matches exactly one byte
+//! * `[abc]`, `[a-z]` match one byte of a set, `[^abc]` one byte not in it
+//! * `\x` matches `x` literally
+
+/// Returns `true` if `string` matches the glob `pattern`.
+pub(super) fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    // Position in pattern/string to resume from if the most recent `*`
+    // has to swallow one more byte.
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut s) = (0, 0);
+
+    while s < string.len() {
+        let matched = match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, s));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match_class(pattern, p, string[s]),
+            Some(b'\\') if p + 1 < pattern.len() && pattern[p + 1] == string[s] => Some(p + 2),
+            Some(b'\\') if p + 1 < pattern.len() => None,
+            Some(&c) if c == string[s] => Some(p + 1),
+            _ => None,
+        };
+
+        match (matched, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                s += 1;
+            }
+            (None, Some((star, from))) => {
+                p = star + 1;
+                s = from + 1;
+                backtrack = Some((star, from + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    // Only stars may be left in the pattern
+    pattern[p.min(pattern.len())..].iter().all(|&c| c == b'*')
+}
+
+/// Matches `c` against the character class starting at `pattern[start]`
+/// (the opening `[`).
+///
+/// Returns the index right after the class on success. An unterminated
+/// class extends to the end of the pattern, like in Redis.
+fn match_class(pattern: &[u8], start: usize, c: u8) -> Option<usize> {
+    let mut i = start + 1;
+    let negate = pattern.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+
+    let mut found = false;
+    while i < pattern.len() && pattern[i] != b']' {
+        if pattern[i] == b'\\' && i + 1 < pattern.len() {
+            found |= pattern[i + 1] == c;
+            i += 2;
+        } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+            let (lo, hi) = (
+                pattern[i].min(pattern[i + 2]),
+                pattern[i].max(pattern[i + 2]),
+            );
+            found |= lo <= c && c <= hi;
+            i += 3;
+        } else {
+            found |= pattern[i] == c;
+            i += 1;
+        }
+    }
+
+    if found != negate {
+        Some((i + 1).min(pattern.len()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    #[test]
+    fn patterns() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+        assert!(glob_match(b"h*llo", b"heeeello"));
+        assert!(glob_match(b"h*llo", b"hllo"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[ae]llo", b"hillo"));
+        assert!(glob_match(b"h[^e]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(glob_match(b"field:*:name", b"field:1:2:name"));
+        assert!(!glob_match(b"field:*:name", b"field:1:2:nam"));
+        assert!(glob_match(b"a\\*b", b"a*b"));
+        assert!(!glob_match(b"a\\*b", b"axb"));
+        assert!(glob_match(b"*a*b*", b"xxaxxbxx"));
+        assert!(!glob_match(b"abc", b"ab"));
+    }
+}
@@ -0,0 +1,190 @@
+//! Redis hash commands.
+//!
+//! A hash is stored as `Data::Hash`, a nested `Index` mapping fields to
+//! values. Like in Redis, a hash is deleted as soon as its last field is
+//! removed so commands never observe an empty one.
+
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::scan::{parse_cursor, parse_scan_options, scan_index};
+use super::{parse_int, Args, Reply, ERR_NOT_INTEGER};
+use crate::indexmap::hash::HashFunction;
+use crate::indexmap::{Bucket, Index};
+use crate::resp::value::Value;
+use crate::{Data, Item, SashStore};
+
+/// A hash maps fields to values.
+type Hash = Index<Vec<u8>, Vec<u8>, HashFunction>;
+
+impl SashStore {
+    /// HSET key field value [field value ...]
+    pub(super) fn redis_hset(&mut self, args: Args) -> Reply {
+        if args.len() % 2 != 0 {
+            return Err(super::wrong_arity(&args));
+        }
+        let mut args = args.into_iter().skip(1);
+        let key = args.next().unwrap();
+        let hash = self.hash_or_insert(key)?;
+
+        let mut added = 0;
+        while let (Some(field), Some(value)) = (args.next(), args.next()) {
+            if let Bucket::Empty = hash.insert(field, value) {
+                added += 1;
+            }
+        }
+        Ok(Value::Integer(added))
+    }
+
+    /// HGET key field
+    pub(super) fn redis_hget(&mut self, args: Args) -> Reply {
+        let hash = match self.lookup_as(&args[1], Data::as_hash)? {
+            Some(hash) => hash,
+            None => return Ok(Value::Null),
+        };
+        let value = hash.get(&args[2]);
+        Ok(value.map_or(Value::Null, |value| Value::BufBulk(value.clone())))
+    }
+
+    /// HMGET key field [field ...]
+    pub(super) fn redis_hmget(&mut self, args: Args) -> Reply {
+        let hash = self.lookup_as(&args[1], Data::as_hash)?;
+        Ok(Value::Array(
+            args[2..]
+                .iter()
+                .map(
+                    |field| match hash.as_ref().and_then(|hash| hash.get(field)) {
+                        Some(value) => Value::BufBulk(value.clone()),
+                        None => Value::Null,
+                    },
+                )
+                .collect(),
+        ))
+    }
+
+    /// HDEL key field [field ...]
+    pub(super) fn redis_hdel(&mut self, args: Args) -> Reply {
+        let (removed, now_empty) = match self.lookup_as_mut(&args[1], Data::as_hash_mut)? {
+            Some(hash) => {
+                let removed = args[2..]
+                    .iter()
+                    .filter(|field| hash.remove(*field).is_some())
+                    .count();
+                (removed, hash.is_empty())
+            }
+            None => return Ok(Value::Integer(0)),
+        };
+        if now_empty {
+            self.remove(&args[1]);
+        }
+        Ok(Value::Integer(removed as i64))
+    }
+
+    /// HEXISTS key field
+    pub(super) fn redis_hexists(&mut self, args: Args) -> Reply {
+        let hash = self.lookup_as(&args[1], Data::as_hash)?;
+        let exists = hash.map_or(false, |hash| hash.get(&args[2]).is_some());
+        Ok(Value::Integer(exists as i64))
+    }
+
+    /// HLEN key
+    pub(super) fn redis_hlen(&mut self, args: Args) -> Reply {
+        let hash = self.lookup_as(&args[1], Data::as_hash)?;
+        Ok(Value::Integer(hash.map_or(0, |hash| hash.len()) as i64))
+    }
+
+    /// HKEYS key
+    pub(super) fn redis_hkeys(&mut self, args: Args) -> Reply {
+        self.hash_reply(&args[1], |field, _value, reply| {
+            reply.push(Value::BufBulk(field.clone()))
+        })
+    }
+
+    /// HVALS key
+    pub(super) fn redis_hvals(&mut self, args: Args) -> Reply {
+        self.hash_reply(&args[1], |_field, value, reply| {
+            reply.push(Value::BufBulk(value.clone()))
+        })
+    }
+
+    /// HGETALL key
+    pub(super) fn redis_hgetall(&mut self, args: Args) -> Reply {
+        self.hash_reply(&args[1], |field, value, reply| {
+            reply.push(Value::BufBulk(field.clone()));
+            reply.push(Value::BufBulk(value.clone()));
+        })
+    }
+
+    /// HINCRBY key field increment
+    pub(super) fn redis_hincrby(&mut self, mut args: Args) -> Reply {
+        let increment = parse_int(&args[3]).ok_or(Value::StaticError(ERR_NOT_INTEGER))?;
+        let field = args.swap_remove(2);
+        let hash = self.hash_or_insert(args.swap_remove(1))?;
+
+        let new = match hash.get(&field) {
+            Some(value) => parse_int(value)
+                .ok_or(Value::StaticError("ERR hash value is not an integer"))?
+                .checked_add(increment)
+                .ok_or(Value::StaticError(
+                    "ERR increment or decrement would overflow",
+                ))?,
+            None => increment,
+        };
+        hash.insert(field, format!("{}", new).into_bytes());
+        Ok(Value::Integer(new))
+    }
+
+    /// HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]
+    ///
+    /// Walks the buckets of the hash like SCAN walks the key space.
+    pub(super) fn redis_hscan(&mut self, args: Args) -> Reply {
+        let cursor = parse_cursor(&args[2])?;
+        let scan = parse_scan_options(&args[3..])?;
+
+        let mut items = Vec::new();
+        let cursor = match self.lookup_as(&args[1], Data::as_hash)? {
+            Some(hash) => scan_index(hash, cursor, scan.count, |field, value| {
+                if scan.matches(field) {
+                    items.push(Value::BufBulk(field.clone()));
+                    if !scan.novalues {
+                        items.push(Value::BufBulk(value.clone()));
+                    }
+                }
+                true
+            }),
+            None => 0,
+        };
+        Ok(Value::Array(vec![
+            Value::BufBulk(format!("{}", cursor).into_bytes()),
+            Value::Array(items),
+        ]))
+    }
+
+    /// Builds an array reply by calling `f` on every field of the hash
+    /// stored at `key`, a missing key is an empty hash.
+    fn hash_reply<F>(&self, key: &[u8], mut f: F) -> Reply
+    where
+        F: FnMut(&Vec<u8>, &Vec<u8>, &mut Vec<Value>),
+    {
+        let mut reply = Vec::new();
+        if let Some(hash) = self.lookup_as(key, Data::as_hash)? {
+            for pair in hash.iter() {
+                f(pair.0, pair.1, &mut reply);
+            }
+        }
+        Ok(Value::Array(reply))
+    }
+
+    /// Returns the hash stored at `key`, creating an empty one if the key
+    /// doesn't exist.
+    fn hash_or_insert(&mut self, key: Vec<u8>) -> Result<&mut Hash, Value> {
+        if self.lookup_as(&key, Data::as_hash)?.is_none() {
+            self.insert(
+                key.clone(),
+                Item::with_data(0, Data::Hash(Index::with_hasher(self.config.hasher))),
+            );
+        }
+        Ok(self.lookup_as_mut(&key, Data::as_hash_mut)?.unwrap())
+    }
+}
@@ -0,0 +1,161 @@
+//! Generic commands that work on keys of any type: DEL, UNLINK, RENAME,
+//! RENAMENX, COPY, TOUCH and OBJECT.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::{ok, parse_int, Args, Reply, ERR_NOT_INTEGER, ERR_SYNTAX};
+use crate::resp::value::Value;
+use crate::{Data, Item, SashStore};
+
+impl SashStore {
+    /// DEL key [key ...]
+    ///
+    /// Also serves UNLINK, freeing a value right away is cheap enough for
+    /// us.
+    pub(super) fn redis_del(&mut self, args: Args) -> Reply {
+        let now = self.clock_ms;
+        let deleted = args[1..]
+            .iter()
+            .filter(|key| self.remove(key).map_or(false, |item| !item.is_expired(now)))
+            .count();
+        Ok(Value::Integer(deleted as i64))
+    }
+
+    /// RENAME key newkey
+    pub(super) fn redis_rename(&mut self, args: Args) -> Reply {
+        self.rename(args, false).map(|_| ok())
+    }
+
+    /// RENAMENX key newkey
+    pub(super) fn redis_renamenx(&mut self, args: Args) -> Reply {
+        self.rename(args, true)
+            .map(|renamed| Value::Integer(renamed as i64))
+    }
+
+    /// COPY source destination [DB destination-db] [REPLACE]
+    ///
+    /// There is only database 0.
+    pub(super) fn redis_copy(&mut self, mut args: Args) -> Reply {
+        let mut replace = false;
+        let mut i = 3;
+        while i < args.len() {
+            match args[i].to_ascii_uppercase().as_slice() {
+                b"REPLACE" => replace = true,
+                b"DB" if i + 1 < args.len() => {
+                    match parse_int(&args[i + 1]) {
+                        Some(0) => {}
+                        Some(_) => return Err(Value::StaticError("ERR DB index is out of range")),
+                        None => return Err(Value::StaticError(ERR_NOT_INTEGER)),
+                    }
+                    i += 1;
+                }
+                _ => return Err(Value::StaticError(ERR_SYNTAX)),
+            }
+            i += 1;
+        }
+        if args[1] == args[2] {
+            return Err(Value::StaticError(
+                "ERR source and destination objects are the same",
+            ));
+        }
+
+        let item = match self.lookup(&args[1]) {
+            Some(item) => item.clone(),
+            None => return Ok(Value::Integer(0)),
+        };
+        if !replace && self.lookup(&args[2]).is_some() {
+            return Ok(Value::Integer(0));
+        }
+        self.insert_waking(args.swap_remove(2), item);
+        Ok(Value::Integer(1))
+    }
+
+    /// TOUCH key [key ...]
+    pub(super) fn redis_touch(&mut self, args: Args) -> Reply {
+        let touched = args[1..]
+            .iter()
+            .filter(|key| self.lookup(key).is_some())
+            .count();
+        Ok(Value::Integer(touched as i64))
+    }
+
+    /// OBJECT ENCODING|IDLETIME|FREQ key
+    ///
+    /// Doesn't count as an access to the key.
+    pub(super) fn redis_object(&mut self, args: Args) -> Reply {
+        let subcommand = args[1].to_ascii_uppercase();
+        let known = match subcommand.as_slice() {
+            b"ENCODING" | b"IDLETIME" | b"FREQ" => args.len() == 3,
+            _ => false,
+        };
+        if !known {
+            return Err(Value::Error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try OBJECT HELP.",
+                String::from_utf8_lossy(&args[1])
+            )));
+        }
+
+        let now = self.clock_ms;
+        let item = match self.peek(&args[2]) {
+            Some(item) => item,
+            None => return Ok(Value::Null),
+        };
+        Ok(match subcommand.as_slice() {
+            b"ENCODING" => Value::String(encoding(&item.value).into()),
+            b"IDLETIME" => {
+                Value::Integer((now.saturating_sub(item.accessed_at.get()) / 1000) as i64)
+            }
+            _ => Value::Integer(item.frequency(now) as i64),
+        })
+    }
+
+    /// Stores `item` under `key` like `insert` and wakes up clients blocked
+    /// on `key` if the item is a list.
+    pub(super) fn insert_waking(&mut self, key: Vec<u8>, item: Item) {
+        if !self.blocked.is_empty() && item.value.as_list().is_some() {
+            self.ready_keys.push(key.clone());
+        }
+        self.insert(key, item);
+    }
+
+    /// Returns the item stored under `key` unless it has expired, without
+    /// recording an access like `lookup` does.
+    fn peek(&self, key: &[u8]) -> Option<&Item> {
+        let now = self.clock_ms;
+        self.map.get(key).filter(|item| !item.is_expired(now))
+    }
+
+    /// Moves the item at `args[1]` to `args[2]`, unless `nx` is set and
+    /// `args[2]` exists. Returns `true` if the item was moved.
+    fn rename(&mut self, mut args: Args, nx: bool) -> Result<bool, Value> {
+        if self.lookup(&args[1]).is_none() {
+            return Err(Value::StaticError("ERR no such key"));
+        }
+        if nx && self.lookup(&args[2]).is_some() {
+            return Ok(false);
+        }
+        if args[1] == args[2] {
+            return Ok(true);
+        }
+        let item = self.remove(&args[1]).unwrap();
+        self.insert_waking(args.swap_remove(2), item);
+        Ok(true)
+    }
+}
+
+/// The name Redis uses for the encoding closest to how `data` is stored.
+///
+/// We keep all strings as plain bytes, but report them the way Redis
+/// would, clients use the encoding to tell integers from other strings.
+fn encoding(data: &Data) -> &'static str {
+    match data {
+        Data::String(s) if s.len() <= 20 && parse_int(s).is_some() => "int",
+        Data::String(s) if s.len() <= 44 => "embstr",
+        Data::String(_) => "raw",
+        Data::Hash(_) | Data::Set(_) => "hashtable",
+        Data::List(_) => "quicklist",
+        Data::SortedSet(_) => "skiplist",
+    }
+}
@@ -0,0 +1,108 @@
+//! Commands inspecting the whole key space: SCAN, KEYS, RANDOMKEY, DBSIZE
+//! and TYPE.
+//!
+//! Like the multi-key set commands, they only see the keys of the
+//! partition serving the connection.
+
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::glob::glob_match;
+use super::scan::{parse_cursor, parse_scan_options, scan_index};
+use super::{Args, Reply, ERR_SYNTAX};
+use crate::resp::value::Value;
+use crate::{Data, SashStore};
+
+/// Maximum number of random buckets RANDOMKEY looks at before giving up,
+/// the table may be (almost) empty or full of expired keys.
+const RANDOMKEY_MAX_PROBES: usize = 100;
+
+impl SashStore {
+    /// SCAN cursor [MATCH pattern] [COUNT count]
+    ///
+    /// The cursor walks the buckets of the table (see `Index::scan`), so
+    /// keys that exist during the whole iteration are returned even if the
+    /// table grows. Expired keys don't count towards `COUNT`.
+    pub(super) fn redis_scan(&mut self, args: Args) -> Reply {
+        let cursor = parse_cursor(&args[1])?;
+        let scan = parse_scan_options(&args[2..])?;
+        if scan.novalues {
+            return Err(Value::StaticError(ERR_SYNTAX));
+        }
+
+        let now = self.clock_ms;
+        let mut keys = Vec::new();
+        let cursor = scan_index(&self.map, cursor, scan.count, |key, item| {
+            let live = !item.is_expired(now);
+            if live {
+                keys.push(key.clone());
+            }
+            live
+        });
+
+        let keys = keys
+            .into_iter()
+            .filter(|key| scan.matches(key))
+            .map(Value::BufBulk)
+            .collect();
+        Ok(Value::Array(vec![
+            Value::BufBulk(format!("{}", cursor).into_bytes()),
+            Value::Array(keys),
+        ]))
+    }
+
+    /// KEYS pattern
+    pub(super) fn redis_keys(&mut self, args: Args) -> Reply {
+        let now = self.clock_ms;
+        Ok(Value::Array(
+            self.map
+                .iter()
+                .filter(|pair| !pair.1.is_expired(now) && glob_match(&args[1], pair.0))
+                .map(|pair| Value::BufBulk(pair.0.clone()))
+                .collect(),
+        ))
+    }
+
+    /// RANDOMKEY
+    pub(super) fn redis_randomkey(&mut self, _args: Args) -> Reply {
+        if self.map.is_empty() {
+            return Ok(Value::Null);
+        }
+        let now = self.clock_ms;
+        for _ in 0..RANDOMKEY_MAX_PROBES {
+            let i = (self.random() % self.map.buckets() as u64) as usize;
+            if let Some(pair) = self.map.get_at(i) {
+                if !pair.1.is_expired(now) {
+                    return Ok(Value::BufBulk(pair.0.clone()));
+                }
+            }
+        }
+        // Unlucky or (almost) everything expired, fall back to a full walk
+        Ok(self
+            .map
+            .iter()
+            .find(|pair| !pair.1.is_expired(now))
+            .map_or(Value::Null, |pair| Value::BufBulk(pair.0.clone())))
+    }
+
+    /// DBSIZE
+    ///
+    /// Includes expired keys the expiry cycle didn't reclaim yet, like Redis.
+    pub(super) fn redis_dbsize(&mut self, _args: Args) -> Reply {
+        Ok(Value::Integer(self.map.len() as i64))
+    }
+
+    /// TYPE key
+    pub(super) fn redis_type(&mut self, args: Args) -> Reply {
+        let name = match self.lookup(&args[1]).as_ref().map(|item| &item.value) {
+            Some(Data::String(_)) => "string",
+            Some(Data::Hash(_)) => "hash",
+            Some(Data::List(_)) => "list",
+            Some(Data::Set(_)) => "set",
+            Some(Data::SortedSet(_)) => "zset",
+            None => "none",
+        };
+        Ok(Value::String(name.into()))
+    }
+}
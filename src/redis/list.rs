@@ -0,0 +1,453 @@
+//! Redis list commands, including the blocking BLPOP/BRPOP.
+//!
+//! A list is stored as `Data::List`, a `VecDeque` so pushing and popping
+//! at both ends is cheap. Like in Redis, a list is deleted as soon as its
+//! last element is removed.
+//!
+//! Blocking never blocks the thread: a client that has to wait is added to
+//! `SashStore::blocked` and its remaining input is left unprocessed. Pushes
+//! to a key somebody waits for put it on `SashStore::ready_keys`, the
+//! waiting clients are then served right after the command that made the
+//! key ready. The server loop collects those replies (and the ones of
+//! clients whose timeout expired) with `SashStore::unblock_clients`.
+
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{parse_int, Args, Reply, ERR_NOT_INTEGER, ERR_SYNTAX};
+use crate::arch::arch::unix_time_ms;
+use crate::resp::serialize::encode_with_buf;
+use crate::resp::value::Value;
+use crate::{Data, Item, SashStore};
+
+/// A list of elements.
+type List = VecDeque<Vec<u8>>;
+
+/// Which end of a list a command operates on.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum End {
+    Left,
+    Right,
+}
+
+/// A client waiting in BLPOP/BRPOP.
+#[derive(Debug)]
+pub(crate) struct BlockedClient {
+    /// The connection the client is using
+    client: usize,
+    /// Keys the client waits for, in the order they were given
+    keys: Vec<Vec<u8>>,
+    /// The end to pop from
+    end: End,
+    /// Unix time (in ms) at which we give up, `None` waits forever
+    deadline: Option<u64>,
+}
+
+impl SashStore {
+    /// LPUSH key element [element ...]
+    pub(super) fn redis_lpush(&mut self, args: Args) -> Reply {
+        self.push(args, End::Left)
+    }
+
+    /// RPUSH key element [element ...]
+    pub(super) fn redis_rpush(&mut self, args: Args) -> Reply {
+        self.push(args, End::Right)
+    }
+
+    /// LPOP key [count]
+    pub(super) fn redis_lpop(&mut self, args: Args) -> Reply {
+        self.pop(args, End::Left)
+    }
+
+    /// RPOP key [count]
+    pub(super) fn redis_rpop(&mut self, args: Args) -> Reply {
+        self.pop(args, End::Right)
+    }
+
+    /// LLEN key
+    pub(super) fn redis_llen(&mut self, args: Args) -> Reply {
+        let list = self.lookup_as(&args[1], Data::as_list)?;
+        Ok(Value::Integer(list.map_or(0, |list| list.len()) as i64))
+    }
+
+    /// LRANGE key start stop
+    pub(super) fn redis_lrange(&mut self, args: Args) -> Reply {
+        let (start, stop) = parse_range(&args[2], &args[3])?;
+        let list = match self.lookup_as(&args[1], Data::as_list)? {
+            Some(list) => list,
+            None => return Ok(Value::Array(Vec::new())),
+        };
+
+        let elements = match list_range(start, stop, list.len()) {
+            Some((start, stop)) => list
+                .range(start..=stop)
+                .map(|element| Value::BufBulk(element.clone()))
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(Value::Array(elements))
+    }
+
+    /// LINDEX key index
+    pub(super) fn redis_lindex(&mut self, args: Args) -> Reply {
+        let index = parse_int(&args[2]).ok_or(Value::StaticError(ERR_NOT_INTEGER))?;
+        let list = match self.lookup_as(&args[1], Data::as_list)? {
+            Some(list) => list,
+            None => return Ok(Value::Null),
+        };
+        match list_index(index, list.len()).and_then(|i| list.get(i)) {
+            Some(element) => Ok(Value::BufBulk(element.clone())),
+            None => Ok(Value::Null),
+        }
+    }
+
+    /// LSET key index element
+    pub(super) fn redis_lset(&mut self, mut args: Args) -> Reply {
+        let index = parse_int(&args[2]).ok_or(Value::StaticError(ERR_NOT_INTEGER))?;
+        let element = args.pop().unwrap();
+        let list = self
+            .lookup_as_mut(&args[1], Data::as_list_mut)?
+            .ok_or(Value::StaticError("ERR no such key"))?;
+
+        match list_index(index, list.len()) {
+            Some(i) => {
+                list[i] = element;
+                Ok(super::ok())
+            }
+            None => Err(Value::StaticError("ERR index out of range")),
+        }
+    }
+
+    /// LREM key count element
+    pub(super) fn redis_lrem(&mut self, args: Args) -> Reply {
+        let count = parse_int(&args[2]).ok_or(Value::StaticError(ERR_NOT_INTEGER))?;
+        let element = &args[3];
+        let (removed, now_empty) = match self.lookup_as_mut(&args[1], Data::as_list_mut)? {
+            Some(list) => {
+                let limit = match count.checked_abs() {
+                    Some(0) | None => usize::MAX,
+                    Some(limit) => limit as usize,
+                };
+                let mut removed = 0;
+                if count >= 0 {
+                    let mut i = 0;
+                    while i < list.len() && removed < limit {
+                        if &list[i] == element {
+                            list.remove(i);
+                            removed += 1;
+                        } else {
+                            i += 1;
+                        }
+                    }
+                } else {
+                    let mut i = list.len();
+                    while i > 0 && removed < limit {
+                        i -= 1;
+                        if &list[i] == element {
+                            list.remove(i);
+                            removed += 1;
+                        }
+                    }
+                }
+                (removed, list.is_empty())
+            }
+            None => return Ok(Value::Integer(0)),
+        };
+        if now_empty {
+            self.remove(&args[1]);
+        }
+        Ok(Value::Integer(removed as i64))
+    }
+
+    /// LTRIM key start stop
+    pub(super) fn redis_ltrim(&mut self, args: Args) -> Reply {
+        let (start, stop) = parse_range(&args[2], &args[3])?;
+        let now_empty = match self.lookup_as_mut(&args[1], Data::as_list_mut)? {
+            Some(list) => {
+                match list_range(start, stop, list.len()) {
+                    Some((start, stop)) => {
+                        list.truncate(stop + 1);
+                        list.drain(..start);
+                    }
+                    None => list.clear(),
+                }
+                list.is_empty()
+            }
+            None => false,
+        };
+        if now_empty {
+            self.remove(&args[1]);
+        }
+        Ok(super::ok())
+    }
+
+    /// LINSERT key BEFORE | AFTER pivot element
+    pub(super) fn redis_linsert(&mut self, mut args: Args) -> Reply {
+        let after = match args[2].to_ascii_uppercase().as_slice() {
+            b"BEFORE" => false,
+            b"AFTER" => true,
+            _ => return Err(Value::StaticError(ERR_SYNTAX)),
+        };
+        let element = args.pop().unwrap();
+        let list = match self.lookup_as_mut(&args[1], Data::as_list_mut)? {
+            Some(list) => list,
+            None => return Ok(Value::Integer(0)),
+        };
+
+        match list.iter().position(|e| e == &args[3]) {
+            Some(i) => {
+                list.insert(if after { i + 1 } else { i }, element);
+                Ok(Value::Integer(list.len() as i64))
+            }
+            None => Ok(Value::Integer(-1)),
+        }
+    }
+
+    /// BLPOP key [key ...] timeout
+    pub(super) fn redis_blpop(&mut self, args: Args) -> Reply {
+        self.blocking_pop(args, End::Left)
+    }
+
+    /// BRPOP key [key ...] timeout
+    pub(super) fn redis_brpop(&mut self, args: Args) -> Reply {
+        self.blocking_pop(args, End::Right)
+    }
+
+    /// Returns `true` if `client` waits in a blocking command.
+    pub(super) fn is_blocked(&self, client: usize) -> bool {
+        self.blocked.iter().any(|blocked| blocked.client == client)
+    }
+
+    /// Stops waiting for `client`, without replying.
+    pub(super) fn cancel_blocking(&mut self, client: usize) {
+        self.blocked.retain(|blocked| blocked.client != client);
+    }
+
+    /// Serves the clients waiting for keys that received new elements.
+    ///
+    /// Clients are served in the order they blocked, the replies are
+    /// queued for `unblock_clients`.
+    pub(super) fn serve_ready_keys(&mut self) {
+        while let Some(key) = self.ready_keys.pop() {
+            let mut i = 0;
+            while i < self.blocked.len() {
+                if !self.blocked[i].keys.contains(&key) {
+                    i += 1;
+                    continue;
+                }
+                match self.pop_one(&key, self.blocked[i].end) {
+                    Some(element) => {
+                        self.signal_modified_key(&key);
+                        let blocked = self.blocked.remove(i);
+                        let reply = Value::Array(vec![Value::BufBulk(key.clone()), element]);
+                        self.unblocked
+                            .push((blocked.client, encode_with_buf(Vec::new(), &reply)));
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    /// Returns the replies for clients that are no longer blocked, either
+    /// because they were served or because their timeout expired.
+    ///
+    /// The caller should send the replies and then process any input of
+    /// these clients that was held back while they were blocked.
+    pub fn unblock_clients(&mut self) -> Vec<(usize, Vec<u8>)> {
+        let now = unix_time_ms();
+        let mut unblocked = core::mem::take(&mut self.unblocked);
+
+        let mut i = 0;
+        while i < self.blocked.len() {
+            match self.blocked[i].deadline {
+                Some(deadline) if deadline <= now => {
+                    let blocked = self.blocked.remove(i);
+                    unblocked.push((
+                        blocked.client,
+                        encode_with_buf(Vec::new(), &Value::NullArray),
+                    ));
+                }
+                _ => i += 1,
+            }
+        }
+        unblocked
+    }
+
+    /// Milliseconds until the next blocked client times out, `None` if
+    /// no client waits with a timeout.
+    pub fn next_block_timeout_ms(&self) -> Option<u64> {
+        let now = unix_time_ms();
+        self.blocked
+            .iter()
+            .filter_map(|blocked| blocked.deadline)
+            .min()
+            .map(|deadline| deadline.saturating_sub(now))
+    }
+
+    /// Pushes all elements in `args[2..]` to the list in `args[1]`.
+    fn push(&mut self, args: Args, end: End) -> Reply {
+        let mut args = args.into_iter().skip(1);
+        let key = args.next().unwrap();
+        if !self.blocked.is_empty() {
+            self.ready_keys.push(key.clone());
+        }
+
+        let list = self.list_or_insert(key)?;
+        for element in args {
+            match end {
+                End::Left => list.push_front(element),
+                End::Right => list.push_back(element),
+            }
+        }
+        Ok(Value::Integer(list.len() as i64))
+    }
+
+    /// Pops one or (if a count is given) several elements.
+    fn pop(&mut self, args: Args, end: End) -> Reply {
+        if args.len() > 3 {
+            return Err(super::wrong_arity(&args));
+        }
+        let count = match args.get(2).map(|count| parse_int(count)) {
+            Some(Some(count)) if count >= 0 => Some(count as usize),
+            Some(_) => {
+                return Err(Value::StaticError(
+                    "ERR value is out of range, must be positive",
+                ))
+            }
+            None => None,
+        };
+        if self.lookup_as(&args[1], Data::as_list)?.is_none() {
+            return Ok(if count.is_some() {
+                Value::NullArray
+            } else {
+                Value::Null
+            });
+        }
+
+        match count {
+            Some(count) => {
+                let mut elements = Vec::new();
+                while elements.len() < count {
+                    match self.pop_one(&args[1], end) {
+                        Some(element) => elements.push(element),
+                        None => break,
+                    }
+                }
+                Ok(Value::Array(elements))
+            }
+            None => Ok(self.pop_one(&args[1], end).unwrap_or(Value::Null)),
+        }
+    }
+
+    /// Removes an element from the list stored at `key`, deleting the key
+    /// if the list becomes empty.
+    ///
+    /// Returns `None` if there is no list at `key`.
+    fn pop_one(&mut self, key: &[u8], end: End) -> Option<Value> {
+        let (element, now_empty) = {
+            let list = self.lookup_as_mut(key, Data::as_list_mut).ok()??;
+            let element = match end {
+                End::Left => list.pop_front(),
+                End::Right => list.pop_back(),
+            };
+            (element?, list.is_empty())
+        };
+        if now_empty {
+            self.remove(key);
+        }
+        Some(Value::BufBulk(element))
+    }
+
+    /// Pops from the first non-empty list, or blocks the current client.
+    fn blocking_pop(&mut self, mut args: Args, end: End) -> Reply {
+        let timeout = args.pop().unwrap();
+        let timeout = super::parse_float(&timeout).ok_or(Value::StaticError(
+            "ERR timeout is not a float or out of range",
+        ))?;
+        if timeout < 0.0 {
+            return Err(Value::StaticError("ERR timeout is negative"));
+        }
+        let deadline = if timeout > 0.0 {
+            let deadline = self
+                .clock_ms
+                .checked_add((timeout * 1000.0).ceil() as u64)
+                .ok_or(Value::StaticError("ERR timeout is out of range"))?;
+            Some(deadline)
+        } else {
+            None
+        };
+
+        for key in &args[1..] {
+            if self.lookup_as(key, Data::as_list)?.is_some() {
+                let element = self.pop_one(key, end).unwrap();
+                return Ok(Value::Array(vec![Value::BufBulk(key.clone()), element]));
+            }
+        }
+
+        args.remove(0);
+        self.blocked.push(BlockedClient {
+            client: self.client,
+            keys: args,
+            end,
+            deadline,
+        });
+        // Not sent, the client gets its reply once it is unblocked
+        Ok(Value::NullArray)
+    }
+
+    /// Returns the list stored at `key`, creating an empty one if the key
+    /// doesn't exist.
+    fn list_or_insert(&mut self, key: Vec<u8>) -> Result<&mut List, Value> {
+        if self.lookup_as(&key, Data::as_list)?.is_none() {
+            self.insert(key.clone(), Item::with_data(0, Data::List(VecDeque::new())));
+        }
+        Ok(self.lookup_as_mut(&key, Data::as_list_mut)?.unwrap())
+    }
+}
+
+/// Parses the `start` and `stop` arguments of LRANGE and LTRIM.
+fn parse_range(start: &[u8], stop: &[u8]) -> Result<(i64, i64), Value> {
+    match (parse_int(start), parse_int(stop)) {
+        (Some(start), Some(stop)) => Ok((start, stop)),
+        _ => Err(Value::StaticError(ERR_NOT_INTEGER)),
+    }
+}
+
+/// Converts a Redis list index (negative ones count from the end) into an
+/// index of a list with `len` elements.
+fn list_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    if index >= 0 && index < len as i64 {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
+
+/// Converts inclusive `start`/`stop` indices of LRANGE and LTRIM (also
+/// ranks of ZRANGE) into valid indices of a list with `len` elements.
+///
+/// Unlike `string::clamp_range` a negative `stop` before the beginning of
+/// the list always selects nothing.
+///
+/// Returns `None` if the resulting range is empty.
+pub(super) fn list_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        None
+    } else {
+        Some((start as usize, stop as usize))
+    }
+}
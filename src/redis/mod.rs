@@ -0,0 +1,1460 @@
+//! Execute Redis commands (RESP protocol) against a `SashStore`.
+//!
+//! Keys, values and command arguments are handled as plain byte strings
+//! throughout: the decoder always runs with `with_buf_bulk` and replies are
+//! encoded as `Value::BufBulk`, so binary payloads round-trip unchanged.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+
+use log::trace;
+
+use crate::arch::arch::unix_time_ms;
+use crate::resp::serialize::{decode_prefix, encode_with_buf};
+use crate::resp::value::Value;
+use crate::resp::DecodeError;
+use crate::{Data, SashStore};
+
+mod client;
+mod command;
+mod dump;
+mod expire;
+mod float;
+mod glob;
+mod hash;
+mod keys;
+mod keyspace;
+mod list;
+mod multi;
+mod pubsub;
+mod scan;
+mod script;
+mod server;
+mod set;
+mod skiplist;
+mod string;
+mod zset;
+
+pub(crate) use client::Client;
+pub(crate) use command::{command_table, Command};
+pub(crate) use list::BlockedClient;
+pub use pubsub::Publication;
+pub use server::{OpsSampler, Stats};
+pub use zset::SortedSet;
+
+/// A decoded command: the command name followed by its arguments.
+type Args = Vec<Vec<u8>>;
+
+/// The outcome of a command, errors are sent to the client as error replies.
+type Reply = Result<Value, Value>;
+
+/// Executes a command with already validated arity.
+type Handler = fn(&mut SashStore, Args) -> Reply;
+
+const ERR_NOT_INTEGER: &str = "ERR value is not an integer or out of range";
+const ERR_NOT_FLOAT: &str = "ERR value is not a valid float";
+const ERR_SYNTAX: &str = "ERR syntax error";
+const ERR_WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+impl SashStore {
+    /// Execute all complete RESP commands `client` sent, contained in `buf`.
+    ///
+    /// Decoded commands are removed from `buf`, a partially received command
+    /// stays at the front of it so the caller can append more data and call
+    /// us again. The same happens to all commands following one that blocks
+    /// the client, see `unblock_clients`. Returns the encoded replies (empty
+    /// if nothing was executed), followed by any Pub/Sub messages queued for
+    /// `client` in the meantime.
+    pub fn handle_resp_request(&mut self, client: usize, buf: &mut Vec<u8>) -> Vec<u8> {
+        if self.is_blocked(client) {
+            return Vec::new();
+        }
+        self.client = client;
+        let total = buf.len();
+        let mut consumed = 0;
+        let mut response = Vec::new();
+
+        while consumed < total {
+            // Commands are flat arrays, don't accept any nesting
+            let reply = match decode_prefix(&buf[consumed..], true, 1) {
+                Ok((value, len)) => {
+                    consumed += len;
+                    trace!("Received value={:?}", value);
+                    let reply = self.execute_resp_cmd(value);
+                    if !self.ready_keys.is_empty() {
+                        self.serve_ready_keys();
+                    }
+                    if !self.blocked.is_empty() && self.is_blocked(client) {
+                        break;
+                    }
+                    reply
+                }
+                Err(DecodeError::UnexpectedEof) => break,
+                Err(e) => {
+                    // We can't resynchronize with the stream, drop what we have
+                    consumed = total;
+                    Value::Error(format!("ERR Protocol error: {:?}", e))
+                }
+            };
+            response = encode_with_buf(response, &reply);
+            if !self.messages.is_empty() {
+                self.flush_messages(client, &mut response);
+            }
+            if self.is_killed(client) {
+                // Killed itself, nothing else it sent gets executed
+                consumed = total;
+                break;
+            }
+        }
+
+        buf.drain(..consumed);
+        response
+    }
+
+    /// Execute a parsed RESP command against our KV store.
+    fn execute_resp_cmd(&mut self, cmd: Value) -> Value {
+        let args = match into_args(cmd) {
+            Some(args) if !args.is_empty() => args,
+            _ => return Value::StaticError("ERR Protocol error: expected array of bulk strings"),
+        };
+        let now = unix_time_ms();
+        let client = self.current_client();
+        client.last_active = now;
+        client.last_command = args[0].to_ascii_lowercase();
+        self.execute_args(args)
+    }
+
+    /// Execute a decoded command, queueing it instead if the connection is
+    /// inside MULTI.
+    fn execute_args(&mut self, args: Args) -> Value {
+        self.clock_ms = unix_time_ms();
+        // Lookups can't move an incremental resize of the keyspace along,
+        // so every command takes a step of it
+        self.map.migrate();
+        let command = match self.command(&args[0]) {
+            Some(command) => command,
+            None => {
+                self.fail_multi();
+                return Value::Error(format!(
+                    "ERR unknown command `{}`",
+                    String::from_utf8_lossy(&args[0])
+                ));
+            }
+        };
+
+        if !command.accepts(args.len()) {
+            self.fail_multi();
+            return wrong_arity(&args);
+        }
+        let allowed = match command.name {
+            "subscribe" | "unsubscribe" | "psubscribe" | "punsubscribe" => true,
+            _ => !self.in_push_mode(),
+        };
+        if !allowed {
+            return Value::Error(format!(
+                "ERR Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE are allowed in this context",
+                command.name
+            ));
+        }
+        let queue = match command.name {
+            "multi" | "exec" | "discard" | "watch" => false,
+            _ => self.in_multi(),
+        };
+        if queue {
+            return self.queue_command(args);
+        }
+        // Only successful writes touch watched keys, WATCH ignores failed
+        // ones (like WRONGTYPE errors)
+        let written: Vec<Vec<u8>> = if command.has_flag("write") && !self.watching.is_empty() {
+            command.keys(&args).cloned().collect()
+        } else {
+            Vec::new()
+        };
+        let reply = match (command.handler)(self, args) {
+            Ok(reply) => {
+                for key in &written {
+                    self.signal_modified_key(key);
+                }
+                reply
+            }
+            Err(error) => error,
+        };
+        // Counted afterwards like in Redis, INFO doesn't count itself
+        self.stats()
+            .commands_processed
+            .fetch_add(1, Ordering::Relaxed);
+        reply
+    }
+
+    /// Returns the value stored under `key` if it exists, `f` selects the
+    /// expected data type.
+    ///
+    /// Fails with a `WRONGTYPE` error if the key holds a different type.
+    fn lookup_as<T>(&self, key: &[u8], f: fn(&Data) -> Option<&T>) -> Result<Option<&T>, Value> {
+        match self.lookup(key) {
+            Some(item) => f(&item.value)
+                .map(Some)
+                .ok_or(Value::StaticError(ERR_WRONGTYPE)),
+            None => Ok(None),
+        }
+    }
+
+    /// Mutable version of `lookup_as`.
+    fn lookup_as_mut<T>(
+        &mut self,
+        key: &[u8],
+        f: fn(&mut Data) -> Option<&mut T>,
+    ) -> Result<Option<&mut T>, Value> {
+        match self.lookup_mut(key) {
+            Some(item) => f(&mut item.value)
+                .map(Some)
+                .ok_or(Value::StaticError(ERR_WRONGTYPE)),
+            None => Ok(None),
+        }
+    }
+    /// Returns the next number of a xorshift64* pseudo random sequence.
+    ///
+    /// Good enough to pick random members, not for anything security
+    /// related.
+    pub(crate) fn random(&self) -> u64 {
+        let mut x = self.rng.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.rng.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+/// Converts a request into a list of byte strings.
+///
+/// Returns `None` if the request is not an array of bulk strings.
+fn into_args(cmd: Value) -> Option<Args> {
+    match cmd {
+        Value::Array(values) => values
+            .into_iter()
+            .map(|v| match v {
+                Value::BufBulk(bytes) => Some(bytes),
+                Value::Bulk(string) => Some(string.into_bytes()),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// The `+OK` status reply.
+fn ok() -> Value {
+    Value::String(String::from("OK"))
+}
+
+/// Parses an integer the way Redis does: no whitespace, no `+` sign and
+/// no leading zeros.
+fn parse_int(bytes: &[u8]) -> Option<i64> {
+    let digits = match bytes.first() {
+        Some(b'-') => &bytes[1..],
+        _ => bytes,
+    };
+    if digits.is_empty()
+        || !digits.iter().all(u8::is_ascii_digit)
+        || (digits[0] == b'0' && bytes.len() > 1)
+    {
+        return None;
+    }
+    core::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// Parses a finite floating point number.
+fn parse_float(bytes: &[u8]) -> Option<f64> {
+    let s = core::str::from_utf8(bytes).ok()?;
+    if s.is_empty() || s.starts_with(char::is_whitespace) || s.ends_with(char::is_whitespace) {
+        return None;
+    }
+    s.parse::<f64>().ok().filter(|f| f.is_finite())
+}
+
+fn wrong_arity(args: &Args) -> Value {
+    Value::Error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        String::from_utf8_lossy(&args[0]).to_lowercase()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resp::serialize::Decoder;
+    use alloc::collections::VecDeque;
+    use rand::{Rng, RngCore, SeedableRng};
+
+    /// Encodes a command the same way a Redis client would.
+    fn command(args: &[&[u8]]) -> Vec<u8> {
+        let array = args.iter().map(|a| Value::BufBulk(a.to_vec())).collect();
+        encode_with_buf(Vec::new(), &Value::Array(array))
+    }
+
+    #[test]
+    fn binary_set_get_roundtrip() {
+        let mut store = SashStore::with_capacity(64);
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(0xdead_beef);
+
+        for _ in 0..256 {
+            let mut key = vec![0u8; rng.gen_range(1, 64)];
+            let mut value = vec![0u8; rng.gen_range(0, 4096)];
+            rng.fill_bytes(&mut key);
+            rng.fill_bytes(&mut value);
+
+            let mut buf = command(&[b"SET", &key, &value]);
+            assert_eq!(store.handle_resp_request(0, &mut buf), b"+OK\r\n");
+            assert!(buf.is_empty());
+
+            let mut buf = command(&[b"GET", &key]);
+            let expected = encode_with_buf(Vec::new(), &Value::BufBulk(value));
+            assert_eq!(store.handle_resp_request(0, &mut buf), expected);
+        }
+    }
+
+    #[test]
+    fn partial_and_pipelined_requests() {
+        let mut store = SashStore::with_capacity(64);
+        let mut stream = command(&[b"set", b"k\r\n\0", b"\xff\xfe\r\n"]);
+        stream.extend(command(&[b"get", b"k\r\n\0"]));
+
+        // Feed the stream byte by byte, replies only show up once a
+        // command is complete.
+        let mut buf = Vec::new();
+        let mut replies = Vec::new();
+        for byte in stream {
+            buf.push(byte);
+            replies.extend(store.handle_resp_request(0, &mut buf));
+        }
+
+        assert!(buf.is_empty());
+        assert_eq!(replies, b"+OK\r\n$4\r\n\xff\xfe\r\n\r\n".to_vec());
+    }
+
+    #[test]
+    fn nested_requests_are_rejected() {
+        let mut store = SashStore::with_capacity(64);
+        let mut buf = b"*1\r\n*1\r\n$4\r\nPING\r\n".to_vec();
+        assert_eq!(
+            store.handle_resp_request(0, &mut buf),
+            b"-ERR Protocol error: InvalidInput\r\n".to_vec()
+        );
+        assert!(buf.is_empty());
+
+        // Deep enough to overflow the stack if we recursed into it
+        let mut buf = b"*1\r\n".repeat(1 << 20);
+        buf.extend_from_slice(b":1\r\n");
+        assert_eq!(
+            store.handle_resp_request(0, &mut buf),
+            b"-ERR Protocol error: InvalidInput\r\n".to_vec()
+        );
+    }
+
+    /// Runs a single command and returns the encoded reply.
+    fn run(store: &mut SashStore, args: &[&[u8]]) -> Vec<u8> {
+        let mut buf = command(args);
+        store.handle_resp_request(0, &mut buf)
+    }
+
+    /// Runs `command` (HSCAN, SSCAN...) on `key` until the cursor comes
+    /// back to `0`, returns the distinct elements and the number of calls.
+    fn scan_all(
+        store: &mut SashStore,
+        command: &[u8],
+        key: &[u8],
+        options: &[&[u8]],
+    ) -> (Vec<Vec<u8>>, usize) {
+        let mut cursor = b"0".to_vec();
+        let mut found = Vec::new();
+        let mut calls = 0;
+        loop {
+            let mut args = vec![command, key, &cursor];
+            args.extend(options);
+            let reply = run(store, &args);
+            let mut decoder = Decoder::with_buf_bulk(VecDeque::from(reply));
+            let next = match decoder.decode().unwrap() {
+                Value::Array(mut reply) => {
+                    found.extend(into_args(reply.pop().unwrap()).unwrap());
+                    into_args(Value::Array(reply)).unwrap().remove(0)
+                }
+                reply => panic!("unexpected reply {:?}", reply),
+            };
+            cursor = next;
+            calls += 1;
+            if cursor == b"0" {
+                break;
+            }
+        }
+        found.sort();
+        found.dedup();
+        (found, calls)
+    }
+
+    #[test]
+    fn string_commands() {
+        let mut store = SashStore::with_capacity(64);
+        assert_eq!(run(&mut store, &[b"INCR", b"n"]), b":1\r\n");
+        assert_eq!(run(&mut store, &[b"INCRBY", b"n", b"41"]), b":42\r\n");
+        assert_eq!(run(&mut store, &[b"DECRBY", b"n", b"50"]), b":-8\r\n");
+        assert_eq!(
+            run(&mut store, &[b"INCRBYFLOAT", b"n", b"0.5"]),
+            b"$4\r\n-7.5\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"INCRBYFLOAT", b"f", b"0.1"]),
+            b"$3\r\n0.1\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"INCRBYFLOAT", b"f", b"0.2"]),
+            b"$3\r\n0.3\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"INCR", b"n"]),
+            b"-ERR value is not an integer or out of range\r\n".to_vec()
+        );
+
+        assert_eq!(
+            run(&mut store, &[b"SET", b"max", b"9223372036854775807"]),
+            b"+OK\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"INCR", b"max"]),
+            b"-ERR increment or decrement would overflow\r\n".to_vec()
+        );
+
+        assert_eq!(run(&mut store, &[b"APPEND", b"s", b"Hello"]), b":5\r\n");
+        assert_eq!(run(&mut store, &[b"APPEND", b"s", b" World"]), b":11\r\n");
+        assert_eq!(
+            run(&mut store, &[b"GETRANGE", b"s", b"-5", b"-1"]),
+            b"$5\r\nWorld\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"GETRANGE", b"s", b"0", b"100"]),
+            b"$11\r\nHello World\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"SETRANGE", b"s", b"6", b"Redis"]),
+            b":11\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"SETRANGE", b"z", b"2", b"x"]), b":3\r\n");
+        assert_eq!(run(&mut store, &[b"GET", b"z"]), b"$3\r\n\0\0x\r\n");
+        assert_eq!(run(&mut store, &[b"STRLEN", b"s"]), b":11\r\n");
+
+        assert_eq!(
+            run(&mut store, &[b"GETDEL", b"s"]),
+            b"$11\r\nHello Redis\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"GETDEL", b"s"]), b"$-1\r\n");
+        assert_eq!(run(&mut store, &[b"SETNX", b"s", b"a"]), b":1\r\n");
+        assert_eq!(run(&mut store, &[b"SETNX", b"s", b"b"]), b":0\r\n");
+        assert_eq!(run(&mut store, &[b"GETSET", b"s", b"c"]), b"$1\r\na\r\n");
+
+        assert_eq!(
+            run(&mut store, &[b"MSETNX", b"s", b"1", b"t", b"2"]),
+            b":0\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"MSET", b"s", b"1", b"t", b"2"]),
+            b"+OK\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"MGET", b"s", b"t", b"u"]),
+            b"*3\r\n$1\r\n1\r\n$1\r\n2\r\n$-1\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn expiry() {
+        let mut store = SashStore::with_capacity(64);
+        assert_eq!(
+            run(&mut store, &[b"SET", b"k", b"v", b"EX", b"100"]),
+            b"+OK\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"TTL", b"k"]), b":100\r\n");
+        assert_eq!(
+            run(&mut store, &[b"SET", b"k", b"w", b"KEEPTTL", b"GET"]),
+            b"$1\r\nv\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"TTL", b"k"]), b":100\r\n");
+        assert_eq!(run(&mut store, &[b"PERSIST", b"k"]), b":1\r\n");
+        assert_eq!(run(&mut store, &[b"TTL", b"k"]), b":-1\r\n");
+        assert_eq!(run(&mut store, &[b"TTL", b"nope"]), b":-2\r\n");
+
+        assert_eq!(run(&mut store, &[b"SET", b"k", b"x", b"NX"]), b"$-1\r\n");
+        assert_eq!(run(&mut store, &[b"SET", b"n", b"x", b"XX"]), b"$-1\r\n");
+        assert_eq!(
+            run(&mut store, &[b"SET", b"k", b"x", b"NX", b"XX"]),
+            b"-ERR syntax error\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"SET", b"k", b"x", b"EX", b"0"]),
+            b"-ERR invalid expire time in 'set' command\r\n".to_vec()
+        );
+
+        // A deadline in the past removes the key
+        assert_eq!(run(&mut store, &[b"EXPIREAT", b"k", b"1"]), b":1\r\n");
+        assert_eq!(run(&mut store, &[b"GET", b"k"]), b"$-1\r\n");
+        assert_eq!(run(&mut store, &[b"EXPIRE", b"k", b"10"]), b":0\r\n");
+
+        // Expired keys are invisible right away and reclaimed by the cycle
+        for i in 0..32u8 {
+            run(&mut store, &[b"SET", &[i], b"v", b"PXAT", b"1"]);
+        }
+        assert_eq!(
+            run(&mut store, &[b"MGET", &[0], &[31]]),
+            b"*2\r\n$-1\r\n$-1\r\n"
+        );
+        assert_eq!(store.map.len(), 32);
+        assert_eq!(store.expires, 32);
+        while store.active_expire_cycle() > 0 {}
+        assert_eq!(store.map.len(), 0);
+        assert_eq!((store.expires, store.deadline_sum), (0, 0));
+
+        // Reads move a resize of the keyspace along, so does the cycle
+        let mut store = SashStore::with_capacity(1024);
+        let mut keys = 0u32;
+        while !store.map.is_resizing() {
+            run(&mut store, &[b"SET", &keys.to_be_bytes(), b"v"]);
+            keys += 1;
+        }
+        let mut reads = 0;
+        while store.map.is_resizing() {
+            assert_eq!(
+                run(&mut store, &[b"GET", &0u32.to_be_bytes()]),
+                b"$1\r\nv\r\n"
+            );
+            reads += 1;
+        }
+        assert!(reads > 1);
+        assert_eq!(store.map.len(), keys as usize);
+
+        while !store.map.is_resizing() {
+            run(&mut store, &[b"SET", &keys.to_be_bytes(), b"v"]);
+            keys += 1;
+        }
+        store.active_expire_cycle();
+        assert!(!store.map.is_resizing());
+        assert_eq!(store.map.len(), keys as usize);
+    }
+
+    #[test]
+    fn hash_commands() {
+        let mut store = SashStore::with_capacity(64);
+        assert_eq!(
+            run(&mut store, &[b"HSET", b"h", b"a", b"1", b"b", b"2"]),
+            b":2\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"HSET", b"h", b"a", b"3", b"c", b"4"]),
+            b":1\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"HGET", b"h", b"a"]), b"$1\r\n3\r\n");
+        assert_eq!(run(&mut store, &[b"HLEN", b"h"]), b":3\r\n");
+        assert_eq!(
+            run(&mut store, &[b"HMGET", b"h", b"b", b"x"]),
+            b"*2\r\n$1\r\n2\r\n$-1\r\n".to_vec()
+        );
+        assert_eq!(
+            run(&mut store, &[b"HINCRBY", b"h", b"b", b"40"]),
+            b":42\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"HINCRBY", b"h", b"n", b"-1"]),
+            b":-1\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"HEXISTS", b"h", b"n"]), b":1\r\n");
+        assert_eq!(
+            run(&mut store, &[b"HDEL", b"h", b"a", b"c", b"n", b"x"]),
+            b":3\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"HGETALL", b"h"]),
+            b"*2\r\n$1\r\nb\r\n$2\r\n42\r\n".to_vec()
+        );
+
+        run(
+            &mut store,
+            &[b"HSET", b"h", b"field:1", b"x", b"other", b"y"],
+        );
+        assert_eq!(
+            run(&mut store, &[b"HSCAN", b"h", b"0", b"MATCH", b"field:*"]),
+            b"*2\r\n$1\r\n0\r\n*2\r\n$7\r\nfield:1\r\n$1\r\nx\r\n".to_vec()
+        );
+
+        // COUNT bounds the work per call, the cursor resumes where it stopped
+        for i in 0..100 {
+            let field = format!("many:{}", i);
+            run(&mut store, &[b"HSET", b"big", field.as_bytes(), b"v"]);
+        }
+        let (fields, calls) =
+            scan_all(&mut store, b"HSCAN", b"big", &[b"COUNT", b"5", b"NOVALUES"]);
+        assert!(calls > 1);
+        assert_eq!(fields.len(), 100);
+        run(&mut store, &[b"DEL", b"big"]);
+
+        // The key disappears with its last field
+        assert_eq!(
+            run(&mut store, &[b"HDEL", b"h", b"b", b"field:1", b"other"]),
+            b":3\r\n"
+        );
+        assert_eq!(store.map.len(), 0);
+
+        let wrongtype = b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n";
+        run(&mut store, &[b"SET", b"s", b"v"]);
+        assert_eq!(run(&mut store, &[b"HGET", b"s", b"a"]), wrongtype.to_vec());
+        assert_eq!(
+            run(&mut store, &[b"HSET", b"s", b"a", b"1"]),
+            wrongtype.to_vec()
+        );
+        run(&mut store, &[b"HSET", b"h", b"a", b"1"]);
+        assert_eq!(run(&mut store, &[b"GET", b"h"]), wrongtype.to_vec());
+        assert_eq!(
+            run(&mut store, &[b"APPEND", b"h", b"x"]),
+            wrongtype.to_vec()
+        );
+        assert_eq!(
+            run(&mut store, &[b"HSET", b"h", b"a"]),
+            b"-ERR wrong number of arguments for 'hset' command\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn list_commands() {
+        let mut store = SashStore::with_capacity(64);
+        assert_eq!(run(&mut store, &[b"RPUSH", b"l", b"b", b"c"]), b":2\r\n");
+        assert_eq!(run(&mut store, &[b"LPUSH", b"l", b"a", b"z"]), b":4\r\n");
+        assert_eq!(
+            run(&mut store, &[b"LRANGE", b"l", b"1", b"-1"]),
+            b"*3\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n".to_vec()
+        );
+        assert_eq!(run(&mut store, &[b"LRANGE", b"l", b"0", b"-9"]), b"*0\r\n");
+        assert_eq!(run(&mut store, &[b"LINDEX", b"l", b"-1"]), b"$1\r\nc\r\n");
+        assert_eq!(run(&mut store, &[b"LSET", b"l", b"0", b"a"]), b"+OK\r\n");
+        assert_eq!(
+            run(&mut store, &[b"LSET", b"l", b"9", b"a"]),
+            b"-ERR index out of range\r\n".to_vec()
+        );
+        assert_eq!(
+            run(&mut store, &[b"LINSERT", b"l", b"AFTER", b"c", b"a"]),
+            b":5\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"LREM", b"l", b"-2", b"a"]), b":2\r\n");
+        assert_eq!(
+            run(&mut store, &[b"LPOP", b"l", b"2"]),
+            b"*2\r\n$1\r\na\r\n$1\r\nb\r\n".to_vec()
+        );
+        assert_eq!(run(&mut store, &[b"RPOP", b"l"]), b"$1\r\nc\r\n");
+        assert_eq!(run(&mut store, &[b"LLEN", b"l"]), b":0\r\n");
+        assert_eq!(run(&mut store, &[b"LPOP", b"l", b"1"]), b"*-1\r\n");
+        assert_eq!(store.map.len(), 0);
+
+        run(&mut store, &[b"RPUSH", b"l", b"a", b"b", b"c", b"d"]);
+        assert_eq!(run(&mut store, &[b"LTRIM", b"l", b"1", b"-2"]), b"+OK\r\n");
+        assert_eq!(
+            run(&mut store, &[b"LRANGE", b"l", b"0", b"-1"]),
+            b"*2\r\n$1\r\nb\r\n$1\r\nc\r\n".to_vec()
+        );
+        assert_eq!(run(&mut store, &[b"LTRIM", b"l", b"5", b"10"]), b"+OK\r\n");
+        assert_eq!(store.map.len(), 0);
+    }
+
+    #[test]
+    fn blocking_pop() {
+        let mut store = SashStore::with_capacity(64);
+        let (producer, consumer, other) = (0, 1, 2);
+
+        // Commands after a blocking one wait until the client is unblocked
+        let mut buf = command(&[b"BRPOP", b"q1", b"q2", b"0"]);
+        buf.extend(command(&[b"LLEN", b"q2"]));
+        assert!(store.handle_resp_request(consumer, &mut buf).is_empty());
+        assert!(!buf.is_empty());
+        let mut buf2 = command(&[b"BLPOP", b"q2", b"0"]);
+        assert!(store.handle_resp_request(other, &mut buf2).is_empty());
+        assert!(store.unblock_clients().is_empty());
+
+        // The client that blocked first is served first
+        let mut push = command(&[b"RPUSH", b"q2", b"a", b"b"]);
+        assert_eq!(store.handle_resp_request(producer, &mut push), b":2\r\n");
+        assert_eq!(
+            store.unblock_clients(),
+            vec![
+                (consumer, b"*2\r\n$2\r\nq2\r\n$1\r\nb\r\n".to_vec()),
+                (other, b"*2\r\n$2\r\nq2\r\n$1\r\na\r\n".to_vec()),
+            ]
+        );
+        assert_eq!(store.handle_resp_request(consumer, &mut buf), b":0\r\n");
+
+        // Data that is already there is returned right away
+        run(&mut store, &[b"RPUSH", b"q1", b"x"]);
+        assert_eq!(
+            run(&mut store, &[b"BLPOP", b"q2", b"q1", b"1"]),
+            b"*2\r\n$2\r\nq1\r\n$1\r\nx\r\n".to_vec()
+        );
+
+        let mut buf = command(&[b"BLPOP", b"q1", b"0.01"]);
+        assert!(store.handle_resp_request(consumer, &mut buf).is_empty());
+        assert!(store.next_block_timeout_ms().unwrap() <= 10);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert_eq!(
+            store.unblock_clients(),
+            vec![(consumer, b"*-1\r\n".to_vec())]
+        );
+        assert_eq!(store.next_block_timeout_ms(), None);
+
+        assert_eq!(
+            run(&mut store, &[b"BLPOP", b"q1", b"1e300"]),
+            b"-ERR timeout is out of range\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn set_commands() {
+        let mut store = SashStore::with_capacity(64);
+        assert_eq!(
+            run(&mut store, &[b"SADD", b"a", b"1", b"2", b"3", b"2"]),
+            b":3\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"SADD", b"b", b"3", b"4"]), b":2\r\n");
+        assert_eq!(run(&mut store, &[b"SCARD", b"a"]), b":3\r\n");
+        assert_eq!(run(&mut store, &[b"SISMEMBER", b"a", b"2"]), b":1\r\n");
+        assert_eq!(
+            run(&mut store, &[b"SMISMEMBER", b"a", b"4", b"1"]),
+            b"*2\r\n:0\r\n:1\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"SINTER", b"a", b"b"]),
+            b"*1\r\n$1\r\n3\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"SINTER", b"a", b"nope"]), b"*0\r\n");
+        assert_eq!(
+            run(&mut store, &[b"SUNIONSTORE", b"u", b"a", b"b"]),
+            b":4\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"SDIFFSTORE", b"d", b"u", b"a"]),
+            b":1\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"SMEMBERS", b"d"]), b"*1\r\n$1\r\n4\r\n");
+        assert_eq!(
+            run(&mut store, &[b"SDIFFSTORE", b"d", b"a", b"u"]),
+            b":0\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"SCARD", b"d"]), b":0\r\n");
+        assert_eq!(
+            run(&mut store, &[b"SSCAN", b"u", b"0", b"MATCH", b"[34]"]).len(),
+            b"*2\r\n$1\r\n0\r\n*2\r\n$1\r\n3\r\n$1\r\n4\r\n".len()
+        );
+        for i in 0..100 {
+            run(&mut store, &[b"SADD", b"big", format!("m{}", i).as_bytes()]);
+        }
+        let (members, calls) = scan_all(&mut store, b"SSCAN", b"big", &[b"COUNT", b"5"]);
+        assert!(calls > 1);
+        assert_eq!(members.len(), 100);
+        run(&mut store, &[b"DEL", b"big"]);
+
+        // Random members are distinct unless the count is negative
+        assert_eq!(
+            run(&mut store, &[b"SRANDMEMBER", b"u", b"-6"]).len(),
+            b"*6\r\n".len() + 6 * b"$1\r\n1\r\n".len()
+        );
+        let mut buf = command(&[b"SRANDMEMBER", b"u", b"3"]);
+        let reply = store.handle_resp_request(0, &mut buf);
+        let mut decoder = Decoder::with_buf_bulk(VecDeque::from(reply));
+        let members = match decoder.decode() {
+            Ok(Value::Array(members)) => members,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        assert_eq!(members.len(), 3);
+        assert!(members
+            .iter()
+            .all(|m| members.iter().filter(|n| n == &m).count() == 1));
+
+        for remaining in (0..4).rev() {
+            run(&mut store, &[b"SPOP", b"u"]);
+            assert_eq!(
+                run(&mut store, &[b"SCARD", b"u"]),
+                format!(":{}\r\n", remaining).into_bytes()
+            );
+        }
+        assert_eq!(run(&mut store, &[b"SPOP", b"u"]), b"$-1\r\n");
+        assert_eq!(run(&mut store, &[b"SPOP", b"a", b"10"]).len(), 4 + 3 * 7);
+        assert_eq!(run(&mut store, &[b"SREM", b"b", b"3", b"4"]), b":2\r\n");
+        assert_eq!(store.map.len(), 0);
+    }
+
+    #[test]
+    fn sorted_set_commands() {
+        let mut store = SashStore::with_capacity(64);
+        assert_eq!(
+            run(
+                &mut store,
+                &[b"ZADD", b"z", b"1", b"a", b"2", b"b", b"3", b"c"]
+            ),
+            b":3\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"ZADD", b"z", b"CH", b"5", b"a", b"4", b"d"]),
+            b":2\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"ZADD", b"z", b"GT", b"1", b"a"]),
+            b":0\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"ZADD", b"z", b"XX", b"INCR", b"1.5", b"b"]),
+            b"$3\r\n3.5\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"ZADD", b"z", b"NX", b"INCR", b"1", b"b"]),
+            b"$-1\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"ZINCRBY", b"z", b"-1", b"b"]),
+            b"$3\r\n2.5\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"ZSCORE", b"z", b"a"]), b"$1\r\n5\r\n");
+        assert_eq!(run(&mut store, &[b"ZCARD", b"z"]), b":4\r\n");
+
+        // b=2.5 c=3 d=4 a=5
+        assert_eq!(run(&mut store, &[b"ZRANK", b"z", b"d"]), b":2\r\n");
+        assert_eq!(run(&mut store, &[b"ZREVRANK", b"z", b"d"]), b":1\r\n");
+        assert_eq!(run(&mut store, &[b"ZRANK", b"z", b"x"]), b"$-1\r\n");
+        assert_eq!(
+            run(&mut store, &[b"ZCOUNT", b"z", b"(3", b"+inf"]),
+            b":2\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"ZRANGE", b"z", b"0", b"1", b"WITHSCORES"]),
+            b"*4\r\n$1\r\nb\r\n$3\r\n2.5\r\n$1\r\nc\r\n$1\r\n3\r\n".to_vec()
+        );
+        assert_eq!(
+            run(&mut store, &[b"ZRANGE", b"z", b"0", b"1", b"REV"]),
+            b"*2\r\n$1\r\na\r\n$1\r\nd\r\n".to_vec()
+        );
+        assert_eq!(
+            run(
+                &mut store,
+                &[b"ZRANGE", b"z", b"(5", b"3", b"BYSCORE", b"REV"]
+            ),
+            b"*2\r\n$1\r\nd\r\n$1\r\nc\r\n".to_vec()
+        );
+        assert_eq!(
+            run(
+                &mut store,
+                &[b"ZRANGE", b"z", b"-inf", b"+inf", b"BYSCORE", b"LIMIT", b"1", b"2"]
+            ),
+            b"*2\r\n$1\r\nc\r\n$1\r\nd\r\n".to_vec()
+        );
+
+        run(
+            &mut store,
+            &[
+                b"ZADD", b"lex", b"0", b"apple", b"0", b"banana", b"0", b"cherry",
+            ],
+        );
+        assert_eq!(
+            run(&mut store, &[b"ZRANGE", b"lex", b"(apple", b"+", b"BYLEX"]),
+            b"*2\r\n$6\r\nbanana\r\n$6\r\ncherry\r\n".to_vec()
+        );
+        assert_eq!(
+            run(
+                &mut store,
+                &[b"ZRANGE", b"lex", b"[b", b"-", b"BYLEX", b"REV"]
+            ),
+            b"*1\r\n$5\r\napple\r\n".to_vec()
+        );
+
+        assert_eq!(
+            run(&mut store, &[b"ZPOPMIN", b"z"]),
+            b"*2\r\n$1\r\nb\r\n$3\r\n2.5\r\n".to_vec()
+        );
+        assert_eq!(
+            run(&mut store, &[b"ZPOPMAX", b"z", b"2"]),
+            b"*4\r\n$1\r\na\r\n$1\r\n5\r\n$1\r\nd\r\n$1\r\n4\r\n".to_vec()
+        );
+        assert_eq!(run(&mut store, &[b"ZREM", b"z", b"c", b"x"]), b":1\r\n");
+        assert_eq!(run(&mut store, &[b"ZCARD", b"z"]), b":0\r\n");
+        assert_eq!(
+            run(&mut store, &[b"ZADD", b"z", b"NX", b"XX", b"1", b"a"]),
+            b"-ERR XX and NX options at the same time are not compatible\r\n".to_vec()
+        );
+        assert_eq!(
+            run(&mut store, &[b"ZADD", b"z", b"1", b"a", b"x"]),
+            b"-ERR syntax error\r\n".to_vec()
+        );
+        assert_eq!(store.map.len(), 1);
+    }
+
+    #[test]
+    fn transactions() {
+        let mut store = SashStore::with_capacity(64);
+        let mut buf = command(&[b"MULTI"]);
+        buf.extend(command(&[b"SET", b"k", b"1"]));
+        buf.extend(command(&[b"INCR", b"k"]));
+        buf.extend(command(&[b"BLPOP", b"l", b"0"]));
+        buf.extend(command(&[b"EXEC"]));
+        assert_eq!(
+            store.handle_resp_request(0, &mut buf),
+            b"+OK\r\n+QUEUED\r\n+QUEUED\r\n+QUEUED\r\n*3\r\n+OK\r\n:2\r\n*-1\r\n".to_vec()
+        );
+        assert!(store.unblock_clients().is_empty());
+
+        // Errors while queueing abort the transaction
+        let mut buf = command(&[b"MULTI"]);
+        buf.extend(command(&[b"INCR", b"k"]));
+        buf.extend(command(&[b"NOPE"]));
+        buf.extend(command(&[b"EXEC"]));
+        assert_eq!(
+            store.handle_resp_request(0, &mut buf),
+            b"+OK\r\n+QUEUED\r\n-ERR unknown command `NOPE`\r\n-EXECABORT Transaction discarded because of previous errors.\r\n".to_vec()
+        );
+        assert_eq!(run(&mut store, &[b"GET", b"k"]), b"$1\r\n2\r\n");
+        assert_eq!(
+            run(&mut store, &[b"EXEC"]),
+            b"-ERR EXEC without MULTI\r\n".to_vec()
+        );
+
+        // A write by another connection after WATCH makes EXEC fail
+        let watch_and_exec = |store: &mut SashStore, writer: Option<&[&[u8]]>| {
+            let mut buf = command(&[b"WATCH", b"k", b"missing"]);
+            store.handle_resp_request(1, &mut buf);
+            if let Some(write) = writer {
+                let mut buf = command(write);
+                store.handle_resp_request(0, &mut buf);
+            }
+            let mut buf = command(&[b"MULTI"]);
+            buf.extend(command(&[b"GET", b"k"]));
+            buf.extend(command(&[b"EXEC"]));
+            store.handle_resp_request(1, &mut buf)
+        };
+        assert_eq!(
+            watch_and_exec(&mut store, None),
+            b"+OK\r\n+QUEUED\r\n*1\r\n$1\r\n2\r\n".to_vec()
+        );
+        assert_eq!(
+            watch_and_exec(&mut store, Some(&[b"APPEND", b"k", b"0"])),
+            b"+OK\r\n+QUEUED\r\n*-1\r\n".to_vec()
+        );
+        assert_eq!(
+            watch_and_exec(&mut store, Some(&[b"SET", b"missing", b"x"])),
+            b"+OK\r\n+QUEUED\r\n*-1\r\n".to_vec()
+        );
+        assert_eq!(
+            watch_and_exec(&mut store, Some(&[b"GET", b"k"])),
+            b"+OK\r\n+QUEUED\r\n*1\r\n$2\r\n20\r\n".to_vec()
+        );
+        // Failed writes don't count
+        assert_eq!(
+            watch_and_exec(&mut store, Some(&[b"HSET", b"k", b"f", b"v"])),
+            b"+OK\r\n+QUEUED\r\n*1\r\n$2\r\n20\r\n".to_vec()
+        );
+        assert_eq!(
+            watch_and_exec(&mut store, Some(&[b"INCRBYFLOAT", b"missing", b"1"])),
+            b"+OK\r\n+QUEUED\r\n*1\r\n$2\r\n20\r\n".to_vec()
+        );
+
+        // Creating and deleting a watched key counts, deleting a missing one
+        // doesn't
+        let watch_writes_exec = |store: &mut SashStore, writes: &[&[&[u8]]]| {
+            let mut buf = command(&[b"WATCH", b"fresh"]);
+            store.handle_resp_request(1, &mut buf);
+            for write in writes {
+                run(store, write);
+            }
+            let mut buf = command(&[b"MULTI"]);
+            buf.extend(command(&[b"GET", b"fresh"]));
+            buf.extend(command(&[b"EXEC"]));
+            store.handle_resp_request(1, &mut buf)
+        };
+        assert_eq!(
+            watch_writes_exec(
+                &mut store,
+                &[&[b"SET", b"fresh", b"1"], &[b"DEL", b"fresh"]]
+            ),
+            b"+OK\r\n+QUEUED\r\n*-1\r\n".to_vec()
+        );
+        assert_eq!(
+            watch_writes_exec(&mut store, &[&[b"DEL", b"fresh"]]),
+            b"+OK\r\n+QUEUED\r\n*1\r\n$-1\r\n".to_vec()
+        );
+        assert!(store.watching.is_empty());
+
+        // The connection's partition serves all of its keys
+        let mut store = SashStore::with_partitions(64, 2);
+        let mut buf = command(&[b"MULTI"]);
+        buf.extend(command(&[b"MSET", b"a", b"1", b"b", b"2", b"c", b"3"]));
+        buf.extend(command(&[b"EXEC"]));
+        assert_eq!(
+            store.handle_resp_request(0, &mut buf),
+            b"+OK\r\n+QUEUED\r\n*1\r\n+OK\r\n".to_vec()
+        );
+        assert_eq!(
+            run(
+                &mut store,
+                &[b"EVAL", b"return call(\"GET\", KEYS[1]);", b"1", b"c"]
+            ),
+            b"$1\r\n3\r\n"
+        );
+    }
+
+    #[test]
+    fn keyspace_commands() {
+        let mut store = SashStore::with_capacity(4);
+        assert_eq!(run(&mut store, &[b"RANDOMKEY"]), b"$-1\r\n");
+        for i in 0..50 {
+            let key = format!("key:{}", i);
+            run(&mut store, &[b"SET", key.as_bytes(), b"v"]);
+        }
+        run(&mut store, &[b"HSET", b"h", b"f", b"v"]);
+        run(&mut store, &[b"ZADD", b"z", b"1", b"m"]);
+        assert_eq!(run(&mut store, &[b"DBSIZE"]), b":52\r\n");
+        assert_eq!(run(&mut store, &[b"TYPE", b"h"]), b"+hash\r\n");
+        assert_eq!(run(&mut store, &[b"TYPE", b"z"]), b"+zset\r\n");
+        assert_eq!(run(&mut store, &[b"TYPE", b"nope"]), b"+none\r\n");
+        assert_eq!(run(&mut store, &[b"KEYS", b"key:4?"]).len(), 5 + 10 * 12);
+        assert!(run(&mut store, &[b"RANDOMKEY"]).starts_with(b"$"));
+
+        // Grow the table half way through, every key is still returned
+        let mut found = Vec::new();
+        let mut cursor = b"0".to_vec();
+        loop {
+            let reply = run(
+                &mut store,
+                &[b"SCAN", &cursor, b"MATCH", b"key:*", b"COUNT", b"5"],
+            );
+            let mut decoder = Decoder::with_buf_bulk(VecDeque::from(reply));
+            match decoder.decode().unwrap() {
+                Value::Array(mut reply) => {
+                    found.extend(into_args(reply.pop().unwrap()).unwrap());
+                    cursor = into_args(Value::Array(reply)).unwrap().remove(0);
+                }
+                reply => panic!("unexpected reply {:?}", reply),
+            }
+            if found.len() >= 25 && store.map.capacity() < 256 {
+                for i in 0..store.map.capacity() {
+                    let key = format!("other:{}", i);
+                    run(&mut store, &[b"SET", key.as_bytes(), b"v"]);
+                }
+            }
+            if cursor == b"0" {
+                break;
+            }
+        }
+        found.sort();
+        found.dedup();
+        assert_eq!(found.len(), 50);
+
+        // A huge COUNT returns everything in one go
+        let reply = run(
+            &mut store,
+            &[
+                b"SCAN",
+                b"0",
+                b"MATCH",
+                b"key:1?",
+                b"COUNT",
+                b"9223372036854775807",
+            ],
+        );
+        assert!(reply.starts_with(b"*2\r\n$1\r\n0\r\n*10\r\n"));
+    }
+
+    #[test]
+    fn key_management() {
+        let mut store = SashStore::with_capacity(64);
+        run(&mut store, &[b"SET", b"a", b"12", b"EX", b"100"]);
+        assert_eq!(run(&mut store, &[b"RENAME", b"a", b"b"]), b"+OK\r\n");
+        assert_eq!(run(&mut store, &[b"TTL", b"b"]), b":100\r\n");
+        assert_eq!(
+            run(&mut store, &[b"RENAME", b"a", b"b"]),
+            b"-ERR no such key\r\n".to_vec()
+        );
+        run(&mut store, &[b"SET", b"c", b"x"]);
+        assert_eq!(run(&mut store, &[b"RENAMENX", b"b", b"c"]), b":0\r\n");
+        assert_eq!(run(&mut store, &[b"COPY", b"b", b"c"]), b":0\r\n");
+        assert_eq!(
+            run(&mut store, &[b"COPY", b"b", b"c", b"REPLACE"]),
+            b":1\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"GET", b"c"]), b"$2\r\n12\r\n");
+        assert_eq!(run(&mut store, &[b"TOUCH", b"b", b"c", b"d"]), b":2\r\n");
+        assert_eq!(
+            run(&mut store, &[b"OBJECT", b"ENCODING", b"c"]),
+            b"+int\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"OBJECT", b"IDLETIME", b"c"]), b":0\r\n");
+        assert_eq!(run(&mut store, &[b"UNLINK", b"b", b"c", b"d"]), b":2\r\n");
+
+        // DUMP/RESTORE round trip every type
+        run(&mut store, &[b"RPUSH", b"l", b"x", b"y"]);
+        run(&mut store, &[b"SADD", b"s", b"x", b"y"]);
+        run(&mut store, &[b"ZADD", b"z", b"1.5", b"x", b"-inf", b"y"]);
+        run(&mut store, &[b"HSET", b"h", b"f", b"v"]);
+        run(&mut store, &[b"SET", b"str", b"\0\xff"]);
+        let reads: &[&[&[u8]]] = &[
+            &[b"LRANGE", b"l", b"0", b"-1"],
+            &[b"SCARD", b"s"],
+            &[b"ZRANGE", b"z", b"0", b"-1", b"WITHSCORES"],
+            &[b"HGETALL", b"h"],
+            &[b"GET", b"str"],
+        ];
+        for read in reads {
+            let key = read[1];
+            let before = run(&mut store, read);
+            let mut decoder =
+                Decoder::with_buf_bulk(VecDeque::from(run(&mut store, &[b"DUMP", key])));
+            let payload = into_args(Value::Array(vec![decoder.decode().unwrap()])).unwrap();
+            assert_eq!(
+                run(&mut store, &[b"RESTORE", key, b"0", &payload[0]]),
+                b"-BUSYKEY Target key name already exists.\r\n".to_vec()
+            );
+            assert_eq!(
+                run(
+                    &mut store,
+                    &[b"RESTORE", key, b"0", &payload[0], b"REPLACE"]
+                ),
+                b"+OK\r\n"
+            );
+            assert_eq!(run(&mut store, read), before);
+
+            let mut corrupted = payload[0].clone();
+            corrupted[1] ^= 1;
+            assert_eq!(
+                run(&mut store, &[b"RESTORE", b"new", b"0", &corrupted]),
+                b"-ERR DUMP payload version or checksum are wrong\r\n".to_vec()
+            );
+        }
+    }
+
+    #[test]
+    fn publish_subscribe() {
+        let mut store = SashStore::with_partitions(64, 2);
+        let mut buf = command(&[b"SUBSCRIBE", b"news", b"sport"]);
+        assert_eq!(
+            store.handle_resp_request(1, &mut buf),
+            b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n*3\r\n$9\r\nsubscribe\r\n$5\r\nsport\r\n:2\r\n".to_vec()
+        );
+        let mut buf = command(&[b"PSUBSCRIBE", b"n*"]);
+        store.handle_resp_request(2, &mut buf);
+        let mut buf = command(&[b"GET", b"k"]);
+        assert_eq!(
+            store.handle_resp_request(1, &mut buf),
+            b"-ERR Can't execute 'get': only (P)SUBSCRIBE / (P)UNSUBSCRIBE are allowed in this context\r\n".to_vec()
+        );
+
+        assert_eq!(run(&mut store, &[b"PUBLISH", b"news", b"hi"]), b":2\r\n");
+        assert_eq!(
+            store.take_messages(),
+            vec![
+                (
+                    1,
+                    b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n".to_vec()
+                ),
+                (
+                    2,
+                    b"*4\r\n$8\r\npmessage\r\n$2\r\nn*\r\n$4\r\nnews\r\n$2\r\nhi\r\n".to_vec()
+                )
+            ]
+        );
+        let published = store.take_published();
+        assert_eq!(published.len(), 1);
+        assert_eq!(
+            (&published[0].channel[..], &published[0].message[..]),
+            (&b"news"[..], &b"hi"[..])
+        );
+
+        assert_eq!(
+            run(&mut store, &[b"PUBSUB", b"NUMSUB", b"news", b"x"]),
+            b"*4\r\n$4\r\nnews\r\n:1\r\n$1\r\nx\r\n:0\r\n".to_vec()
+        );
+        let mut buf = command(&[b"UNSUBSCRIBE"]);
+        store.handle_resp_request(1, &mut buf);
+        assert_eq!(run(&mut store, &[b"PUBSUB", b"CHANNELS"]), b"*0\r\n");
+        let mut buf = command(&[b"GET", b"k"]);
+        assert_eq!(store.handle_resp_request(1, &mut buf), b"$-1\r\n");
+    }
+
+    #[test]
+    fn scripts() {
+        let mut store = SashStore::with_capacity(64);
+        let cas: &[u8] = b"if call(\"GET\", KEYS[1]) == ARGV[1] {\n\
+            call(\"SET\", KEYS[1], ARGV[2]);\n\
+            return 1;\n\
+        }\n\
+        return 0;";
+        run(&mut store, &[b"SET", b"k", b"old"]);
+        assert_eq!(
+            run(&mut store, &[b"EVAL", cas, b"1", b"k", b"new", b"x"]),
+            b":0\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"EVAL", cas, b"1", b"k", b"old", b"new"]),
+            b":1\r\n"
+        );
+        assert_eq!(run(&mut store, &[b"GET", b"k"]), b"$3\r\nnew\r\n");
+
+        // Increment while below a limit
+        let incr: &[u8] = b"let n = int(call(\"GET\", KEYS[1])) || 0;\n\
+            if n < int(ARGV[1]) { return call(\"INCR\", KEYS[1]) } else { return nil }";
+        for expected in &[&b":1\r\n"[..], b":2\r\n", b"$-1\r\n"] {
+            assert_eq!(
+                run(&mut store, &[b"EVAL", incr, b"1", b"n", b"2"]),
+                *expected
+            );
+        }
+
+        assert_eq!(
+            run(
+                &mut store,
+                &[b"EVAL", b"return [1 + 2 * 3, \"a\" .. 7, KEYS[2]]", b"0"]
+            ),
+            b"*3\r\n:7\r\n$2\r\na7\r\n$-1\r\n"
+        );
+        assert_eq!(
+            run(
+                &mut store,
+                &[b"EVAL", b"return call(\"LPUSH\", KEYS[1], 1)", b"1", b"k"]
+            ),
+            format!("-{}\r\n", ERR_WRONGTYPE).into_bytes()
+        );
+        assert_eq!(
+            run(
+                &mut store,
+                &[b"EVAL", b"let i = 0; while true { i = i + 1 }", b"0"]
+            ),
+            b"-ERR script exceeded its budget of 100000 instructions\r\n".to_vec()
+        );
+        // Copying a 100 KiB value a thousand times costs 100 KiB each
+        let copies: &[u8] =
+            b"let n = 0; while n < 1000 { let copy = ARGV[1]; n = n + 1 } return n;";
+        assert_eq!(
+            run(&mut store, &[b"EVAL", copies, b"0", &[b'x'; 100 * 1024]]),
+            b"-ERR script exceeded its budget of 100000 instructions\r\n".to_vec()
+        );
+        assert_eq!(
+            run(&mut store, &[b"EVAL", copies, b"0", b"x"]),
+            b":1000\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"EVAL", b"return 1 +", b"0"]),
+            b"-ERR syntax error in script at byte 10: expected an expression\r\n".to_vec()
+        );
+        assert_eq!(
+            run(&mut store, &[b"EVAL", &[b'('; 100], b"0"]),
+            b"-ERR syntax error in script at byte 64: too deeply nested\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn client_and_config() {
+        let mut store = SashStore::with_capacity(64);
+        store.accept_client(0, String::from("127.0.0.1:1000"));
+        store.accept_client(1, String::from("127.0.0.1:1001"));
+        assert_eq!(run(&mut store, &[b"CLIENT", b"ID"]), b":0\r\n");
+        assert_eq!(run(&mut store, &[b"CLIENT", b"GETNAME"]), b"$-1\r\n");
+        assert_eq!(
+            run(&mut store, &[b"CLIENT", b"SETNAME", b"a b"]),
+            b"-ERR Client names cannot contain spaces, newlines or special characters.\r\n"
+                .to_vec()
+        );
+        run(&mut store, &[b"CLIENT", b"SETNAME", b"worker"]);
+        assert_eq!(
+            run(&mut store, &[b"CLIENT", b"GETNAME"]),
+            b"$6\r\nworker\r\n"
+        );
+        let mut buf = command(&[b"SUBSCRIBE", b"news"]);
+        store.handle_resp_request(1, &mut buf);
+        let list = run(&mut store, &[b"CLIENT", b"LIST"]);
+        let list = String::from_utf8_lossy(&list);
+        assert!(list.contains(
+            "id=0 addr=127.0.0.1:1000 name=worker age=0 idle=0 flags=N sub=0 psub=0 multi=-1 cmd=client\n"
+        ));
+        assert!(list.contains("id=1 addr=127.0.0.1:1001 name= age=0 idle=0 flags=P sub=1"));
+
+        assert_eq!(
+            run(&mut store, &[b"CLIENT", b"KILL", b"ID", b"0"]),
+            b":0\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"CLIENT", b"KILL", b"127.0.0.1:1001"]),
+            b"+OK\r\n"
+        );
+        assert_eq!(store.take_killed(), vec![1]);
+        assert_eq!(run(&mut store, &[b"PUBLISH", b"news", b"hi"]), b":0\r\n");
+
+        assert_eq!(
+            run(&mut store, &[b"CONFIG", b"GET", b"*load", b"PORT"]),
+            b"*6\r\n$4\r\nport\r\n$4\r\n6666\r\n$8\r\nmax-load\r\n$3\r\n0.7\r\n$8\r\nmin-load\r\n$3\r\n0.1\r\n".to_vec()
+        );
+        assert_eq!(
+            run(&mut store, &[b"CONFIG", b"GET", b"hasher"]),
+            b"*2\r\n$6\r\nhasher\r\n$3\r\nfnv\r\n".to_vec()
+        );
+        assert_eq!(
+            run(&mut store, &[b"CONFIG", b"SET", b"max-load", b"0.5", b"port", b"1"]),
+            b"-ERR CONFIG SET failed (possibly related to argument 'port') - can't set immutable config\r\n"
+                .to_vec()
+        );
+        // SCAN cursors only stay valid if the keyspace grows by doubling
+        assert_eq!(
+            run(&mut store, &[b"CONFIG", b"SET", b"growth-policy", b"2"]),
+            b"-ERR CONFIG SET failed (possibly related to argument 'growth-policy') - can't set immutable config\r\n"
+                .to_vec()
+        );
+        assert_eq!(store.map.max_load(), 0.7);
+        assert_eq!(
+            run(&mut store, &[b"CONFIG", b"SET", b"max-load", b"0.5"]),
+            b"+OK\r\n"
+        );
+        assert_eq!(store.map.max_load(), 0.5);
+        assert_eq!(
+            run(&mut store, &[b"CONFIG", b"SET", b"min-load", b"0.25"]),
+            b"+OK\r\n"
+        );
+        assert_eq!(store.map.min_load(), 0.25);
+        assert_eq!(run(&mut store, &[b"CONFIG", b"RESETSTAT"]), b"+OK\r\n");
+        assert_eq!(store.stats().commands_processed.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn nested_indexes_use_configured_hasher() {
+        use crate::indexmap::hash::{HashFunction, SipHasherBuilder};
+
+        let hasher = HashFunction::SipHash13(SipHasherBuilder::new(7, 11));
+        let config = crate::arch::CmdArgs {
+            hasher,
+            ..Default::default()
+        };
+        let stats: alloc::sync::Arc<[Stats]> = (0..1).map(|_| Default::default()).collect();
+        let mut store = SashStore::with_config(config, 0, stats);
+        run(&mut store, &[b"HSET", b"h", b"f", b"v"]);
+        run(&mut store, &[b"SADD", b"s", b"a", b"b"]);
+        run(&mut store, &[b"SINTERSTORE", b"i", b"s", b"s"]);
+        let dump = run(&mut store, &[b"DUMP", b"h"]);
+        let mut decoder = Decoder::with_buf_bulk(VecDeque::from(dump));
+        let body = match decoder.decode() {
+            Ok(Value::BufBulk(body)) => body,
+            other => panic!("unexpected DUMP reply {:?}", other),
+        };
+        run(&mut store, &[b"RESTORE", b"r", b"0", &body]);
+        let mut buf = command(&[b"SUBSCRIBE", b"news"]);
+        store.handle_resp_request(1, &mut buf);
+
+        let hash = |key: &[u8]| *store.lookup(key).unwrap().value.as_hash().unwrap().hasher();
+        assert_eq!(hash(b"h"), hasher);
+        assert_eq!(hash(b"r"), hasher);
+        let set = |key: &[u8]| *store.lookup(key).unwrap().value.as_set().unwrap().hasher();
+        assert_eq!(set(b"s"), hasher);
+        assert_eq!(set(b"i"), hasher);
+        assert_eq!(*store.subscribers.hasher(), hasher);
+        assert_eq!(
+            run(&mut store, &[b"SMEMBERS", b"i"]).len(),
+            b"*2\r\n$1\r\na\r\n$1\r\nb\r\n".len()
+        );
+    }
+
+    #[test]
+    fn info_sections() {
+        let config = crate::arch::CmdArgs {
+            threads: 2,
+            ..Default::default()
+        };
+        let stats: alloc::sync::Arc<[Stats]> = (0..2).map(|_| Default::default()).collect();
+        let mut first = SashStore::with_config(config, 0, stats.clone());
+        let mut second = SashStore::with_config(config, 1, stats);
+        run(&mut first, &[b"SET", b"a", b"1"]);
+        run(&mut first, &[b"SET", b"t", b"1", b"EX", b"100"]);
+        run(&mut second, &[b"GET", b"a"]);
+        run(&mut second, &[b"SET", b"b", b"1"]);
+        run(&mut second, &[b"GET", b"b"]);
+        second.sample_stats();
+
+        let info = run(&mut first, &[b"INFO"]);
+        let info = String::from_utf8_lossy(&info);
+        for section in &["# Server", "# Clients", "# Memory", "# Stats", "# Keyspace"] {
+            assert!(info.contains(section));
+        }
+        assert!(info.contains("\r\ntotal_commands_processed:5\r\n"));
+        assert!(info.contains("\r\nkeyspace_hits:1\r\nkeyspace_misses:1\r\n"));
+        let db0 = info.split("\r\ndb0:").nth(1).unwrap();
+        assert!(db0.starts_with("keys=3,expires=1,avg_ttl="));
+        let avg_ttl: u64 = db0["keys=3,expires=1,avg_ttl=".len()..db0.find('\r').unwrap()]
+            .parse()
+            .unwrap();
+        assert!(avg_ttl > 90_000 && avg_ttl <= 100_000);
+        assert!(info.contains("\r\npartition1:keys=1,capacity=10000,load=0.000\r\n"));
+
+        assert!(!info.contains("# Hashtable"));
+
+        let info = run(&mut first, &[b"INFO", b"CLIENTS"]);
+        assert!(info.ends_with(b"# Clients\r\nconnected_clients:2\r\nblocked_clients:0\r\n\r\n"));
+
+        let info = run(&mut second, &[b"INFO", b"hashtable"]);
+        let info = String::from_utf8_lossy(&info);
+        assert!(info.contains("# Hashtable\r\npartition:1\r\nkeys:1\r\ncapacity:10000\r\n"));
+        assert!(info.contains("\r\nprobe_lengths:1=1\r\navg_probe_length:1.000\r\n"));
+    }
+
+    #[test]
+    fn command_introspection() {
+        let mut store = SashStore::with_capacity(64);
+        assert_eq!(run(&mut store, &[b"COMMAND", b"COUNT"]), b":104\r\n");
+        assert_eq!(
+            run(&mut store, &[b"COMMAND", b"INFO", b"get", b"nope"]),
+            b"*2\r\n*6\r\n$3\r\nget\r\n:2\r\n*2\r\n+readonly\r\n+fast\r\n:1\r\n:1\r\n:1\r\n*-1\r\n"
+                .to_vec()
+        );
+        assert_eq!(
+            run(&mut store, &[b"COMMAND", b"DOCS", b"GET"]),
+            b"*2\r\n$3\r\nget\r\n*4\r\n$7\r\nsummary\r\n$34\r\nReturns the string value of a key.\r\n$5\r\ngroup\r\n$6\r\nstring\r\n"
+                .to_vec()
+        );
+        assert_eq!(
+            run(
+                &mut store,
+                &[b"COMMAND", b"GETKEYS", b"MSET", b"a", b"1", b"b", b"2"]
+            ),
+            b"*2\r\n$1\r\na\r\n$1\r\nb\r\n"
+        );
+        assert_eq!(
+            run(
+                &mut store,
+                &[
+                    b"COMMAND",
+                    b"GETKEYS",
+                    b"EVAL",
+                    b"return 1;",
+                    b"1",
+                    b"k",
+                    b"v"
+                ]
+            ),
+            b"*1\r\n$1\r\nk\r\n"
+        );
+        assert_eq!(
+            run(&mut store, &[b"COMMAND", b"GETKEYS", b"DBSIZE"]),
+            b"-ERR The command has no key arguments\r\n".to_vec()
+        );
+        assert_eq!(
+            run(&mut store, &[b"COMMAND", b"GETKEYS", b"GET"]),
+            b"-ERR Invalid number of arguments specified for command\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn missing_key_and_errors() {
+        let mut store = SashStore::with_capacity(64);
+        let mut buf = command(&[b"GET", b"nope"]);
+        assert_eq!(store.handle_resp_request(0, &mut buf), b"$-1\r\n");
+
+        let mut buf = command(&[b"GET"]);
+        assert_eq!(
+            store.handle_resp_request(0, &mut buf),
+            b"-ERR wrong number of arguments for 'get' command\r\n".to_vec()
+        );
+    }
+}
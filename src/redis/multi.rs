@@ -0,0 +1,150 @@
+//! Transactions: MULTI, EXEC, DISCARD and optimistic locking with WATCH.
+//!
+//! Every partition is served by a single thread, so a queued transaction
+//! runs atomically simply by executing it in one go. A connection is bound
+//! to the partition that accepted it, like every other command a
+//! transaction only sees the keys of that partition.
+//!
+//! Like in Redis, the partition keeps track of the connections watching
+//! each key. Writing or deleting a key marks them dirty, EXEC only runs the
+//! transaction of a connection that isn't. A key that expired since WATCH
+//! counts as written.
+
+use alloc::vec::Vec;
+
+use super::{ok, Args, Reply};
+use crate::resp::value::Value;
+use crate::SashStore;
+
+impl SashStore {
+    /// MULTI
+    pub(super) fn redis_multi(&mut self, _args: Args) -> Reply {
+        let client = self.current_client();
+        if client.multi.is_some() {
+            return Err(Value::StaticError("ERR MULTI calls can not be nested"));
+        }
+        client.multi = Some(Vec::new());
+        client.multi_failed = false;
+        Ok(ok())
+    }
+
+    /// EXEC
+    pub(super) fn redis_exec(&mut self, _args: Args) -> Reply {
+        let client = self.current_client();
+        let queued = client
+            .multi
+            .take()
+            .ok_or(Value::StaticError("ERR EXEC without MULTI"))?;
+        let failed = client.multi_failed;
+        let client = self.client;
+        let state = &self.clients[client];
+        let dirty = state.dirty
+            || state
+                .watched
+                .iter()
+                .any(|(key, existed)| *existed && self.lookup(key).is_none());
+        self.unwatch_all(client);
+
+        if failed {
+            return Err(Value::StaticError(
+                "EXECABORT Transaction discarded because of previous errors.",
+            ));
+        }
+        if dirty {
+            return Ok(Value::NullArray);
+        }
+
+        let mut replies = Vec::with_capacity(queued.len());
+        for args in queued {
+            let mut reply = self.execute_args(args);
+            // Blocking commands behave like they timed out right away
+            if self.is_blocked(client) {
+                self.cancel_blocking(client);
+                reply = Value::NullArray;
+            }
+            replies.push(reply);
+        }
+        Ok(Value::Array(replies))
+    }
+
+    /// DISCARD
+    pub(super) fn redis_discard(&mut self, _args: Args) -> Reply {
+        if self.current_client().multi.take().is_none() {
+            return Err(Value::StaticError("ERR DISCARD without MULTI"));
+        }
+        self.unwatch_all(self.client);
+        Ok(ok())
+    }
+
+    /// WATCH key [key ...]
+    pub(super) fn redis_watch(&mut self, args: Args) -> Reply {
+        if self.current_client().multi.is_some() {
+            return Err(Value::StaticError("ERR WATCH inside MULTI is not allowed"));
+        }
+        let client = self.client;
+        for key in args.into_iter().skip(1) {
+            if self.clients[client].watched.iter().any(|(k, _)| *k == key) {
+                continue;
+            }
+            let exists = self.lookup(&key).is_some();
+            match self.watching.get_mut(&key) {
+                Some(clients) => clients.push(client),
+                None => {
+                    self.watching.insert(key.clone(), vec![client]);
+                }
+            }
+            self.clients[client].watched.push((key, exists));
+        }
+        Ok(ok())
+    }
+
+    /// UNWATCH
+    pub(super) fn redis_unwatch(&mut self, _args: Args) -> Reply {
+        self.unwatch_all(self.client);
+        Ok(ok())
+    }
+
+    /// Forgets the keys `client` watches.
+    pub(super) fn unwatch_all(&mut self, client: usize) {
+        let state = self.client_state(client);
+        let watched = core::mem::take(&mut state.watched);
+        state.dirty = false;
+        for (key, _) in watched {
+            let clients = self.watching.get_mut(&key).expect("watched key");
+            clients.retain(|c| *c != client);
+            if clients.is_empty() {
+                self.watching.remove(&key);
+            }
+        }
+    }
+
+    /// Makes EXEC fail for the connections watching `key`.
+    pub(crate) fn touch_watched_key(&mut self, key: &[u8]) {
+        if let Some(clients) = self.watching.get(key) {
+            for &client in clients {
+                self.clients[client].dirty = true;
+            }
+        }
+    }
+
+    /// Returns `true` if the current connection is inside MULTI.
+    pub(super) fn in_multi(&mut self) -> bool {
+        self.current_client().multi.is_some()
+    }
+
+    /// Makes the next EXEC of the current connection fail, if it is
+    /// inside MULTI.
+    pub(super) fn fail_multi(&mut self) {
+        let client = self.current_client();
+        if client.multi.is_some() {
+            client.multi_failed = true;
+        }
+    }
+
+    /// Adds a command to the transaction of the current connection.
+    pub(super) fn queue_command(&mut self, args: Args) -> Value {
+        let client = self.current_client();
+        client.multi.as_mut().unwrap().push(args);
+        Value::String("QUEUED".into())
+    }
+}
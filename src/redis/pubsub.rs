@@ -0,0 +1,253 @@
+//! Publish/subscribe messaging: SUBSCRIBE, PSUBSCRIBE, PUBLISH and PUBSUB.
+//!
+//! Every worker thread keeps track of the subscriptions of its own
+//! connections. PUBLISH hands a message to the local subscribers right away
+//! and queues it for `take_published`, the server loop forwards it to the
+//! other worker threads which pass it to `deliver`. Like in Redis Cluster,
+//! the numbers returned by PUBLISH and PUBSUB only cover the subscribers of
+//! the worker thread running the command.
+//!
+//! A connection with at least one subscription is in push mode: it may only
+//! (un)subscribe and receives a `message` (or `pmessage`) array for every
+//! matching publication, see `take_messages`.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::glob::glob_match;
+use super::{Args, Reply};
+use crate::resp::serialize::encode_with_buf;
+use crate::resp::value::Value;
+use crate::SashStore;
+
+/// A message published on a channel.
+#[derive(Debug, Clone)]
+pub struct Publication {
+    pub channel: Vec<u8>,
+    pub message: Vec<u8>,
+}
+
+impl SashStore {
+    /// SUBSCRIBE channel [channel ...]
+    pub(super) fn redis_subscribe(&mut self, args: Args) -> Reply {
+        let client = self.client;
+        let mut replies = Vec::with_capacity(args.len() - 1);
+        for channel in args.into_iter().skip(1) {
+            let subscribed = &mut self.current_client().channels;
+            if !subscribed.contains(&channel) {
+                subscribed.push(channel.clone());
+                self.subscribers
+                    .entry(channel.clone())
+                    .or_default()
+                    .push(client);
+            }
+            replies.push(self.confirmation("subscribe", Value::BufBulk(channel)));
+        }
+        self.confirm_all(replies)
+    }
+
+    /// UNSUBSCRIBE [channel [channel ...]]
+    ///
+    /// Without arguments, the connection leaves all its channels.
+    pub(super) fn redis_unsubscribe(&mut self, args: Args) -> Reply {
+        let channels = match args.len() {
+            1 => self.current_client().channels.clone(),
+            _ => args[1..].to_vec(),
+        };
+        if channels.is_empty() {
+            return Ok(self.confirmation("unsubscribe", Value::Null));
+        }
+
+        let client = self.client;
+        let mut replies = Vec::with_capacity(channels.len());
+        for channel in channels {
+            self.current_client().channels.retain(|c| *c != channel);
+            self.leave_channel(client, &channel);
+            replies.push(self.confirmation("unsubscribe", Value::BufBulk(channel)));
+        }
+        self.confirm_all(replies)
+    }
+
+    /// PSUBSCRIBE pattern [pattern ...]
+    pub(super) fn redis_psubscribe(&mut self, args: Args) -> Reply {
+        let client = self.client;
+        let mut replies = Vec::with_capacity(args.len() - 1);
+        for pattern in args.into_iter().skip(1) {
+            let subscribed = &mut self.current_client().patterns;
+            if !subscribed.contains(&pattern) {
+                subscribed.push(pattern.clone());
+                self.pattern_subscribers.push((pattern.clone(), client));
+            }
+            replies.push(self.confirmation("psubscribe", Value::BufBulk(pattern)));
+        }
+        self.confirm_all(replies)
+    }
+
+    /// PUNSUBSCRIBE [pattern [pattern ...]]
+    ///
+    /// Without arguments, the connection leaves all its patterns.
+    pub(super) fn redis_punsubscribe(&mut self, args: Args) -> Reply {
+        let patterns = match args.len() {
+            1 => self.current_client().patterns.clone(),
+            _ => args[1..].to_vec(),
+        };
+        if patterns.is_empty() {
+            return Ok(self.confirmation("punsubscribe", Value::Null));
+        }
+
+        let client = self.client;
+        let mut replies = Vec::with_capacity(patterns.len());
+        for pattern in patterns {
+            self.current_client().patterns.retain(|p| *p != pattern);
+            self.pattern_subscribers
+                .retain(|(p, c)| *c != client || *p != pattern);
+            replies.push(self.confirmation("punsubscribe", Value::BufBulk(pattern)));
+        }
+        self.confirm_all(replies)
+    }
+
+    /// PUBLISH channel message
+    pub(super) fn redis_publish(&mut self, mut args: Args) -> Reply {
+        let publication = Publication {
+            message: args.pop().unwrap(),
+            channel: args.pop().unwrap(),
+        };
+        let receivers = self.deliver(&publication);
+        if self.partitions > 1 {
+            self.published.push(publication);
+        }
+        Ok(Value::Integer(receivers as i64))
+    }
+
+    /// PUBSUB CHANNELS [pattern] | NUMSUB [channel ...]
+    pub(super) fn redis_pubsub(&mut self, args: Args) -> Reply {
+        match args[1].to_ascii_uppercase().as_slice() {
+            b"CHANNELS" if args.len() <= 3 => Ok(Value::Array(
+                self.subscribers
+                    .keys()
+                    .filter(|channel| args.get(2).map_or(true, |p| glob_match(p, channel)))
+                    .map(|channel| Value::BufBulk(channel.clone()))
+                    .collect(),
+            )),
+            b"NUMSUB" => {
+                let mut reply = Vec::with_capacity(2 * (args.len() - 2));
+                for channel in &args[2..] {
+                    let count = self.subscribers.get(channel).map_or(0, |c| c.len());
+                    reply.push(Value::BufBulk(channel.clone()));
+                    reply.push(Value::Integer(count as i64));
+                }
+                Ok(Value::Array(reply))
+            }
+            _ => Err(Value::Error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try PUBSUB HELP.",
+                String::from_utf8_lossy(&args[1])
+            ))),
+        }
+    }
+
+    /// Queues `publication` for all local subscribers of its channel and of
+    /// matching patterns.
+    ///
+    /// Returns the number of messages queued.
+    pub fn deliver(&mut self, publication: &Publication) -> usize {
+        let mut receivers = 0;
+        if let Some(clients) = self.subscribers.get(&publication.channel) {
+            let message = encode_with_buf(
+                Vec::new(),
+                &Value::Array(vec![
+                    Value::BufBulk(b"message".to_vec()),
+                    Value::BufBulk(publication.channel.clone()),
+                    Value::BufBulk(publication.message.clone()),
+                ]),
+            );
+            for &client in clients.iter() {
+                self.messages.push((client, message.clone()));
+            }
+            receivers += clients.len();
+        }
+
+        for (pattern, client) in &self.pattern_subscribers {
+            if glob_match(pattern, &publication.channel) {
+                let message = Value::Array(vec![
+                    Value::BufBulk(b"pmessage".to_vec()),
+                    Value::BufBulk(pattern.clone()),
+                    Value::BufBulk(publication.channel.clone()),
+                    Value::BufBulk(publication.message.clone()),
+                ]);
+                self.messages
+                    .push((*client, encode_with_buf(Vec::new(), &message)));
+                receivers += 1;
+            }
+        }
+        receivers
+    }
+
+    /// Returns the messages published since the last call, they have to
+    /// be delivered to the other worker threads.
+    pub fn take_published(&mut self) -> Vec<Publication> {
+        core::mem::take(&mut self.published)
+    }
+
+    /// Returns the encoded messages for subscribed connections since the
+    /// last call, per connection.
+    pub fn take_messages(&mut self) -> Vec<(usize, Vec<u8>)> {
+        core::mem::take(&mut self.messages)
+    }
+
+    /// Returns `true` if the current connection is subscribed to anything.
+    pub(super) fn in_push_mode(&self) -> bool {
+        self.clients
+            .get(self.client)
+            .map_or(false, |client| client.subscriptions() > 0)
+    }
+
+    /// Moves the queued messages for `client` to the end of `response`.
+    pub(super) fn flush_messages(&mut self, client: usize, response: &mut Vec<u8>) {
+        let (own, others): (Vec<_>, Vec<_>) = core::mem::take(&mut self.messages)
+            .into_iter()
+            .partition(|(c, _)| *c == client);
+        self.messages = others;
+        for (_, message) in own {
+            response.extend(message);
+        }
+    }
+
+    /// Removes `client` from the subscribers of `channel`.
+    pub(super) fn leave_channel(&mut self, client: usize, channel: &[u8]) {
+        let now_empty = match self.subscribers.get_mut(channel) {
+            Some(clients) => {
+                clients.retain(|&c| c != client);
+                clients.is_empty()
+            }
+            None => false,
+        };
+        if now_empty {
+            self.subscribers.remove(channel);
+        }
+    }
+
+    /// The reply confirming a (un)subscription, with the number of
+    /// subscriptions the connection has afterwards.
+    fn confirmation(&mut self, kind: &str, name: Value) -> Value {
+        let count = self.current_client().subscriptions();
+        Value::Array(vec![
+            Value::BufBulk(kind.as_bytes().to_vec()),
+            name,
+            Value::Integer(count as i64),
+        ])
+    }
+
+    /// Replies with the first of `replies`, the others are queued as
+    /// messages and follow right after it (see `handle_resp_request`).
+    fn confirm_all(&mut self, replies: Vec<Value>) -> Reply {
+        let mut replies = replies.into_iter();
+        let first = replies.next().unwrap();
+        for reply in replies {
+            self.messages
+                .push((self.client, encode_with_buf(Vec::new(), &reply)));
+        }
+        Ok(first)
+    }
+}
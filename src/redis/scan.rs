@@ -0,0 +1,99 @@
+//! Argument parsing and iteration shared by the SCAN command family.
+
+use core::hash::{BuildHasher, Hash};
+
+use super::glob::glob_match;
+use super::{parse_int, ERR_NOT_INTEGER, ERR_SYNTAX};
+use crate::indexmap::Index;
+use crate::resp::value::Value;
+
+/// Options of a `*SCAN` command.
+pub(super) struct ScanOptions<'a> {
+    /// Only return elements matching this glob pattern (`MATCH`)
+    pub pattern: Option<&'a [u8]>,
+    /// Hint for the amount of work per call (`COUNT`)
+    pub count: usize,
+    /// Return hash fields without their values (`NOVALUES`, HSCAN only)
+    pub novalues: bool,
+}
+
+impl ScanOptions<'_> {
+    /// Returns `true` if `element` matches the `MATCH` pattern (if any).
+    pub fn matches(&self, element: &[u8]) -> bool {
+        self.pattern
+            .map_or(true, |pattern| glob_match(pattern, element))
+    }
+}
+
+/// Parses a scan cursor, any unsigned 64-bit number is valid.
+pub(super) fn parse_cursor(cursor: &[u8]) -> Result<u64, Value> {
+    core::str::from_utf8(cursor)
+        .ok()
+        .filter(|cursor| cursor.bytes().all(|c| c.is_ascii_digit()))
+        .and_then(|cursor| cursor.parse().ok())
+        .ok_or(Value::StaticError("ERR invalid cursor"))
+}
+
+/// Continues the iteration over `index` at `cursor` (see `Index::scan`),
+/// calling `f` on every element visited.
+///
+/// Like in Redis, `count` is the number of elements to look at before
+/// `MATCH` filters them (`f` returns whether an element counts), at most
+/// ten times as many buckets are visited to find them. Returns the cursor
+/// to continue at, `0` once the iteration is complete.
+pub(super) fn scan_index<K, V, S, F>(
+    index: &Index<K, V, S>,
+    mut cursor: u64,
+    count: usize,
+    mut f: F,
+) -> u64
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+    F: FnMut(&K, &V) -> bool,
+{
+    let mut found = 0;
+    let mut buckets = 0;
+    loop {
+        cursor = index.scan(cursor, |key, value| {
+            if f(key, value) {
+                found += 1;
+            }
+        });
+        buckets += 1;
+        if cursor == 0 || found >= count || buckets >= count.saturating_mul(10) {
+            return cursor;
+        }
+    }
+}
+
+/// Parses the options following the cursor of a `*SCAN` command.
+pub(super) fn parse_scan_options(options: &[Vec<u8>]) -> Result<ScanOptions, Value> {
+    let mut scan = ScanOptions {
+        pattern: None,
+        count: 10,
+        novalues: false,
+    };
+
+    let mut i = 0;
+    while i < options.len() {
+        match options[i].to_ascii_uppercase().as_slice() {
+            b"MATCH" if i + 1 < options.len() => {
+                scan.pattern = Some(&options[i + 1]);
+                i += 1;
+            }
+            b"COUNT" if i + 1 < options.len() => {
+                scan.count = match parse_int(&options[i + 1]) {
+                    Some(count) if count > 0 => count as usize,
+                    Some(_) => return Err(Value::StaticError(ERR_SYNTAX)),
+                    None => return Err(Value::StaticError(ERR_NOT_INTEGER)),
+                };
+                i += 1;
+            }
+            b"NOVALUES" => scan.novalues = true,
+            _ => return Err(Value::StaticError(ERR_SYNTAX)),
+        }
+        i += 1;
+    }
+    Ok(scan)
+}
@@ -0,0 +1,822 @@
+//! EVAL: atomic server-side scripts in a tiny language of our own.
+//!
+//! We can't embed Lua, so scripts are written in a small expression
+//! language that is parsed and interpreted right here. A script runs like
+//! any other command: nothing else executes on the partition in between,
+//! so compare-then-set and similar read-modify-write logic is atomic.
+//! Writes done before a script fails are not rolled back, just like in
+//! Redis.
+//!
+//! ```text
+//! # Set KEYS[1] to ARGV[2] if it currently holds ARGV[1]
+//! if call("GET", KEYS[1]) == ARGV[1] {
+//!     call("SET", KEYS[1], ARGV[2]);
+//!     return 1;
+//! }
+//! return 0;
+//! ```
+//!
+//! The language:
+//!
+//! * values are `nil`, `true`/`false`, 64-bit integers, byte strings
+//!   (`"..."` with `\n`, `\r`, `\t`, `\"`, `\\` and `\xHH` escapes) and
+//!   arrays (`[a, b]`, indexed from 1 like in Lua: `KEYS[1]`)
+//! * statements: `let x = e;`, `x = e;`, `if e { .. } else if e { .. } else
+//!   { .. }`, `while e { .. }`, `return e;` and `e;`, all variables share
+//!   one scope
+//! * operators, loosest binding first: `||`, `&&`, comparisons (`==`, `!=`,
+//!   `<`, `<=`, `>`, `>=`), `..` (concatenation), `+ -`, `* / %`, unary
+//!   `- !`, indexing
+//! * `nil`, `false` and `0` are false, everything else is true; `&&` and
+//!   `||` return one of their operands
+//! * `==` compares an integer and a string by the decimal form of the
+//!   integer, so `call("GET", k) == 5` works
+//! * functions: `call(command, args..)` runs a command and fails the
+//!   script on an error reply, `int(x)` parses a string (`nil` if it is not
+//!   an integer), `str(x)` and `len(x)`
+//! * `#` starts a comment that runs until the end of the line
+//!
+//! Command replies become values (status replies are strings) and the
+//! returned value becomes the reply: `true` is `1`, `false` is `nil`.
+//!
+//! Every statement and expression evaluated costs an instruction,
+//! concatenation and reading a variable one more per KiB they copy. A
+//! script that runs out of its `SCRIPT_INSTRUCTION_BUDGET` is aborted.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{parse_int, Args, Reply, ERR_NOT_INTEGER};
+use crate::resp::value::Value;
+use crate::SashStore;
+
+/// Number of instructions a script may execute.
+const SCRIPT_INSTRUCTION_BUDGET: usize = 100_000;
+
+/// Maximum nesting of blocks and expressions, keeps the parser and the
+/// interpreter from overflowing the stack.
+const MAX_NESTING: usize = 64;
+
+/// Operators and punctuation, longer ones first.
+const PUNCTUATION: [&str; 24] = [
+    "==", "!=", "<=", ">=", "&&", "||", "..", "+", "-", "*", "/", "%", "<", ">", "!", "=", "(",
+    ")", "{", "}", "[", "]", ",", ";",
+];
+
+impl SashStore {
+    /// EVAL script numkeys [key [key ...]] [arg [arg ...]]
+    ///
+    /// Like any other command the script only sees the keys of the
+    /// partition serving the connection.
+    pub(super) fn redis_eval(&mut self, mut args: Args) -> Reply {
+        let numkeys = match parse_int(&args[2]) {
+            Some(numkeys) if numkeys < 0 => {
+                return Err(Value::StaticError("ERR Number of keys can't be negative"))
+            }
+            Some(numkeys) if numkeys as usize > args.len() - 3 => {
+                return Err(Value::StaticError(
+                    "ERR Number of keys can't be greater than number of args",
+                ))
+            }
+            Some(numkeys) => numkeys as usize,
+            None => return Err(Value::StaticError(ERR_NOT_INTEGER)),
+        };
+        let program = Parser::new(&args[1])
+            .and_then(|mut parser| parser.program())
+            .map_err(|(at, message)| {
+                Value::Error(format!(
+                    "ERR syntax error in script at byte {}: {}",
+                    at, message
+                ))
+            })?;
+        let argv = args.split_off(3 + numkeys);
+        let keys = args.split_off(3);
+
+        let mut interpreter = Interpreter {
+            store: self,
+            variables: vec![
+                (String::from("KEYS"), Val::array(keys)),
+                (String::from("ARGV"), Val::array(argv)),
+            ],
+            budget: SCRIPT_INSTRUCTION_BUDGET,
+        };
+        match interpreter.block(&program)? {
+            Flow::Return(value) => Ok(value.into_reply()),
+            Flow::Next => Ok(Value::Null),
+        }
+    }
+}
+
+/// A value a script works with.
+#[derive(Debug, Clone, PartialEq)]
+enum Val {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Str(Vec<u8>),
+    Array(Vec<Val>),
+}
+
+impl Val {
+    fn array(strings: Vec<Vec<u8>>) -> Val {
+        Val::Array(strings.into_iter().map(Val::Str).collect())
+    }
+
+    /// Approximate number of bytes the value takes up in memory.
+    fn size(&self) -> usize {
+        let inline = core::mem::size_of::<Val>();
+        match self {
+            Val::Str(s) => inline + s.len(),
+            Val::Array(items) => inline + items.iter().map(Val::size).sum::<usize>(),
+            _ => inline,
+        }
+    }
+
+    fn is_true(&self) -> bool {
+        !matches!(self, Val::Nil | Val::Bool(false) | Val::Int(0))
+    }
+
+    /// The bytes of a string or the decimal form of an integer.
+    fn to_bytes(&self) -> Result<Vec<u8>, Value> {
+        match self {
+            Val::Str(s) => Ok(s.clone()),
+            Val::Int(i) => Ok(format!("{}", i).into_bytes()),
+            other => Err(script_error(&format!(
+                "expected a string or an integer, got {}",
+                other.type_name()
+            ))),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Val::Nil => "nil",
+            Val::Bool(_) => "a boolean",
+            Val::Int(_) => "an integer",
+            Val::Str(_) => "a string",
+            Val::Array(_) => "an array",
+        }
+    }
+
+    /// Converts a command reply, error replies fail the script.
+    fn from_reply(reply: Value) -> Result<Val, Value> {
+        match reply {
+            Value::Null | Value::NullArray => Ok(Val::Nil),
+            Value::Integer(i) => Ok(Val::Int(i)),
+            Value::String(s) | Value::Bulk(s) => Ok(Val::Str(s.into_bytes())),
+            Value::BufBulk(bytes) => Ok(Val::Str(bytes)),
+            Value::Array(values) => values
+                .into_iter()
+                .map(Val::from_reply)
+                .collect::<Result<_, _>>()
+                .map(Val::Array),
+            error => Err(error),
+        }
+    }
+
+    fn into_reply(self) -> Value {
+        match self {
+            Val::Nil | Val::Bool(false) => Value::Null,
+            Val::Bool(true) => Value::Integer(1),
+            Val::Int(i) => Value::Integer(i),
+            Val::Str(bytes) => Value::BufBulk(bytes),
+            Val::Array(values) => Value::Array(values.into_iter().map(Val::into_reply).collect()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Concat,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Val),
+    Var(String),
+    List(Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Let(String, Expr),
+    Assign(String, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Expr),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Str(Vec<u8>),
+    Ident(String),
+    Punct(&'static str),
+    End,
+}
+
+/// A parse error: byte offset in the script and what went wrong.
+type ParseError = (usize, String);
+
+/// Splits a script into tokens, each with its byte offset.
+fn tokenize(src: &[u8]) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < src.len() {
+        let start = i;
+        let c = src[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if c == b'#' {
+            while i < src.len() && src[i] != b'\n' {
+                i += 1;
+            }
+        } else if c.is_ascii_digit() {
+            while i < src.len() && src[i].is_ascii_digit() {
+                i += 1;
+            }
+            let number = core::str::from_utf8(&src[start..i])
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| (start, String::from("integer out of range")))?;
+            tokens.push((start, Token::Int(number)));
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while i < src.len() && (src[i].is_ascii_alphanumeric() || src[i] == b'_') {
+                i += 1;
+            }
+            let name = String::from_utf8_lossy(&src[start..i]).into_owned();
+            tokens.push((start, Token::Ident(name)));
+        } else if c == b'"' {
+            let (string, end) = string_literal(src, i)?;
+            tokens.push((start, Token::Str(string)));
+            i = end;
+        } else {
+            let punct = PUNCTUATION
+                .iter()
+                .find(|p| src[i..].starts_with(p.as_bytes()))
+                .ok_or_else(|| (start, format!("unexpected character '{}'", c as char)))?;
+            tokens.push((start, Token::Punct(punct)));
+            i += punct.len();
+        }
+    }
+    tokens.push((src.len(), Token::End));
+    Ok(tokens)
+}
+
+/// Reads the string literal starting with the quote at `src[start]`,
+/// returns its bytes and the offset after the closing quote.
+fn string_literal(src: &[u8], start: usize) -> Result<(Vec<u8>, usize), ParseError> {
+    let mut string = Vec::new();
+    let mut i = start + 1;
+    loop {
+        match src.get(i) {
+            None => return Err((start, String::from("unterminated string"))),
+            Some(b'"') => return Ok((string, i + 1)),
+            Some(b'\\') => {
+                let escaped = match src.get(i + 1) {
+                    Some(b'n') => b'\n',
+                    Some(b'r') => b'\r',
+                    Some(b't') => b'\t',
+                    Some(b'"') => b'"',
+                    Some(b'\\') => b'\\',
+                    Some(b'x') => {
+                        let hex = src
+                            .get(i + 2..i + 4)
+                            .and_then(|hex| core::str::from_utf8(hex).ok())
+                            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                            .ok_or_else(|| (i, String::from("invalid \\x escape")))?;
+                        i += 2;
+                        hex
+                    }
+                    _ => return Err((i, String::from("invalid escape"))),
+                };
+                string.push(escaped);
+                i += 2;
+            }
+            Some(&c) => {
+                string.push(c);
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Recursive descent parser, one method per grammar rule.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn new(src: &[u8]) -> Result<Parser, ParseError> {
+        Ok(Parser {
+            tokens: tokenize(src)?,
+            pos: 0,
+            depth: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].1.clone();
+        if token != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Consumes the punctuation `p` if it comes next.
+    fn eat(&mut self, p: &str) -> bool {
+        match self.peek() {
+            Token::Punct(q) if *q == p => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, p: &str) -> Result<(), ParseError> {
+        if self.eat(p) {
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", p))
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, ParseError> {
+        Err((self.tokens[self.pos].0, String::from(message)))
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Token::Ident(name) => name == keyword,
+            _ => false,
+        }
+    }
+
+    fn program(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut statements = Vec::new();
+        while *self.peek() != Token::End {
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.expect("{")?;
+        self.enter()?;
+        let mut statements = Vec::new();
+        while !self.eat("}") {
+            if *self.peek() == Token::End {
+                return self.error("expected '}'");
+            }
+            statements.push(self.statement()?);
+        }
+        self.depth -= 1;
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        if self.is_keyword("if") {
+            return self.if_statement();
+        }
+        if self.is_keyword("while") {
+            self.next();
+            let condition = self.expression()?;
+            return Ok(Stmt::While(condition, self.block()?));
+        }
+
+        let statement = if self.is_keyword("let") {
+            self.next();
+            let name = self.identifier()?;
+            self.expect("=")?;
+            Stmt::Let(name, self.expression()?)
+        } else if self.is_keyword("return") {
+            self.next();
+            Stmt::Return(self.expression()?)
+        } else if self.tokens[self.pos + 1].1 == Token::Punct("=") {
+            let name = self.identifier()?;
+            self.next();
+            Stmt::Assign(name, self.expression()?)
+        } else {
+            Stmt::Expr(self.expression()?)
+        };
+        // The last statement of a block doesn't need a semicolon
+        match self.peek() {
+            Token::Punct("}") | Token::End => Ok(statement),
+            _ => self.expect(";").map(|_| statement),
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.next();
+        let condition = self.expression()?;
+        let then = self.block()?;
+        if !self.is_keyword("else") {
+            return Ok(Stmt::If(condition, then, Vec::new()));
+        }
+        self.next();
+        let otherwise = if self.is_keyword("if") {
+            self.enter()?;
+            let nested = vec![self.if_statement()?];
+            self.depth -= 1;
+            nested
+        } else {
+            self.block()?
+        };
+        Ok(Stmt::If(condition, then, otherwise))
+    }
+
+    fn identifier(&mut self) -> Result<String, ParseError> {
+        let at = self.pos;
+        match self.next() {
+            Token::Ident(name) => Ok(name),
+            _ => {
+                self.pos = at;
+                self.error("expected a name")
+            }
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, ParseError> {
+        self.enter()?;
+        let expr = self.binary(0);
+        self.depth -= 1;
+        expr
+    }
+
+    /// Parses binary operators of precedence `level` and up.
+    fn binary(&mut self, level: usize) -> Result<Expr, ParseError> {
+        const LEVELS: [&[(&str, BinOp)]; 6] = [
+            &[("||", BinOp::Or)],
+            &[("&&", BinOp::And)],
+            &[
+                ("==", BinOp::Eq),
+                ("!=", BinOp::Ne),
+                ("<=", BinOp::Le),
+                (">=", BinOp::Ge),
+                ("<", BinOp::Lt),
+                (">", BinOp::Gt),
+            ],
+            &[("..", BinOp::Concat)],
+            &[("+", BinOp::Add), ("-", BinOp::Sub)],
+            &[("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Rem)],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Token::Punct(p) => LEVELS[level].iter().find(|(q, _)| q == p).map(|op| op.1),
+                _ => None,
+            };
+            match op {
+                Some(op) => {
+                    self.next();
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(op, Box::new(left), Box::new(right));
+                }
+                None => return Ok(left),
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat("!") {
+            self.enter()?;
+            let operand = self.unary()?;
+            self.depth -= 1;
+            return Ok(Expr::Not(Box::new(operand)));
+        }
+        if self.eat("-") {
+            self.enter()?;
+            let operand = self.unary()?;
+            self.depth -= 1;
+            return Ok(Expr::Neg(Box::new(operand)));
+        }
+
+        let mut expr = self.primary()?;
+        while self.eat("[") {
+            let index = self.expression()?;
+            self.expect("]")?;
+            expr = Expr::Index(Box::new(expr), Box::new(index));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let at = self.pos;
+        match self.next() {
+            Token::Int(i) => Ok(Expr::Literal(Val::Int(i))),
+            Token::Str(s) => Ok(Expr::Literal(Val::Str(s))),
+            Token::Ident(name) => match name.as_str() {
+                "nil" => Ok(Expr::Literal(Val::Nil)),
+                "true" => Ok(Expr::Literal(Val::Bool(true))),
+                "false" => Ok(Expr::Literal(Val::Bool(false))),
+                _ if self.eat("(") => Ok(Expr::Call(name, self.list(")")?)),
+                _ => Ok(Expr::Var(name)),
+            },
+            Token::Punct("(") => {
+                let expr = self.expression()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Punct("[") => Ok(Expr::List(self.list("]")?)),
+            _ => {
+                self.pos = at;
+                self.error("expected an expression")
+            }
+        }
+    }
+
+    /// Comma separated expressions up to the punctuation `end`.
+    fn list(&mut self, end: &str) -> Result<Vec<Expr>, ParseError> {
+        let mut items = Vec::new();
+        if self.eat(end) {
+            return Ok(items);
+        }
+        loop {
+            items.push(self.expression()?);
+            if self.eat(end) {
+                return Ok(items);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), ParseError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return self.error("too deeply nested");
+        }
+        Ok(())
+    }
+}
+
+/// What to do after a statement.
+enum Flow {
+    Next,
+    Return(Val),
+}
+
+/// Runs a parsed script against the store.
+///
+/// The parser bounds the nesting of the program, so recursing over it
+/// can't overflow the stack.
+struct Interpreter<'a> {
+    store: &'a mut SashStore,
+    variables: Vec<(String, Val)>,
+    /// Instructions left
+    budget: usize,
+}
+
+impl Interpreter<'_> {
+    /// Spends `cost` instructions.
+    fn spend(&mut self, cost: usize) -> Result<(), Value> {
+        if cost > self.budget {
+            return Err(Value::Error(format!(
+                "ERR script exceeded its budget of {} instructions",
+                SCRIPT_INSTRUCTION_BUDGET
+            )));
+        }
+        self.budget -= cost;
+        Ok(())
+    }
+
+    fn block(&mut self, statements: &[Stmt]) -> Result<Flow, Value> {
+        for statement in statements {
+            if let Flow::Return(value) = self.statement(statement)? {
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Result<Flow, Value> {
+        self.spend(1)?;
+        match statement {
+            Stmt::Let(name, expr) => {
+                let value = self.eval(expr)?;
+                match self.variables.iter_mut().find(|(n, _)| n == name) {
+                    Some(variable) => variable.1 = value,
+                    None => self.variables.push((name.clone(), value)),
+                }
+            }
+            Stmt::Assign(name, expr) => {
+                let value = self.eval(expr)?;
+                match self.variables.iter_mut().find(|(n, _)| n == name) {
+                    Some(variable) => variable.1 = value,
+                    None => return Err(script_error(&format!("unknown variable {}", name))),
+                }
+            }
+            Stmt::If(condition, then, otherwise) => {
+                let branch = if self.eval(condition)?.is_true() {
+                    then
+                } else {
+                    otherwise
+                };
+                return self.block(branch);
+            }
+            Stmt::While(condition, body) => {
+                while self.eval(condition)?.is_true() {
+                    if let Flow::Return(value) = self.block(body)? {
+                        return Ok(Flow::Return(value));
+                    }
+                }
+            }
+            Stmt::Return(expr) => return Ok(Flow::Return(self.eval(expr)?)),
+            Stmt::Expr(expr) => {
+                self.eval(expr)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Val, Value> {
+        self.spend(1)?;
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Var(name) => {
+                let size = match self.variables.iter().find(|(n, _)| n == name) {
+                    Some((_, value)) => value.size(),
+                    None => return Err(script_error(&format!("unknown variable {}", name))),
+                };
+                // Reading a variable copies its value
+                self.spend(size / 1024)?;
+                let (_, value) = self.variables.iter().find(|(n, _)| n == name).unwrap();
+                Ok(value.clone())
+            }
+            Expr::List(items) => items
+                .iter()
+                .map(|item| self.eval(item))
+                .collect::<Result<_, _>>()
+                .map(Val::Array),
+            Expr::Index(array, index) => match (self.eval(array)?, self.eval(index)?) {
+                (Val::Array(items), Val::Int(i)) if i >= 1 => {
+                    Ok(items.into_iter().nth(i as usize - 1).unwrap_or(Val::Nil))
+                }
+                (Val::Array(_), Val::Int(_)) => Ok(Val::Nil),
+                (other, _) => Err(script_error(&format!("can't index {}", other.type_name()))),
+            },
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(name, args)
+            }
+            Expr::Not(operand) => Ok(Val::Bool(!self.eval(operand)?.is_true())),
+            Expr::Neg(operand) => match self.eval(operand)? {
+                Val::Int(i) => i.checked_neg().map(Val::Int).ok_or_else(overflow),
+                other => Err(script_error(&format!("can't negate {}", other.type_name()))),
+            },
+            Expr::Binary(BinOp::And, left, right) => {
+                let left = self.eval(left)?;
+                if left.is_true() {
+                    self.eval(right)
+                } else {
+                    Ok(left)
+                }
+            }
+            Expr::Binary(BinOp::Or, left, right) => {
+                let left = self.eval(left)?;
+                if left.is_true() {
+                    Ok(left)
+                } else {
+                    self.eval(right)
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                self.binary(*op, left, right)
+            }
+        }
+    }
+
+    fn binary(&mut self, op: BinOp, left: Val, right: Val) -> Result<Val, Value> {
+        match op {
+            BinOp::Eq => return Ok(Val::Bool(equal(&left, &right))),
+            BinOp::Ne => return Ok(Val::Bool(!equal(&left, &right))),
+            BinOp::Concat => {
+                let mut bytes = left.to_bytes()?;
+                let right = right.to_bytes()?;
+                self.spend((bytes.len() + right.len()) / 1024)?;
+                bytes.extend(right);
+                return Ok(Val::Str(bytes));
+            }
+            _ => {}
+        }
+
+        let comparison = matches!(op, BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge);
+        let ordering = match (&left, &right) {
+            (Val::Int(a), Val::Int(b)) => a.cmp(b),
+            (Val::Str(a), Val::Str(b)) if comparison => a.cmp(b),
+            _ => {
+                return Err(script_error(&format!(
+                    "can't apply {:?} to {} and {}",
+                    op,
+                    left.type_name(),
+                    right.type_name()
+                )))
+            }
+        };
+        let (a, b) = match (left, right) {
+            (Val::Int(a), Val::Int(b)) => (a, b),
+            _ => (0, 0),
+        };
+        let result = match op {
+            BinOp::Lt => return Ok(Val::Bool(ordering.is_lt())),
+            BinOp::Le => return Ok(Val::Bool(ordering.is_le())),
+            BinOp::Gt => return Ok(Val::Bool(ordering.is_gt())),
+            BinOp::Ge => return Ok(Val::Bool(ordering.is_ge())),
+            BinOp::Add => a.checked_add(b),
+            BinOp::Sub => a.checked_sub(b),
+            BinOp::Mul => a.checked_mul(b),
+            BinOp::Div | BinOp::Rem if b == 0 => return Err(script_error("division by zero")),
+            BinOp::Div => a.checked_div(b),
+            BinOp::Rem => a.checked_rem(b),
+            _ => unreachable!("handled above"),
+        };
+        result.map(Val::Int).ok_or_else(overflow)
+    }
+
+    /// Calls one of the built-in functions.
+    fn call(&mut self, name: &str, args: Vec<Val>) -> Result<Val, Value> {
+        match (name, args.as_slice()) {
+            ("call", [command, ..]) => {
+                // Commands flagged `noscript` block, change the state of the
+                // connection or would run a script from a script
+                let command = self.store.command(&command.to_bytes()?);
+                if command.map_or(false, |command| command.has_flag("noscript")) {
+                    return Err(Value::StaticError(
+                        "ERR This command is not allowed from scripts",
+                    ));
+                }
+                let args = args
+                    .iter()
+                    .map(Val::to_bytes)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Val::from_reply(self.store.execute_args(args))
+            }
+            ("int", [Val::Int(i)]) => Ok(Val::Int(*i)),
+            ("int", [Val::Str(s)]) => Ok(parse_int(s).map_or(Val::Nil, Val::Int)),
+            ("int", [Val::Nil]) => Ok(Val::Nil),
+            ("str", [value]) => value.to_bytes().map(Val::Str),
+            ("len", [Val::Str(s)]) => Ok(Val::Int(s.len() as i64)),
+            ("len", [Val::Array(items)]) => Ok(Val::Int(items.len() as i64)),
+            ("len", [Val::Nil]) => Ok(Val::Int(0)),
+            ("call", _) | ("int", _) | ("str", _) | ("len", _) => {
+                Err(script_error(&format!("wrong arguments for {}()", name)))
+            }
+            _ => Err(script_error(&format!("unknown function {}()", name))),
+        }
+    }
+}
+
+/// `==` of the script language.
+fn equal(left: &Val, right: &Val) -> bool {
+    match (left, right) {
+        (Val::Int(i), Val::Str(s)) | (Val::Str(s), Val::Int(i)) => {
+            format!("{}", i).as_bytes() == s.as_slice()
+        }
+        _ => left == right,
+    }
+}
+
+fn script_error(message: &str) -> Value {
+    Value::Error(format!("ERR script error: {}", message))
+}
+
+fn overflow() -> Value {
+    script_error("integer overflow")
+}
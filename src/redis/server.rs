@@ -0,0 +1,443 @@
+//! Commands about the server itself: CONFIG and INFO.
+//!
+//! INFO reports the totals of all partitions: every partition keeps its
+//! statistics in its own `Stats` and reads the ones of the others, as of
+//! their last `sample_stats`.
+//!
+//! The CONFIG parameters are the command-line flags the server was started with
+//! and the tuning knobs of the key space table. Only the latter can be
+//! changed at runtime, and like everything else a worker thread keeps, they
+//! only change for the partition serving the connection.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Display;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use super::glob::glob_match;
+use super::{ok, Args, Reply};
+use crate::arch::arch::{memory_usage, unix_time_ms};
+use crate::arch::{NumaTopology, Transport};
+use crate::indexmap::hash::HashFunction;
+use crate::resp::value::Value;
+use crate::SashStore;
+
+/// The parameters CONFIG knows, in the order CONFIG GET reports them.
+const PARAMETERS: [&str; 10] = [
+    "threads",
+    "capacity",
+    "thread-mapping",
+    "transport",
+    "incoming-tcp-connections",
+    "port",
+    "hasher",
+    "max-load",
+    "min-load",
+    "growth-policy",
+];
+
+/// The Redis version whose commands and replies we follow, clients look at
+/// it to decide what they can use.
+const REDIS_VERSION: &str = "6.0.0";
+
+/// The sections of INFO, in the order they are reported.
+const SECTIONS: [&str; 5] = ["server", "clients", "memory", "stats", "keyspace"];
+
+/// Sections of INFO only reported when asked for by name, computing them
+/// walks the whole table of the partition.
+const EXPENSIVE_SECTIONS: [&str; 1] = ["hashtable"];
+
+/// Values CONFIG SET accepts for `max-load`, lower ones waste memory and
+/// higher ones make probe sequences long.
+const MAX_LOAD_RANGE: (f64, f64) = (0.1, 0.95);
+
+/// Values CONFIG SET accepts for `min-load`, 0 never shrinks the keyspace.
+const MIN_LOAD_RANGE: (f64, f64) = (0.0, 0.5);
+
+/// Number of samples `instantaneous_ops_per_sec` is averaged over.
+const OPS_SAMPLES: usize = 16;
+
+/// Statistics reported by INFO.
+///
+/// Every partition updates its own and reads the ones of the others to
+/// report totals. The counters go back to zero with CONFIG RESETSTAT, the
+/// rest is refreshed by `sample_stats`.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Commands executed (queued ones when EXEC runs them), including the
+    /// ones that failed
+    pub commands_processed: AtomicU64,
+    /// Lookups that found their key
+    pub keyspace_hits: AtomicU64,
+    /// Lookups that didn't find their key
+    pub keyspace_misses: AtomicU64,
+    /// Keys reclaimed by the active expiry cycle
+    pub expired_keys: AtomicU64,
+    /// Commands per second, averaged over the last `OPS_SAMPLES` samples
+    pub ops_per_sec: AtomicU64,
+    /// Number of keys in the table
+    pub keys: AtomicUsize,
+    /// Number of keys with a deadline
+    pub expires: AtomicUsize,
+    /// Average time to live (in ms) of the keys with a deadline
+    pub avg_ttl: AtomicU64,
+    /// Number of buckets of the table
+    pub capacity: AtomicUsize,
+    /// Number of open connections
+    pub connected_clients: AtomicUsize,
+    /// Number of connections waiting in a blocking command
+    pub blocked_clients: AtomicUsize,
+}
+
+impl Stats {
+    /// Counts a lookup of a key.
+    pub fn count_lookup(&self, hit: bool) {
+        let counter = if hit {
+            &self.keyspace_hits
+        } else {
+            &self.keyspace_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn reset(&self) {
+        self.commands_processed.store(0, Ordering::Relaxed);
+        self.keyspace_hits.store(0, Ordering::Relaxed);
+        self.keyspace_misses.store(0, Ordering::Relaxed);
+        self.expired_keys.store(0, Ordering::Relaxed);
+        self.ops_per_sec.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Turns the number of commands processed into commands per second, like
+/// Redis does every 100 ms.
+#[derive(Debug, Default)]
+pub struct OpsSampler {
+    /// Unix time (in ms) of the previous sample, 0 before the first one
+    at: u64,
+    /// Commands processed at the time of the previous sample
+    commands: u64,
+    /// Commands per second between consecutive samples, a ring buffer
+    samples: [u64; OPS_SAMPLES],
+    next: usize,
+}
+
+impl OpsSampler {
+    /// Records that `commands` were processed by `now`, returns the average
+    /// of the samples.
+    fn sample(&mut self, now: u64, commands: u64) -> u64 {
+        let elapsed = now.saturating_sub(self.at);
+        if self.at != 0 && elapsed > 0 {
+            // Goes backwards after CONFIG RESETSTAT
+            self.samples[self.next] = commands.saturating_sub(self.commands) * 1000 / elapsed;
+            self.next = (self.next + 1) % OPS_SAMPLES;
+        }
+        self.at = now;
+        self.commands = commands;
+        self.samples.iter().sum::<u64>() / OPS_SAMPLES as u64
+    }
+}
+
+impl SashStore {
+    /// CONFIG GET parameter [parameter ...]
+    /// CONFIG SET parameter value [parameter value ...]
+    /// CONFIG RESETSTAT
+    pub(super) fn redis_config(&mut self, args: Args) -> Reply {
+        let subcommand = args[1].to_ascii_uppercase();
+        match subcommand.as_slice() {
+            b"GET" if args.len() > 2 => Ok(self.config_get(&args[2..])),
+            b"SET" if args.len() > 2 && args.len() % 2 == 0 => self.config_set(&args[2..]),
+            b"RESETSTAT" if args.len() == 2 => {
+                for stats in self.partition_stats.iter() {
+                    stats.reset();
+                }
+                Ok(ok())
+            }
+            _ => Err(Value::Error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try CONFIG HELP.",
+                String::from_utf8_lossy(&args[1])
+            ))),
+        }
+    }
+
+    /// INFO [section [section ...]]
+    ///
+    /// The `hashtable` section reports the probe lengths of the table of
+    /// this partition and is only included when asked for by name.
+    pub(super) fn redis_info(&mut self, args: Args) -> Reply {
+        self.publish_stats();
+        let wanted: Vec<Vec<u8>> = args[1..].iter().map(|s| s.to_ascii_lowercase()).collect();
+        let everything = wanted.is_empty()
+            || wanted
+                .iter()
+                .any(|s| s == b"all" || s == b"default" || s == b"everything");
+
+        let mut info = String::new();
+        for section in SECTIONS.iter().chain(EXPENSIVE_SECTIONS.iter()) {
+            let named = wanted.iter().any(|s| s == section.as_bytes());
+            if named || (everything && SECTIONS.contains(section)) {
+                if !info.is_empty() {
+                    info.push_str("\r\n");
+                }
+                self.info_section(section, &mut info);
+            }
+        }
+        Ok(Value::BufBulk(info.into_bytes()))
+    }
+
+    /// Appends the section `name` (one of `SECTIONS`) of INFO to `info`.
+    fn info_section(&self, name: &str, info: &mut String) {
+        let total = |counter: fn(&Stats) -> &AtomicU64| -> u64 {
+            self.partition_stats
+                .iter()
+                .map(|stats| counter(stats).load(Ordering::Relaxed))
+                .sum()
+        };
+        let count = |counter: fn(&Stats) -> &AtomicUsize| -> usize {
+            self.partition_stats
+                .iter()
+                .map(|stats| counter(stats).load(Ordering::Relaxed))
+                .sum()
+        };
+
+        let mut title = String::from(name);
+        title[..1].make_ascii_uppercase();
+        info.push_str(&format!("# {}\r\n", title));
+        match name {
+            "server" => {
+                let uptime = unix_time_ms().saturating_sub(self.started_at) / 1000;
+                field(info, "redis_version", REDIS_VERSION);
+                field(info, "sashstore_version", env!("CARGO_PKG_VERSION"));
+                field(info, "redis_mode", "standalone");
+                field(info, "tcp_port", self.config.port + self.partition);
+                field(info, "uptime_in_seconds", uptime);
+                field(info, "uptime_in_days", uptime / 86400);
+                field(info, "partitions", self.partitions);
+                field(info, "partition", self.partition);
+            }
+            "clients" => {
+                field(info, "connected_clients", count(|s| &s.connected_clients));
+                field(info, "blocked_clients", count(|s| &s.blocked_clients));
+            }
+            "memory" => {
+                let (allocated, resident) = memory_usage();
+                field(info, "used_memory", allocated);
+                field(info, "used_memory_human", human_bytes(allocated));
+                field(info, "used_memory_rss", resident);
+                field(info, "used_memory_rss_human", human_bytes(resident));
+                field(info, "mem_allocator", "jemalloc");
+            }
+            "stats" => {
+                let commands = total(|s| &s.commands_processed);
+                field(info, "total_commands_processed", commands);
+                field(info, "instantaneous_ops_per_sec", total(|s| &s.ops_per_sec));
+                field(info, "keyspace_hits", total(|s| &s.keyspace_hits));
+                field(info, "keyspace_misses", total(|s| &s.keyspace_misses));
+                field(info, "expired_keys", total(|s| &s.expired_keys));
+            }
+            "hashtable" => {
+                // The tables of the other partitions belong to other threads
+                let stats = self.map.stats();
+                let probe_lengths: Vec<String> = stats
+                    .probe_lengths
+                    .iter()
+                    .enumerate()
+                    .map(|(n, count)| format!("{}={}", n + 1, count))
+                    .collect();
+                field(info, "partition", self.partition);
+                field(info, "keys", stats.len);
+                field(info, "capacity", stats.capacity);
+                field(info, "tombstones", stats.tombstones);
+                field(info, "probe_lengths", probe_lengths.join(","));
+                field(
+                    info,
+                    "avg_probe_length",
+                    format!("{:.3}", stats.average_probe_length()),
+                );
+                field(info, "avg_miss_cost", format!("{:.3}", stats.miss_cost));
+                field(info, "longest_cluster", stats.longest_cluster);
+                field(info, "table_bytes", stats.bytes);
+                field(info, "table_bytes_human", human_bytes(stats.bytes));
+            }
+            _ => {
+                let keys = count(|s| &s.keys);
+                if keys > 0 {
+                    let expires = count(|s| &s.expires);
+                    let ttl_sum: u64 = self
+                        .partition_stats
+                        .iter()
+                        .map(|stats| {
+                            let expires = stats.expires.load(Ordering::Relaxed) as u64;
+                            expires * stats.avg_ttl.load(Ordering::Relaxed)
+                        })
+                        .sum();
+                    let avg_ttl = ttl_sum / expires.max(1) as u64;
+                    field(
+                        info,
+                        "db0",
+                        format!("keys={},expires={},avg_ttl={}", keys, expires, avg_ttl),
+                    );
+                }
+                for (partition, stats) in self.partition_stats.iter().enumerate() {
+                    let keys = stats.keys.load(Ordering::Relaxed);
+                    let capacity = stats.capacity.load(Ordering::Relaxed);
+                    let load = keys as f64 / capacity.max(1) as f64;
+                    field(
+                        info,
+                        &format!("partition{}", partition),
+                        format!("keys={},capacity={},load={:.3}", keys, capacity, load),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Statistics of the partition.
+    pub(crate) fn stats(&self) -> &Stats {
+        &self.partition_stats[self.partition]
+    }
+
+    /// Refreshes the statistics of the partition, should be called every
+    /// 100 ms.
+    pub fn sample_stats(&mut self) {
+        self.publish_stats();
+        let commands = self.stats().commands_processed.load(Ordering::Relaxed);
+        let ops = self.ops_sampler.sample(unix_time_ms(), commands);
+        self.stats().ops_per_sec.store(ops, Ordering::Relaxed);
+    }
+
+    /// Updates the statistics other partitions can't count themselves.
+    pub(super) fn publish_stats(&self) {
+        let stats = self.stats();
+        stats.keys.store(self.map.len(), Ordering::Relaxed);
+        stats.expires.store(self.expires, Ordering::Relaxed);
+        let avg_ttl = match self.expires {
+            0 => 0,
+            expires => {
+                ((self.deadline_sum / expires as u128) as u64).saturating_sub(unix_time_ms())
+            }
+        };
+        stats.avg_ttl.store(avg_ttl, Ordering::Relaxed);
+        stats.capacity.store(self.map.capacity(), Ordering::Relaxed);
+        let connected = self.clients.iter().filter(|client| !client.killed).count();
+        stats.connected_clients.store(connected, Ordering::Relaxed);
+        stats
+            .blocked_clients
+            .store(self.blocked.len(), Ordering::Relaxed);
+    }
+
+    /// The parameters matching any of `patterns`, with their values.
+    fn config_get(&self, patterns: &[Vec<u8>]) -> Value {
+        let patterns: Vec<Vec<u8>> = patterns.iter().map(|p| p.to_ascii_lowercase()).collect();
+        let mut reply = Vec::new();
+        for name in PARAMETERS.iter() {
+            if patterns
+                .iter()
+                .any(|pattern| glob_match(pattern, name.as_bytes()))
+            {
+                reply.push(Value::BufBulk(name.as_bytes().to_vec()));
+                reply.push(Value::BufBulk(self.parameter(name).into_bytes()));
+            }
+        }
+        Value::Array(reply)
+    }
+
+    /// Checks all `pairs` of parameter and value before changing any of the
+    /// parameters.
+    fn config_set(&mut self, pairs: &[Vec<u8>]) -> Reply {
+        let mut changes = Vec::with_capacity(pairs.len() / 2);
+        for pair in pairs.chunks(2) {
+            let name = String::from_utf8_lossy(&pair[0]).to_ascii_lowercase();
+            let range = match name.as_str() {
+                "max-load" => MAX_LOAD_RANGE,
+                "min-load" => MIN_LOAD_RANGE,
+                _ if PARAMETERS.contains(&name.as_str()) => {
+                    return Err(config_set_failed(&name, "can't set immutable config"))
+                }
+                _ => {
+                    return Err(Value::Error(format!(
+                        "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                        name
+                    )))
+                }
+            };
+            let value = match core::str::from_utf8(&pair[1])
+                .ok()
+                .and_then(|v| v.parse().ok())
+            {
+                Some(value) if value >= range.0 && value <= range.1 => value,
+                _ => {
+                    return Err(config_set_failed(
+                        &name,
+                        &format!("argument must be between {} and {}", range.0, range.1),
+                    ))
+                }
+            };
+            changes.push((name, value));
+        }
+
+        for (name, value) in changes {
+            match name.as_str() {
+                "max-load" => self.map.set_max_load(value),
+                _ => self.map.set_min_load(value),
+            }
+        }
+        Ok(ok())
+    }
+
+    /// The current value of the parameter `name`, one of `PARAMETERS`.
+    fn parameter(&self, name: &str) -> String {
+        let config = &self.config;
+        match name {
+            "threads" => format!("{}", config.threads),
+            "capacity" => format!("{}", config.capacity),
+            "thread-mapping" => String::from(match config.numa_strategy {
+                NumaTopology::Interleave => "interleave",
+                NumaTopology::Sequential => "sequential",
+            }),
+            "transport" => String::from(match config.transport {
+                Transport::Tcp => "tcp",
+                Transport::Udp => "udp",
+            }),
+            "incoming-tcp-connections" => format!("{}", config.tcp_connections_per_port),
+            "port" => format!("{}", config.port),
+            "hasher" => String::from(match config.hasher {
+                HashFunction::Fnv => "fnv",
+                HashFunction::Fold => "fold",
+                HashFunction::SipHash13(_) => "siphash",
+            }),
+            "max-load" => format!("{}", self.map.max_load()),
+            "min-load" => format!("{}", self.map.min_load()),
+            "growth-policy" => format!("{}", self.map.growth_policy()),
+            _ => unreachable!("unknown parameter {}", name),
+        }
+    }
+}
+
+/// Appends a `name:value` line of INFO to `info`.
+fn field<T: Display>(info: &mut String, name: &str, value: T) {
+    info.push_str(&format!("{}:{}\r\n", name, value));
+}
+
+/// `bytes` in the human readable form of INFO, like `1.50M`.
+fn human_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2}{}", value, UNITS[unit])
+}
+
+fn config_set_failed(name: &str, reason: &str) -> Value {
+    Value::Error(format!(
+        "ERR CONFIG SET failed (possibly related to argument '{}') - {}",
+        name, reason
+    ))
+}
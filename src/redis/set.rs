@@ -0,0 +1,348 @@
+//! Redis set commands.
+//!
+//! A set is stored as `Data::Set`, an `Index` with `()` values. Like in
+//! Redis, a set is deleted as soon as its last member is removed.
+//!
+//! Multi-key commands (SINTER, SUNION, SDIFF and their *STORE variants)
+//! only see the keys of the partition serving the connection, clients are
+//! expected to send them to the partition that owns all keys involved.
+
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::scan::{parse_cursor, parse_scan_options, scan_index};
+use super::{parse_int, Args, Reply, ERR_NOT_INTEGER, ERR_SYNTAX};
+use crate::indexmap::hash::HashFunction;
+use crate::indexmap::{Bucket, Index};
+use crate::resp::value::Value;
+use crate::{Data, Item, SashStore};
+
+/// A set of members.
+type Set = Index<Vec<u8>, (), HashFunction>;
+
+/// How the sets of a multi-key command are combined.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+impl SashStore {
+    /// SADD key member [member ...]
+    pub(super) fn redis_sadd(&mut self, args: Args) -> Reply {
+        let mut args = args.into_iter().skip(1);
+        let key = args.next().unwrap();
+        let set = self.set_or_insert(key)?;
+
+        let mut added = 0;
+        for member in args {
+            if let Bucket::Empty = set.insert(member, ()) {
+                added += 1;
+            }
+        }
+        Ok(Value::Integer(added))
+    }
+
+    /// SREM key member [member ...]
+    pub(super) fn redis_srem(&mut self, args: Args) -> Reply {
+        let (removed, now_empty) = match self.lookup_as_mut(&args[1], Data::as_set_mut)? {
+            Some(set) => {
+                let removed = args[2..]
+                    .iter()
+                    .filter(|member| set.remove(*member).is_some())
+                    .count();
+                (removed, set.is_empty())
+            }
+            None => return Ok(Value::Integer(0)),
+        };
+        if now_empty {
+            self.remove(&args[1]);
+        }
+        Ok(Value::Integer(removed as i64))
+    }
+
+    /// SMEMBERS key
+    pub(super) fn redis_smembers(&mut self, args: Args) -> Reply {
+        let set = self.lookup_as(&args[1], Data::as_set)?;
+        Ok(members_reply(set))
+    }
+
+    /// SISMEMBER key member
+    pub(super) fn redis_sismember(&mut self, args: Args) -> Reply {
+        let set = self.lookup_as(&args[1], Data::as_set)?;
+        let member = set.map_or(false, |set| set.get(&args[2]).is_some());
+        Ok(Value::Integer(member as i64))
+    }
+
+    /// SMISMEMBER key member [member ...]
+    pub(super) fn redis_smismember(&mut self, args: Args) -> Reply {
+        let set = self.lookup_as(&args[1], Data::as_set)?;
+        Ok(Value::Array(
+            args[2..]
+                .iter()
+                .map(|member| {
+                    let found = set.as_ref().map_or(false, |set| set.get(member).is_some());
+                    Value::Integer(found as i64)
+                })
+                .collect(),
+        ))
+    }
+
+    /// SCARD key
+    pub(super) fn redis_scard(&mut self, args: Args) -> Reply {
+        let set = self.lookup_as(&args[1], Data::as_set)?;
+        Ok(Value::Integer(set.map_or(0, |set| set.len()) as i64))
+    }
+
+    /// SPOP key [count]
+    pub(super) fn redis_spop(&mut self, args: Args) -> Reply {
+        let count = parse_count(&args)?;
+        let len = match self.lookup_as(&args[1], Data::as_set)? {
+            Some(set) => set.len(),
+            None if count.is_some() => return Ok(Value::Array(Vec::new())),
+            None => return Ok(Value::Null),
+        };
+
+        let count = match count {
+            Some(count) if count >= len => {
+                // Hand out the whole set
+                let set = match self.remove(&args[1]).map(|item| item.value) {
+                    Some(Data::Set(set)) => set,
+                    _ => unreachable!("checked by lookup_as"),
+                };
+                return Ok(members_reply(Some(&set)));
+            }
+            Some(count) => count,
+            None => 1,
+        };
+
+        let randoms: Vec<u64> = (0..count).map(|_| self.random()).collect();
+        let set = self.lookup_as_mut(&args[1], Data::as_set_mut)?.unwrap();
+        let mut popped = Vec::with_capacity(count);
+        for random in randoms {
+            let member = random_member(set, random);
+            set.remove(&member);
+            popped.push(Value::BufBulk(member));
+        }
+        if set.is_empty() {
+            self.remove(&args[1]);
+        }
+
+        if args.len() == 3 {
+            Ok(Value::Array(popped))
+        } else {
+            Ok(popped.pop().unwrap())
+        }
+    }
+
+    /// SRANDMEMBER key [count]
+    ///
+    /// A negative count may return the same member several times.
+    pub(super) fn redis_srandmember(&mut self, args: Args) -> Reply {
+        if args.len() > 3 {
+            return Err(Value::StaticError(ERR_SYNTAX));
+        }
+        let count = match args.get(2) {
+            Some(count) => Some(parse_int(count).ok_or(Value::StaticError(ERR_NOT_INTEGER))?),
+            None => None,
+        };
+        let set = match self.lookup_as(&args[1], Data::as_set)? {
+            Some(set) => set,
+            None if count.is_some() => return Ok(Value::Array(Vec::new())),
+            None => return Ok(Value::Null),
+        };
+
+        match count {
+            None => Ok(Value::BufBulk(random_member(set, self.random()))),
+            Some(count) if count < 0 => Ok(Value::Array(
+                (count..0)
+                    .map(|_| Value::BufBulk(random_member(set, self.random())))
+                    .collect(),
+            )),
+            Some(count) if count as usize >= set.len() => Ok(members_reply(Some(set))),
+            Some(count) => {
+                // Partial Fisher-Yates shuffle for distinct members
+                let mut members: Vec<Vec<u8>> = set.keys().cloned().collect();
+                let count = count as usize;
+                for i in 0..count {
+                    let j = i + (self.random() as usize) % (members.len() - i);
+                    members.swap(i, j);
+                }
+                members.truncate(count);
+                Ok(Value::Array(
+                    members.into_iter().map(Value::BufBulk).collect(),
+                ))
+            }
+        }
+    }
+
+    /// SINTER key [key ...]
+    pub(super) fn redis_sinter(&mut self, args: Args) -> Reply {
+        let result = self.combine_sets(&args[1..], SetOp::Inter)?;
+        Ok(members_reply(Some(&result)))
+    }
+
+    /// SUNION key [key ...]
+    pub(super) fn redis_sunion(&mut self, args: Args) -> Reply {
+        let result = self.combine_sets(&args[1..], SetOp::Union)?;
+        Ok(members_reply(Some(&result)))
+    }
+
+    /// SDIFF key [key ...]
+    pub(super) fn redis_sdiff(&mut self, args: Args) -> Reply {
+        let result = self.combine_sets(&args[1..], SetOp::Diff)?;
+        Ok(members_reply(Some(&result)))
+    }
+
+    /// SINTERSTORE destination key [key ...]
+    pub(super) fn redis_sinterstore(&mut self, args: Args) -> Reply {
+        self.combine_and_store(args, SetOp::Inter)
+    }
+
+    /// SUNIONSTORE destination key [key ...]
+    pub(super) fn redis_sunionstore(&mut self, args: Args) -> Reply {
+        self.combine_and_store(args, SetOp::Union)
+    }
+
+    /// SDIFFSTORE destination key [key ...]
+    pub(super) fn redis_sdiffstore(&mut self, args: Args) -> Reply {
+        self.combine_and_store(args, SetOp::Diff)
+    }
+
+    /// SSCAN key cursor [MATCH pattern] [COUNT count]
+    ///
+    /// Walks the buckets of the set like SCAN walks the key space.
+    pub(super) fn redis_sscan(&mut self, args: Args) -> Reply {
+        let cursor = parse_cursor(&args[2])?;
+        let scan = parse_scan_options(&args[3..])?;
+        if scan.novalues {
+            return Err(Value::StaticError(ERR_SYNTAX));
+        }
+
+        let mut members = Vec::new();
+        let cursor = match self.lookup_as(&args[1], Data::as_set)? {
+            Some(set) => scan_index(set, cursor, scan.count, |member, _| {
+                if scan.matches(member) {
+                    members.push(Value::BufBulk(member.clone()));
+                }
+                true
+            }),
+            None => 0,
+        };
+        Ok(Value::Array(vec![
+            Value::BufBulk(format!("{}", cursor).into_bytes()),
+            Value::Array(members),
+        ]))
+    }
+
+    /// Computes the intersection, union or difference of the sets stored
+    /// at `keys`, missing keys are empty sets.
+    fn combine_sets(&self, keys: &[Vec<u8>], op: SetOp) -> Result<Set, Value> {
+        let sets = keys
+            .iter()
+            .map(|key| self.lookup_as(key, Data::as_set))
+            .collect::<Result<Vec<Option<&Set>>, Value>>()?;
+        let mut result = Index::with_hasher(self.config.hasher);
+
+        match op {
+            SetOp::Inter => {
+                if sets.iter().any(Option::is_none) {
+                    return Ok(result);
+                }
+                let sets: Vec<&Set> = sets.iter().flatten().map(|set| &**set).collect();
+                let smallest = sets.iter().min_by_key(|set| set.len()).unwrap();
+                for member in smallest.keys() {
+                    if sets.iter().all(|set| set.get(&*member).is_some()) {
+                        result.insert(member.clone(), ());
+                    }
+                }
+            }
+            SetOp::Union => {
+                for set in sets.iter().flatten() {
+                    for member in set.keys() {
+                        result.insert(member.clone(), ());
+                    }
+                }
+            }
+            SetOp::Diff => {
+                if let Some(first) = &sets[0] {
+                    for member in first.keys() {
+                        let in_others = sets[1..]
+                            .iter()
+                            .flatten()
+                            .any(|set| set.get(&*member).is_some());
+                        if !in_others {
+                            result.insert(member.clone(), ());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Stores the combination of the sets in `args[2..]` at `args[1]`.
+    fn combine_and_store(&mut self, mut args: Args, op: SetOp) -> Reply {
+        let result = self.combine_sets(&args[2..], op)?;
+        let destination = args.swap_remove(1);
+        let len = result.len();
+        if len == 0 {
+            self.remove(&destination);
+        } else {
+            self.insert(destination, Item::with_data(0, Data::Set(result)));
+        }
+        Ok(Value::Integer(len as i64))
+    }
+
+    /// Returns the set stored at `key`, creating an empty one if the key
+    /// doesn't exist.
+    fn set_or_insert(&mut self, key: Vec<u8>) -> Result<&mut Set, Value> {
+        if self.lookup_as(&key, Data::as_set)?.is_none() {
+            self.insert(
+                key.clone(),
+                Item::with_data(0, Data::Set(Index::with_hasher(self.config.hasher))),
+            );
+        }
+        Ok(self.lookup_as_mut(&key, Data::as_set_mut)?.unwrap())
+    }
+}
+
+/// Parses the optional, non-negative count of SPOP.
+fn parse_count(args: &Args) -> Result<Option<usize>, Value> {
+    if args.len() > 3 {
+        return Err(Value::StaticError(ERR_SYNTAX));
+    }
+    match args.get(2).map(|count| parse_int(count)) {
+        Some(Some(count)) if count >= 0 => Ok(Some(count as usize)),
+        Some(_) => Err(Value::StaticError(
+            "ERR value is out of range, must be positive",
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Picks a member of the non-empty `set`, `random` selects which one.
+///
+/// Probes random buckets until it hits a full one, like Redis does.
+fn random_member(set: &Set, mut random: u64) -> Vec<u8> {
+    debug_assert!(!set.is_empty());
+    loop {
+        let i = (random % set.buckets() as u64) as usize;
+        if let Some(pair) = set.get_at(i) {
+            return pair.0.clone();
+        }
+        // Cheap way to derive the next bucket to try
+        random = random.rotate_left(17).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+/// An array reply with all members of `set` (empty if there is none).
+fn members_reply(set: Option<&Set>) -> Value {
+    Value::Array(set.map_or(Vec::new(), |set| {
+        set.keys()
+            .map(|member| Value::BufBulk(member.clone()))
+            .collect()
+    }))
+}
@@ -0,0 +1,377 @@
+//! An ordered list of (score, member) pairs with O(log n) rank queries.
+//!
+//! This is the skiplist Redis uses for sorted sets: every link stores its
+//! span (the number of elements it skips) so the rank of an element is the
+//! sum of the spans on the search path. Nodes live in a `Vec` and refer to
+//! each other by index instead of pointers, which keeps everything safe.
+//! Slots of removed nodes are recycled through a free list.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+/// Maximum number of levels, enough for 2^64 elements with p = 1/4.
+const MAX_LEVEL: usize = 32;
+
+/// Index of the sentinel node which starts every level.
+const HEAD: usize = 0;
+
+#[derive(Debug, Clone, Copy)]
+struct Link {
+    /// The next node on this level
+    next: Option<usize>,
+    /// Number of level 0 steps to get to `next`
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    score: f64,
+    member: Vec<u8>,
+    /// One link per level of the node
+    links: Vec<Link>,
+    /// The previous node on level 0 (`None` for the first one)
+    prev: Option<usize>,
+}
+
+impl Node {
+    /// Orders the node relative to `(score, member)`.
+    fn cmp(&self, score: f64, member: &[u8]) -> Ordering {
+        self.score
+            .partial_cmp(&score)
+            .expect("scores are never NaN")
+            .then_with(|| self.member.as_slice().cmp(member))
+    }
+}
+
+/// Elements ordered by score, then member.
+///
+/// Scores must not be NaN and a (score, member) pair must not be inserted
+/// twice, `SortedSet` takes care of both.
+#[derive(Debug, Clone)]
+pub(super) struct SkipList {
+    nodes: Vec<Node>,
+    /// Unused slots in `nodes`
+    free: Vec<usize>,
+    /// The last node on level 0
+    tail: Option<usize>,
+    /// Number of levels in use
+    level: usize,
+    len: usize,
+    /// State of the xorshift generator used to pick node levels
+    rng: u64,
+}
+
+impl SkipList {
+    pub fn new() -> SkipList {
+        SkipList {
+            nodes: vec![Node {
+                score: 0.0,
+                member: Vec::new(),
+                links: vec![
+                    Link {
+                        next: None,
+                        span: 0
+                    };
+                    MAX_LEVEL
+                ],
+                prev: None,
+            }],
+            free: Vec::new(),
+            tail: None,
+            level: 1,
+            len: 0,
+            rng: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Adds `member` with `score`, the pair must not be in the list yet.
+    pub fn insert(&mut self, score: f64, member: Vec<u8>) {
+        let (update, rank) = self.search(score, &member);
+
+        let level = self.random_level();
+        if level > self.level {
+            for i in self.level..level {
+                self.nodes[HEAD].links[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node {
+            score,
+            member,
+            links: vec![
+                Link {
+                    next: None,
+                    span: 0
+                };
+                level
+            ],
+            prev: if update[0] == HEAD {
+                None
+            } else {
+                Some(update[0])
+            },
+        };
+        let x = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for i in 0..level {
+            let before = self.nodes[update[i]].links[i];
+            self.nodes[x].links[i] = Link {
+                next: before.next,
+                span: before.span - (rank[0] - rank[i]),
+            };
+            self.nodes[update[i]].links[i] = Link {
+                next: Some(x),
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        for (i, &u) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[u].links[i].span += 1;
+        }
+
+        match self.nodes[x].links[0].next {
+            Some(next) => self.nodes[next].prev = Some(x),
+            None => self.tail = Some(x),
+        }
+        self.len += 1;
+    }
+
+    /// Removes the pair `(score, member)`, returns `false` if it wasn't
+    /// in the list.
+    pub fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let (update, _) = self.search(score, member);
+        let x = match self.nodes[update[0]].links[0].next {
+            Some(x) if self.nodes[x].cmp(score, member) == Ordering::Equal => x,
+            _ => return false,
+        };
+
+        for (i, &u) in update.iter().enumerate().take(self.level) {
+            let removed = self.nodes[x].links.get(i).copied();
+            let link = &mut self.nodes[u].links[i];
+            match removed {
+                Some(removed) if link.next == Some(x) => {
+                    link.span = link.span + removed.span - 1;
+                    link.next = removed.next;
+                }
+                _ => link.span -= 1,
+            }
+        }
+        match self.nodes[x].links[0].next {
+            Some(next) => self.nodes[next].prev = self.nodes[x].prev,
+            None => self.tail = self.nodes[x].prev,
+        }
+        while self.level > 1 && self.nodes[HEAD].links[self.level - 1].next.is_none() {
+            self.level -= 1;
+        }
+
+        // Release the memory of the member, keep the slot for reuse
+        self.nodes[x].member = Vec::new();
+        self.nodes[x].links = Vec::new();
+        self.free.push(x);
+        self.len -= 1;
+        true
+    }
+
+    /// Counts the elements at the front of the list for which `before`
+    /// returns `true`.
+    ///
+    /// `before` must be monotone: `true` for a prefix of the list and
+    /// `false` for the rest. The count is also the rank of the first
+    /// element `before` rejects.
+    pub fn count_while<F>(&self, before: F) -> usize
+    where
+        F: Fn(f64, &[u8]) -> bool,
+    {
+        let mut x = HEAD;
+        let mut rank = 0;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].links[i].next {
+                if !before(self.nodes[next].score, &self.nodes[next].member) {
+                    break;
+                }
+                rank += self.nodes[x].links[i].span;
+                x = next;
+            }
+        }
+        rank
+    }
+
+    /// Returns the 0-based rank of `(score, member)` if it is in the list.
+    pub fn rank(&self, score: f64, member: &[u8]) -> usize {
+        self.count_while(|s, m| {
+            s.partial_cmp(&score)
+                .expect("scores are never NaN")
+                .then_with(|| m.cmp(member))
+                == Ordering::Less
+        })
+    }
+
+    /// Iterates from the element at `rank` towards the end of the list.
+    pub fn iter_from(&self, rank: usize) -> Iter {
+        Iter {
+            list: self,
+            node: self.node_at(rank),
+            reverse: false,
+        }
+    }
+
+    /// Iterates from the element at `rank` towards the front of the list.
+    pub fn rev_iter_from(&self, rank: usize) -> Iter {
+        Iter {
+            list: self,
+            node: self.node_at(rank),
+            reverse: true,
+        }
+    }
+
+    /// Finds the last node before `(score, member)` on every level,
+    /// together with the rank of these nodes (counting the head as 0).
+    fn search(&self, score: f64, member: &[u8]) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i + 1 == self.level { 0 } else { rank[i + 1] };
+            while let Some(next) = self.nodes[x].links[i].next {
+                if self.nodes[next].cmp(score, member) != Ordering::Less {
+                    break;
+                }
+                rank[i] += self.nodes[x].links[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+        (update, rank)
+    }
+
+    /// Returns the node with the given 0-based rank.
+    fn node_at(&self, rank: usize) -> Option<usize> {
+        if rank >= self.len {
+            return None;
+        }
+        if rank + 1 == self.len {
+            return self.tail;
+        }
+
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].links[i].next {
+                if traversed + self.nodes[x].links[i].span > target {
+                    break;
+                }
+                traversed += self.nodes[x].links[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    /// Picks the level of a new node, level n+1 has a 1/4 chance of
+    /// being used if level n is.
+    fn random_level(&mut self) -> usize {
+        let mut level = 1;
+        loop {
+            self.rng ^= self.rng << 13;
+            self.rng ^= self.rng >> 7;
+            self.rng ^= self.rng << 17;
+            if level == MAX_LEVEL || self.rng & 3 != 0 {
+                return level;
+            }
+            level += 1;
+        }
+    }
+}
+
+/// Iterator over `(score, member)` pairs of a `SkipList`.
+pub(super) struct Iter<'a> {
+    list: &'a SkipList,
+    node: Option<usize>,
+    reverse: bool,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (f64, &'a Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = &self.list.nodes[self.node?];
+        self.node = if self.reverse {
+            node.prev
+        } else {
+            node.links[0].next
+        };
+        Some((node.score, &node.member))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn matches_sorted_vec() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let mut list = SkipList::new();
+        let mut oracle: Vec<(i64, Vec<u8>)> = Vec::new();
+
+        for _ in 0..4000 {
+            let score = rng.gen_range(0, 50);
+            let member = vec![rng.gen_range(0, 40u8)];
+            let pos = oracle.binary_search(&(score, member.clone()));
+            match pos {
+                Ok(i) => {
+                    assert!(list.remove(score as f64, &member));
+                    oracle.remove(i);
+                }
+                Err(_) if rng.gen_bool(0.3) => {
+                    assert!(!list.remove(score as f64, &member));
+                }
+                Err(i) => {
+                    list.insert(score as f64, member.clone());
+                    oracle.insert(i, (score, member));
+                }
+            }
+            assert_eq!(list.len(), oracle.len());
+        }
+
+        for (rank, (score, member)) in oracle.iter().enumerate() {
+            assert_eq!(list.rank(*score as f64, member), rank);
+            let (s, m) = list.iter_from(rank).next().unwrap();
+            assert_eq!((s, m), (*score as f64, member));
+        }
+        let forward: Vec<_> = list
+            .iter_from(0)
+            .map(|(s, m)| (s as i64, m.clone()))
+            .collect();
+        assert_eq!(forward, oracle);
+        let mut backward: Vec<_> = list
+            .rev_iter_from(oracle.len() - 1)
+            .map(|(s, m)| (s as i64, m.clone()))
+            .collect();
+        backward.reverse();
+        assert_eq!(backward, oracle);
+        assert_eq!(
+            list.count_while(|s, _| s < 25.0),
+            oracle.iter().filter(|p| p.0 < 25).count()
+        );
+    }
+}
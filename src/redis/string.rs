@@ -0,0 +1,356 @@
+//! Redis string commands.
+//!
+//! The flags of an `Item` are only used by the memcached front-end and are
+//! zero for anything created through Redis commands.
+
+use alloc::format;
+use alloc::vec::Vec;
+
+use log::trace;
+
+use super::expire::ExpireTime;
+use super::float::format_sum;
+use super::{
+    ok, parse_float, parse_int, Args, Reply, ERR_NOT_FLOAT, ERR_NOT_INTEGER, ERR_SYNTAX,
+    ERR_WRONGTYPE,
+};
+use crate::resp::value::Value;
+use crate::{Data, Item, SashStore};
+
+/// Largest string `SETRANGE` is allowed to create (same as Redis).
+const MAX_STRING_LEN: i64 = 512 * 1024 * 1024;
+
+impl SashStore {
+    /// GET key
+    pub(super) fn redis_get(&mut self, args: Args) -> Reply {
+        trace!("Execute GET for {:?}", args[1]);
+        match self.lookup_as(&args[1], Data::as_string)? {
+            Some(value) => Ok(Value::BufBulk(value.to_vec())),
+            None => Ok(Value::Null),
+        }
+    }
+
+    /// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds |
+    ///   EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]
+    pub(super) fn redis_set(&mut self, mut args: Args) -> Reply {
+        let (mut nx, mut xx, mut get, mut keep_ttl) = (false, false, false, false);
+        let mut expires_at = None;
+
+        let mut i = 3;
+        while i < args.len() {
+            let option = args[i].to_ascii_uppercase();
+            match option.as_slice() {
+                b"NX" if !xx => nx = true,
+                b"XX" if !nx => xx = true,
+                b"GET" => get = true,
+                b"KEEPTTL" if expires_at.is_none() => keep_ttl = true,
+                b"EX" | b"PX" | b"EXAT" | b"PXAT"
+                    if !keep_ttl && expires_at.is_none() && i + 1 < args.len() =>
+                {
+                    let unit = ExpireTime::from_option(&option);
+                    let time =
+                        parse_int(&args[i + 1]).ok_or(Value::StaticError(ERR_NOT_INTEGER))?;
+                    match unit.deadline(time, self.clock_ms) {
+                        Some(deadline) if time > 0 => expires_at = Some(deadline.max(0) as u64),
+                        _ => {
+                            return Err(Value::StaticError(
+                                "ERR invalid expire time in 'set' command",
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                _ => return Err(Value::StaticError(ERR_SYNTAX)),
+            }
+            i += 1;
+        }
+        args.truncate(3);
+        let value = args.pop().unwrap();
+        let key = args.pop().unwrap();
+        trace!("Execute SET for {:?} {:?}", key, value);
+
+        if !(nx || xx || get || keep_ttl) {
+            // Fast path, no need to look at the old value
+            self.insert(
+                key,
+                Item {
+                    expires_at,
+                    ..Item::with_data(0, Data::String(value))
+                },
+            );
+            return Ok(ok());
+        }
+
+        let (exists, old_value, old_deadline) = match self.lookup(&key) {
+            Some(item) => (true, item.value.as_string().cloned(), item.expires_at),
+            None => (false, None, None),
+        };
+        if get && exists && old_value.is_none() {
+            return Err(Value::StaticError(ERR_WRONGTYPE));
+        }
+        let reply = if get {
+            old_value.map_or(Value::Null, Value::BufBulk)
+        } else {
+            ok()
+        };
+        if (nx && exists) || (xx && !exists) {
+            return Ok(if get { reply } else { Value::Null });
+        }
+
+        let expires_at = if keep_ttl { old_deadline } else { expires_at };
+        self.insert(
+            key,
+            Item {
+                expires_at,
+                ..Item::with_data(0, Data::String(value))
+            },
+        );
+        Ok(reply)
+    }
+
+    /// GETSET key value
+    pub(super) fn redis_getset(&mut self, mut args: Args) -> Reply {
+        let old = self
+            .lookup_as(&args[1], Data::as_string)?
+            .map_or(Value::Null, |value| Value::BufBulk(value.to_vec()));
+        let value = args.pop().unwrap();
+        let key = args.pop().unwrap();
+        self.insert(key, Item::new(0, value));
+        Ok(old)
+    }
+
+    /// GETDEL key
+    pub(super) fn redis_getdel(&mut self, args: Args) -> Reply {
+        if self.lookup_as(&args[1], Data::as_string)?.is_none() {
+            return Ok(Value::Null);
+        }
+        match self.remove(&args[1]).map(|item| item.value) {
+            Some(Data::String(value)) => Ok(Value::BufBulk(value)),
+            _ => unreachable!("checked by lookup_as"),
+        }
+    }
+
+    /// SETNX key value
+    pub(super) fn redis_setnx(&mut self, mut args: Args) -> Reply {
+        if self.lookup(&args[1]).is_some() {
+            return Ok(Value::Integer(0));
+        }
+        let value = args.pop().unwrap();
+        let key = args.pop().unwrap();
+        self.insert(key, Item::new(0, value));
+        Ok(Value::Integer(1))
+    }
+
+    /// MGET key [key ...]
+    pub(super) fn redis_mget(&mut self, args: Args) -> Reply {
+        Ok(Value::Array(
+            args[1..]
+                .iter()
+                .map(|key| match self.lookup_as(key, Data::as_string) {
+                    Ok(Some(value)) => Value::BufBulk(value.to_vec()),
+                    // Like Redis, keys of other types are reported as missing
+                    _ => Value::Null,
+                })
+                .collect(),
+        ))
+    }
+
+    /// MSET key value [key value ...]
+    pub(super) fn redis_mset(&mut self, args: Args) -> Reply {
+        if args.len() % 2 == 0 {
+            return Err(super::wrong_arity(&args));
+        }
+        let mut args = args.into_iter().skip(1);
+        while let (Some(key), Some(value)) = (args.next(), args.next()) {
+            self.insert(key, Item::new(0, value));
+        }
+        Ok(ok())
+    }
+
+    /// MSETNX key value [key value ...]
+    pub(super) fn redis_msetnx(&mut self, args: Args) -> Reply {
+        if args.len() % 2 == 0 {
+            return Err(super::wrong_arity(&args));
+        }
+        if args[1..]
+            .iter()
+            .step_by(2)
+            .any(|key| self.lookup(key).is_some())
+        {
+            return Ok(Value::Integer(0));
+        }
+        self.redis_mset(args)?;
+        Ok(Value::Integer(1))
+    }
+
+    /// INCR key
+    pub(super) fn redis_incr(&mut self, args: Args) -> Reply {
+        self.incr_by(&args[1], 1)
+    }
+
+    /// DECR key
+    pub(super) fn redis_decr(&mut self, args: Args) -> Reply {
+        self.incr_by(&args[1], -1)
+    }
+
+    /// INCRBY key increment
+    pub(super) fn redis_incrby(&mut self, args: Args) -> Reply {
+        let increment = parse_int(&args[2]).ok_or(Value::StaticError(ERR_NOT_INTEGER))?;
+        self.incr_by(&args[1], increment)
+    }
+
+    /// DECRBY key decrement
+    pub(super) fn redis_decrby(&mut self, args: Args) -> Reply {
+        let increment = parse_int(&args[2])
+            .and_then(i64::checked_neg)
+            .ok_or(Value::StaticError(ERR_NOT_INTEGER))?;
+        self.incr_by(&args[1], increment)
+    }
+
+    /// INCRBYFLOAT key increment
+    ///
+    /// The result is formatted like Redis does (see `float::format_sum`).
+    pub(super) fn redis_incrbyfloat(&mut self, mut args: Args) -> Reply {
+        let increment = parse_float(&args[2]).ok_or(Value::StaticError(ERR_NOT_FLOAT))?;
+
+        if let Some(value) = self.lookup_as_mut(&args[1], Data::as_string_mut)? {
+            let new = parse_float(value).ok_or(Value::StaticError(ERR_NOT_FLOAT))? + increment;
+            if !new.is_finite() {
+                return Err(Value::StaticError(
+                    "ERR increment would produce NaN or Infinity",
+                ));
+            }
+            *value = format_sum(value, &args[2], new);
+            return Ok(Value::BufBulk(value.clone()));
+        }
+
+        let new = format_sum(b"0", &args[2], increment);
+        self.insert(args.swap_remove(1), Item::new(0, new.clone()));
+        Ok(Value::BufBulk(new))
+    }
+
+    /// APPEND key value
+    pub(super) fn redis_append(&mut self, mut args: Args) -> Reply {
+        if let Some(value) = self.lookup_as_mut(&args[1], Data::as_string_mut)? {
+            value.extend_from_slice(&args[2]);
+            return Ok(Value::Integer(value.len() as i64));
+        }
+
+        let value = args.pop().unwrap();
+        let key = args.pop().unwrap();
+        let len = value.len();
+        self.insert(key, Item::new(0, value));
+        Ok(Value::Integer(len as i64))
+    }
+
+    /// STRLEN key
+    pub(super) fn redis_strlen(&mut self, args: Args) -> Reply {
+        let value = self.lookup_as(&args[1], Data::as_string)?;
+        Ok(Value::Integer(value.map_or(0, |value| value.len()) as i64))
+    }
+
+    /// GETRANGE key start end
+    pub(super) fn redis_getrange(&mut self, args: Args) -> Reply {
+        let (start, end) = match (parse_int(&args[2]), parse_int(&args[3])) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Err(Value::StaticError(ERR_NOT_INTEGER)),
+        };
+        let value = match self.lookup_as(&args[1], Data::as_string)? {
+            Some(value) => value,
+            None => return Ok(Value::BufBulk(Vec::new())),
+        };
+
+        match clamp_range(start, end, value.len()) {
+            Some((start, end)) => Ok(Value::BufBulk(value[start..=end].to_vec())),
+            None => Ok(Value::BufBulk(Vec::new())),
+        }
+    }
+
+    /// SETRANGE key offset value
+    pub(super) fn redis_setrange(&mut self, mut args: Args) -> Reply {
+        let offset = match parse_int(&args[2]) {
+            Some(offset) if offset >= 0 => offset,
+            _ => return Err(Value::StaticError("ERR offset is out of range")),
+        };
+        let patch = args.pop().unwrap();
+        if offset > MAX_STRING_LEN - patch.len() as i64 {
+            return Err(Value::StaticError(
+                "ERR string exceeds maximum allowed size (512MB)",
+            ));
+        }
+        let offset = offset as usize;
+
+        if let Some(value) = self.lookup_as_mut(&args[1], Data::as_string_mut)? {
+            if !patch.is_empty() {
+                write_at(value, offset, &patch);
+            }
+            return Ok(Value::Integer(value.len() as i64));
+        }
+
+        // Like Redis, an empty patch doesn't create the key
+        if patch.is_empty() {
+            return Ok(Value::Integer(0));
+        }
+        let mut value = Vec::new();
+        write_at(&mut value, offset, &patch);
+        let len = value.len();
+        self.insert(args.swap_remove(1), Item::new(0, value));
+        Ok(Value::Integer(len as i64))
+    }
+
+    /// Adds `increment` to the integer stored at `key` (0 if it doesn't exist).
+    fn incr_by(&mut self, key: &[u8], increment: i64) -> Reply {
+        if let Some(value) = self.lookup_as_mut(key, Data::as_string_mut)? {
+            let new = parse_int(value)
+                .ok_or(Value::StaticError(ERR_NOT_INTEGER))?
+                .checked_add(increment)
+                .ok_or(Value::StaticError(
+                    "ERR increment or decrement would overflow",
+                ))?;
+            *value = format!("{}", new).into_bytes();
+            return Ok(Value::Integer(new));
+        }
+
+        self.insert(
+            key.to_vec(),
+            Item::new(0, format!("{}", increment).into_bytes()),
+        );
+        Ok(Value::Integer(increment))
+    }
+}
+
+/// Converts Redis style inclusive `start`/`end` indices (negative ones count
+/// from the end) into valid indices of a string with length `len`.
+///
+/// Returns `None` if the resulting range is empty.
+pub(super) fn clamp_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    if start < 0 && end < 0 && start > end {
+        return None;
+    }
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let end = if end < 0 {
+        (len + end).max(0)
+    } else {
+        end.min(len - 1)
+    };
+    if start > end || len == 0 {
+        None
+    } else {
+        Some((start as usize, end as usize))
+    }
+}
+
+/// Overwrites `value` with `patch` starting at `offset`, zero padding it if
+/// it is too short.
+fn write_at(value: &mut Vec<u8>, offset: usize, patch: &[u8]) {
+    let end = offset + patch.len();
+    if value.len() < end {
+        value.resize(end, 0);
+    }
+    value[offset..end].copy_from_slice(patch);
+}
@@ -0,0 +1,507 @@
+//! Redis sorted set commands.
+//!
+//! A sorted set is stored as `Data::SortedSet`: an `Index` from member to
+//! score for O(1) score lookups and a `SkipList` ordered by (score, member)
+//! for ranges and ranks. Like in Redis, a sorted set is deleted as soon as
+//! its last member is removed.
+//!
+//! Range commands translate their bounds into ranks first (the number of
+//! members before a bound), everything else is iterating the skiplist
+//! from a given rank.
+
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::list::list_range;
+use super::skiplist::SkipList;
+use super::{parse_int, Args, Reply, ERR_NOT_FLOAT, ERR_NOT_INTEGER, ERR_SYNTAX};
+use crate::indexmap::hash::HashFunction;
+use crate::indexmap::Index;
+use crate::resp::value::Value;
+use crate::{Data, Item, SashStore};
+
+const ERR_NOT_FLOAT_RANGE: &str = "ERR min or max is not a float";
+const ERR_NOT_LEX_RANGE: &str = "ERR min or max not valid string range item";
+
+/// Members ordered by score.
+#[derive(Debug, Clone)]
+pub struct SortedSet {
+    scores: Index<Vec<u8>, f64, HashFunction>,
+    order: SkipList,
+}
+
+impl SortedSet {
+    /// Creates an empty sorted set, hashing members with `hasher`.
+    pub(super) fn new(hasher: HashFunction) -> SortedSet {
+        SortedSet {
+            scores: Index::with_hasher(hasher),
+            order: SkipList::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Iterates over `(score, member)` pairs, lowest score first.
+    pub(super) fn iter(&self) -> impl Iterator<Item = (f64, &Vec<u8>)> {
+        self.order.iter_from(0)
+    }
+
+    fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets the score of `member`, adding it if necessary.
+    pub(super) fn insert(&mut self, member: Vec<u8>, score: f64) {
+        if let Some(old) = self.score(&member) {
+            self.order.remove(old, &member);
+        }
+        self.order.insert(score, member.clone());
+        self.scores.insert(member, score);
+    }
+
+    /// Removes `member`, returns `false` if it wasn't in the set.
+    fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.order.remove(score, member),
+            None => false,
+        }
+    }
+
+    /// The 0-based rank of `member` (ordered by ascending score).
+    fn rank(&self, member: &[u8]) -> Option<usize> {
+        self.score(member)
+            .map(|score| self.order.rank(score, member))
+    }
+
+    /// Ranks of the first member within and the first member after the
+    /// score range `min`..`max`.
+    fn score_range(&self, min: ScoreBound, max: ScoreBound) -> (usize, usize) {
+        let start = self.order.count_while(|score, _| match min {
+            ScoreBound::Inclusive(min) => score < min,
+            ScoreBound::Exclusive(min) => score <= min,
+        });
+        let end = self.order.count_while(|score, _| match max {
+            ScoreBound::Inclusive(max) => score <= max,
+            ScoreBound::Exclusive(max) => score < max,
+        });
+        (start, end.max(start))
+    }
+
+    /// Like `score_range` for a lexicographical range, only meaningful if
+    /// all members have the same score.
+    fn lex_range(&self, min: &LexBound, max: &LexBound) -> (usize, usize) {
+        let start = self.order.count_while(|_, member| match min {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(min) => member < min.as_slice(),
+            LexBound::Exclusive(min) => member <= min.as_slice(),
+        });
+        let end = self.order.count_while(|_, member| match max {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(max) => member <= max.as_slice(),
+            LexBound::Exclusive(max) => member < max.as_slice(),
+        });
+        (start, end.max(start))
+    }
+}
+
+/// A bound of a score range, `(` makes it exclusive.
+#[derive(Debug, Clone, Copy)]
+enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+impl ScoreBound {
+    fn parse(bound: &[u8]) -> Result<ScoreBound, Value> {
+        match bound.split_first() {
+            Some((b'(', score)) => parse_score(score).map(ScoreBound::Exclusive),
+            _ => parse_score(bound).map(ScoreBound::Inclusive),
+        }
+        .ok_or(Value::StaticError(ERR_NOT_FLOAT_RANGE))
+    }
+}
+
+/// A bound of a lexicographical range: `-`, `+`, `[member` or `(member`.
+#[derive(Debug, Clone)]
+enum LexBound {
+    Min,
+    Max,
+    Inclusive(Vec<u8>),
+    Exclusive(Vec<u8>),
+}
+
+impl LexBound {
+    fn parse(bound: &[u8]) -> Result<LexBound, Value> {
+        match bound.split_first() {
+            Some((b'-', [])) => Ok(LexBound::Min),
+            Some((b'+', [])) => Ok(LexBound::Max),
+            Some((b'[', member)) => Ok(LexBound::Inclusive(member.to_vec())),
+            Some((b'(', member)) => Ok(LexBound::Exclusive(member.to_vec())),
+            _ => Err(Value::StaticError(ERR_NOT_LEX_RANGE)),
+        }
+    }
+}
+
+/// How ZRANGE interprets its `start` and `stop` arguments.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum RangeBy {
+    Rank,
+    Score,
+    Lex,
+}
+
+impl SashStore {
+    /// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+    pub(super) fn redis_zadd(&mut self, args: Args) -> Reply {
+        let (mut nx, mut xx, mut gt, mut lt, mut ch, mut incr) =
+            (false, false, false, false, false, false);
+        let mut i = 2;
+        while i < args.len() {
+            match args[i].to_ascii_uppercase().as_slice() {
+                b"NX" => nx = true,
+                b"XX" => xx = true,
+                b"GT" => gt = true,
+                b"LT" => lt = true,
+                b"CH" => ch = true,
+                b"INCR" => incr = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        let pairs = &args[i..];
+        if pairs.is_empty() || pairs.len() % 2 != 0 {
+            return Err(Value::StaticError(ERR_SYNTAX));
+        }
+        if nx && xx {
+            return Err(Value::StaticError(
+                "ERR XX and NX options at the same time are not compatible",
+            ));
+        }
+        if (gt && lt) || (nx && (gt || lt)) {
+            return Err(Value::StaticError(
+                "ERR GT, LT, and/or NX options at the same time are not compatible",
+            ));
+        }
+        if incr && pairs.len() > 2 {
+            return Err(Value::StaticError(
+                "ERR INCR option supports a single increment-element pair",
+            ));
+        }
+        // Validate all scores before changing anything
+        let scores = pairs
+            .iter()
+            .step_by(2)
+            .map(|score| parse_score(score).ok_or(Value::StaticError(ERR_NOT_FLOAT)))
+            .collect::<Result<Vec<f64>, Value>>()?;
+
+        let key = &args[1];
+        if self.lookup_as(key, Data::as_zset)?.is_none() {
+            if xx {
+                return Ok(if incr { Value::Null } else { Value::Integer(0) });
+            }
+            self.insert(
+                key.clone(),
+                Item::with_data(0, Data::SortedSet(SortedSet::new(self.config.hasher))),
+            );
+        }
+        let zset = self.lookup_as_mut(key, Data::as_zset_mut)?.unwrap();
+
+        let (mut added, mut changed) = (0, 0);
+        let mut last_score = None;
+        for (score, member) in scores.into_iter().zip(pairs.iter().skip(1).step_by(2)) {
+            let current = zset.score(member);
+            let new = match current {
+                Some(_) if nx => continue,
+                None if xx => continue,
+                Some(current) if incr => current + score,
+                _ => score,
+            };
+            if new.is_nan() {
+                return Err(Value::StaticError(
+                    "ERR resulting score is not a number (NaN)",
+                ));
+            }
+            match current {
+                Some(current) if (gt && new <= current) || (lt && new >= current) => continue,
+                Some(current) if new == current => {}
+                Some(_) => {
+                    zset.insert(member.clone(), new);
+                    changed += 1;
+                }
+                None => {
+                    zset.insert(member.clone(), new);
+                    added += 1;
+                }
+            }
+            last_score = Some(new);
+        }
+        if zset.len() == 0 {
+            // Only possible if nothing was added to a new set
+            self.remove(key);
+        }
+
+        if incr {
+            Ok(last_score.map_or(Value::Null, format_score))
+        } else if ch {
+            Ok(Value::Integer(added + changed))
+        } else {
+            Ok(Value::Integer(added))
+        }
+    }
+
+    /// ZINCRBY key increment member
+    pub(super) fn redis_zincrby(&mut self, args: Args) -> Reply {
+        let mut zadd = Vec::with_capacity(5);
+        zadd.push(b"ZADD".to_vec());
+        zadd.push(args[1].clone());
+        zadd.push(b"INCR".to_vec());
+        zadd.extend(args.into_iter().skip(2));
+        self.redis_zadd(zadd)
+    }
+
+    /// ZREM key member [member ...]
+    pub(super) fn redis_zrem(&mut self, args: Args) -> Reply {
+        let (removed, now_empty) = match self.lookup_as_mut(&args[1], Data::as_zset_mut)? {
+            Some(zset) => {
+                let removed = args[2..]
+                    .iter()
+                    .filter(|member| zset.remove(member))
+                    .count();
+                (removed, zset.len() == 0)
+            }
+            None => return Ok(Value::Integer(0)),
+        };
+        if now_empty {
+            self.remove(&args[1]);
+        }
+        Ok(Value::Integer(removed as i64))
+    }
+
+    /// ZSCORE key member
+    pub(super) fn redis_zscore(&mut self, args: Args) -> Reply {
+        let zset = self.lookup_as(&args[1], Data::as_zset)?;
+        let score = zset.and_then(|zset| zset.score(&args[2]));
+        Ok(score.map_or(Value::Null, format_score))
+    }
+
+    /// ZCARD key
+    pub(super) fn redis_zcard(&mut self, args: Args) -> Reply {
+        let zset = self.lookup_as(&args[1], Data::as_zset)?;
+        Ok(Value::Integer(zset.map_or(0, |zset| zset.len()) as i64))
+    }
+
+    /// ZCOUNT key min max
+    pub(super) fn redis_zcount(&mut self, args: Args) -> Reply {
+        let min = ScoreBound::parse(&args[2])?;
+        let max = ScoreBound::parse(&args[3])?;
+        let zset = self.lookup_as(&args[1], Data::as_zset)?;
+        let (start, end) = zset.map_or((0, 0), |zset| zset.score_range(min, max));
+        Ok(Value::Integer((end - start) as i64))
+    }
+
+    /// ZRANK key member [WITHSCORE]
+    pub(super) fn redis_zrank(&mut self, args: Args) -> Reply {
+        self.rank(args, false)
+    }
+
+    /// ZREVRANK key member [WITHSCORE]
+    pub(super) fn redis_zrevrank(&mut self, args: Args) -> Reply {
+        self.rank(args, true)
+    }
+
+    /// ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count]
+    ///   [WITHSCORES]
+    pub(super) fn redis_zrange(&mut self, args: Args) -> Reply {
+        let (mut by, mut rev, mut withscores) = (RangeBy::Rank, false, false);
+        let mut limit = None;
+        let mut i = 4;
+        while i < args.len() {
+            match args[i].to_ascii_uppercase().as_slice() {
+                b"BYSCORE" => by = RangeBy::Score,
+                b"BYLEX" => by = RangeBy::Lex,
+                b"REV" => rev = true,
+                b"WITHSCORES" => withscores = true,
+                b"LIMIT" if i + 2 < args.len() => {
+                    match (parse_int(&args[i + 1]), parse_int(&args[i + 2])) {
+                        (Some(offset), Some(count)) => limit = Some((offset, count)),
+                        _ => return Err(Value::StaticError(ERR_NOT_INTEGER)),
+                    }
+                    i += 2;
+                }
+                _ => return Err(Value::StaticError(ERR_SYNTAX)),
+            }
+            i += 1;
+        }
+        if limit.is_some() && by == RangeBy::Rank {
+            return Err(Value::StaticError(
+                "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX",
+            ));
+        }
+        if withscores && by == RangeBy::Lex {
+            return Err(Value::StaticError(
+                "ERR syntax error, WITHSCORES not supported in combination with BYLEX",
+            ));
+        }
+
+        // With REV the range is given from the highest to the lowest
+        let (min, max) = if rev && by != RangeBy::Rank {
+            (&args[3], &args[2])
+        } else {
+            (&args[2], &args[3])
+        };
+        let zset = self.lookup_as(&args[1], Data::as_zset)?;
+        let (start, end) = match by {
+            RangeBy::Rank => {
+                let (start, stop) = match (parse_int(min), parse_int(max)) {
+                    (Some(start), Some(stop)) => (start, stop),
+                    _ => return Err(Value::StaticError(ERR_NOT_INTEGER)),
+                };
+                let len = zset.as_ref().map_or(0, |zset| zset.len());
+                match list_range(start, stop, len) {
+                    // Ranks count from the end if REV is given
+                    Some((start, stop)) if rev => (len - 1 - stop, len - start),
+                    Some((start, stop)) => (start, stop + 1),
+                    None => (0, 0),
+                }
+            }
+            RangeBy::Score => {
+                let (min, max) = (ScoreBound::parse(min)?, ScoreBound::parse(max)?);
+                zset.as_ref()
+                    .map_or((0, 0), |zset| zset.score_range(min, max))
+            }
+            RangeBy::Lex => {
+                let (min, max) = (LexBound::parse(min)?, LexBound::parse(max)?);
+                zset.as_ref()
+                    .map_or((0, 0), |zset| zset.lex_range(&min, &max))
+            }
+        };
+
+        let (offset, count) = match limit {
+            Some((offset, _)) if offset < 0 => return Ok(Value::Array(Vec::new())),
+            Some((offset, count)) if count >= 0 => (offset as usize, count as usize),
+            Some((offset, _)) => (offset as usize, usize::MAX),
+            None => (0, usize::MAX),
+        };
+        let len = (end - start).saturating_sub(offset).min(count);
+        let zset = match zset {
+            Some(zset) if len > 0 => zset,
+            _ => return Ok(Value::Array(Vec::new())),
+        };
+
+        let elements = if rev {
+            zset.order.rev_iter_from(end - 1 - offset)
+        } else {
+            zset.order.iter_from(start + offset)
+        };
+        let mut reply = Vec::with_capacity(if withscores { 2 * len } else { len });
+        for (score, member) in elements.take(len) {
+            reply.push(Value::BufBulk(member.clone()));
+            if withscores {
+                reply.push(format_score(score));
+            }
+        }
+        Ok(Value::Array(reply))
+    }
+
+    /// ZPOPMIN key [count]
+    pub(super) fn redis_zpopmin(&mut self, args: Args) -> Reply {
+        self.pop_members(args, false)
+    }
+
+    /// ZPOPMAX key [count]
+    pub(super) fn redis_zpopmax(&mut self, args: Args) -> Reply {
+        self.pop_members(args, true)
+    }
+
+    /// Replies with the rank of `args[2]`, from the highest score if `rev`.
+    fn rank(&mut self, args: Args, rev: bool) -> Reply {
+        let withscore = match args.get(3) {
+            Some(option) if option.eq_ignore_ascii_case(b"WITHSCORE") => true,
+            Some(_) => return Err(Value::StaticError(ERR_SYNTAX)),
+            None => false,
+        };
+        if args.len() > 4 {
+            return Err(super::wrong_arity(&args));
+        }
+
+        let zset = self.lookup_as(&args[1], Data::as_zset)?;
+        let rank = zset.as_ref().and_then(|zset| {
+            let rank = zset.rank(&args[2])?;
+            let rank = if rev { zset.len() - 1 - rank } else { rank };
+            Some((rank, zset.score(&args[2]).unwrap()))
+        });
+        match rank {
+            Some((rank, score)) if withscore => Ok(Value::Array(vec![
+                Value::Integer(rank as i64),
+                format_score(score),
+            ])),
+            Some((rank, _)) => Ok(Value::Integer(rank as i64)),
+            None if withscore => Ok(Value::NullArray),
+            None => Ok(Value::Null),
+        }
+    }
+
+    /// Removes up to `args[2]` (default 1) members with the lowest (or
+    /// highest if `max`) scores.
+    fn pop_members(&mut self, args: Args, max: bool) -> Reply {
+        if args.len() > 3 {
+            return Err(Value::StaticError(ERR_SYNTAX));
+        }
+        let count = match args.get(2).map(|count| parse_int(count)) {
+            Some(Some(count)) if count >= 0 => count as usize,
+            Some(_) => {
+                return Err(Value::StaticError(
+                    "ERR value is out of range, must be positive",
+                ))
+            }
+            None => 1,
+        };
+
+        let (reply, now_empty) = match self.lookup_as_mut(&args[1], Data::as_zset_mut)? {
+            Some(zset) => {
+                let count = count.min(zset.len());
+                let popped: Vec<(f64, Vec<u8>)> = if max {
+                    zset.order.rev_iter_from(zset.len() - 1).take(count)
+                } else {
+                    zset.order.iter_from(0).take(count)
+                }
+                .map(|(score, member)| (score, member.clone()))
+                .collect();
+
+                let mut reply = Vec::with_capacity(2 * popped.len());
+                for (score, member) in popped {
+                    zset.remove(&member);
+                    reply.push(Value::BufBulk(member));
+                    reply.push(format_score(score));
+                }
+                (reply, zset.len() == 0)
+            }
+            None => return Ok(Value::Array(Vec::new())),
+        };
+        if now_empty {
+            self.remove(&args[1]);
+        }
+        Ok(Value::Array(reply))
+    }
+}
+
+/// Parses a score, unlike `parse_float` this accepts `inf`, `+inf` and
+/// `-inf`.
+fn parse_score(bytes: &[u8]) -> Option<f64> {
+    match bytes {
+        b"inf" | b"+inf" => Some(f64::INFINITY),
+        b"-inf" => Some(f64::NEG_INFINITY),
+        _ => super::parse_float(bytes),
+    }
+}
+
+/// Formats a score as bulk string the way Redis does for common values.
+fn format_score(score: f64) -> Value {
+    Value::BufBulk(format!("{}", score).into_bytes())
+}
@@ -12,6 +12,9 @@ use super::DecodeError;
 
 /// up to 512 MB in length
 const RESP_MAX_SIZE: i64 = 512 * 1024 * 1024;
+/// Arrays nested deeper than this are rejected by `Decoder`, so decoding
+/// can't overflow the stack.
+const MAX_DEPTH: usize = 32;
 const CRLF_BYTES: &'static [u8] = b"\r\n";
 const NULL_BYTES: &'static [u8] = b"$-1\r\n";
 const NULL_ARRAY_BYTES: &'static [u8] = b"*-1\r\n";
@@ -128,39 +131,86 @@ impl Decoder {
         }
     }
 
-    // Conversion of: self.reader.read_until(b'\n', &mut res)?;
-    fn read_until(&mut self, byte: u8, buf: &mut Vec<u8>) -> usize {
-        let mut popped = 0;
-        loop {
-            match self.reader.pop_front() {
-                None => return popped,
-                Some(c) => {
-                    popped += 1;
-                    buf.push(c);
-
-                    if c == byte {
-                        return popped;
-                    }
-                }
+    /// Returns the number of bytes that have not been decoded yet.
+    pub fn remaining(&self) -> usize {
+        self.reader.len()
+    }
+
+    /// It will read buffers from the inner BufReader, decode it to a Value.
+    ///
+    /// Nothing is consumed if decoding fails.
+    pub fn decode(&mut self) -> Result<Value, DecodeError> {
+        let (value, len) = decode_prefix(self.reader.make_contiguous(), self.buf_bulk, MAX_DEPTH)?;
+        self.reader.drain(..len);
+        Ok(value)
+    }
+}
+
+/// Decodes the RESP value at the front of `input` in place, bulk values are
+/// decoded to buffer bulks if `buf_bulk` is set. Arrays nested more than
+/// `max_depth` levels deep are rejected with `DecodeError::InvalidInput`.
+///
+/// Returns the value and the number of bytes it took up. Fails with
+/// `DecodeError::UnexpectedEof` if `input` ends before the value does. The
+/// value is only built once all of it arrived, until then we skip over
+/// its lengths without copying anything, but every call starts again from
+/// the first byte.
+pub fn decode_prefix(
+    input: &[u8],
+    buf_bulk: bool,
+    max_depth: usize,
+) -> Result<(Value, usize), DecodeError> {
+    let mut parser = Parser {
+        input,
+        pos: 0,
+        buf_bulk,
+    };
+    parser.skip(max_depth)?;
+    let len = parser.pos;
+
+    parser.pos = 0;
+    let value = parser.decode()?;
+    debug_assert_eq!(parser.pos, len);
+    Ok((value, len))
+}
+
+/// Decodes RESP values from a byte slice, `pos` is the first byte not
+/// decoded yet.
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    buf_bulk: bool,
+}
+
+impl<'a> Parser<'a> {
+    /// Returns the next line including its terminating `\n`.
+    fn read_line(&mut self) -> Result<&'a [u8], DecodeError> {
+        let input = self.input;
+        match input[self.pos..].iter().position(|&c| c == b'\n') {
+            Some(end) => {
+                let line = &input[self.pos..self.pos + end + 1];
+                self.pos += end + 1;
+                Ok(line)
             }
+            // Ran out of input before the end of the line, more data is needed
+            None => Err(DecodeError::UnexpectedEof),
         }
     }
 
-    // Conversion of self.reader.read_exact(buf.as_mut_slice())?;
-    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), DecodeError> {
-        for i in 0..buf.len() {
-            match self.reader.pop_front() {
-                None => return Err(DecodeError::UnexpectedEof),
-                Some(c) => buf[i] = c,
-            }
+    /// Returns the next `len` bytes.
+    fn read_exact(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let input = self.input;
+        if input.len() - self.pos < len {
+            return Err(DecodeError::UnexpectedEof);
         }
-        Ok(())
+        self.pos += len;
+        Ok(&input[self.pos - len..self.pos])
     }
 
-    /// It will read buffers from the inner BufReader, decode it to a Value.
-    pub fn decode(&mut self) -> Result<Value, DecodeError> {
-        let mut res: Vec<u8> = Vec::with_capacity(16);
-        self.read_until(b'\n', &mut res);
+    /// Returns the type prefix of the next value and the rest of its first
+    /// line without the `\r\n`.
+    fn read_header(&mut self) -> Result<(u8, &'a [u8]), DecodeError> {
+        let res = self.read_line()?;
         let len = res.len();
         if len < 3 {
             error!("len < 3");
@@ -170,9 +220,67 @@ impl Decoder {
             error!("!is_crlf(res[len - 2], res[len - 1])");
             return Err(DecodeError::InvalidInput);
         }
+        Ok((res[0], &res[1..len - 2]))
+    }
 
-        let bytes = res[1..len - 2].as_ref();
-        match res[0] {
+    /// Parses the length of a bulk or an array, `None` for a null one.
+    fn parse_length(bytes: &[u8]) -> Result<Option<usize>, DecodeError> {
+        let int = parse_integer(bytes)?;
+        if int == -1 {
+            return Ok(None);
+        }
+        if int < -1 || int >= RESP_MAX_SIZE {
+            error!("int < -1 || int >= RESP_MAX_SIZE");
+            return Err(DecodeError::InvalidInput);
+        }
+        Ok(Some(int as usize))
+    }
+
+    /// Returns the `len` bytes of a bulk, checking the `\r\n` after them.
+    fn read_bulk(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let buf = self.read_exact(len + 2)?;
+        if !is_crlf(buf[len], buf[len + 1]) {
+            error!("!is_crlf(buf[len], buf[len + 1])");
+            return Err(DecodeError::InvalidInput);
+        }
+        Ok(&buf[..len])
+    }
+
+    /// Moves past the next value without copying it, fails like `decode`
+    /// does if it's incomplete or if it nests arrays more than `depth`
+    /// levels deep.
+    fn skip(&mut self, depth: usize) -> Result<(), DecodeError> {
+        let (prefix, bytes) = self.read_header()?;
+        match prefix {
+            b'+' | b'-' => Ok(()),
+            b':' => parse_integer(bytes).map(|_| ()),
+            b'$' => match Self::parse_length(bytes)? {
+                Some(len) => self.read_bulk(len).map(|_| ()),
+                None => Ok(()),
+            },
+            b'*' => {
+                let len = match Self::parse_length(bytes)? {
+                    Some(len) => len,
+                    None => return Ok(()),
+                };
+                if depth == 0 {
+                    error!("arrays nested too deep");
+                    return Err(DecodeError::InvalidInput);
+                }
+                for _ in 0..len {
+                    self.skip(depth - 1)?;
+                }
+                Ok(())
+            }
+            _prefix => Err(DecodeError::InvalidType),
+        }
+    }
+
+    /// Decodes the next value, `skip` must have checked that it's complete
+    /// and not nested too deep.
+    fn decode(&mut self) -> Result<Value, DecodeError> {
+        let (prefix, bytes) = self.read_header()?;
+        match prefix {
             // Value::String
             b'+' => parse_string(bytes).map(Value::String),
             // Value::Error
@@ -181,43 +289,30 @@ impl Decoder {
             b':' => parse_integer(bytes).map(Value::Integer),
             // Value::Bulk
             b'$' => {
-                let int = parse_integer(bytes)?;
-                if int == -1 {
+                let len = match Self::parse_length(bytes)? {
+                    Some(len) => len,
                     // Null bulk
-                    return Ok(Value::Null);
-                }
-                if int < -1 || int >= RESP_MAX_SIZE {
-                    error!("int < -1 || int >= RESP_MAX_SIZE");
-                    return Err(DecodeError::InvalidInput);
-                }
-
-                let mut buf: Vec<u8> = Vec::new();
-                let int = int as usize;
-                buf.resize(int + 2, 0);
-                self.read_exact(buf.as_mut_slice())?;
-                if !is_crlf(buf[int], buf[int + 1]) {
-                    error!("!is_crlf(buf[int], buf[int + 1])");
-                    return Err(DecodeError::InvalidInput);
-                }
-                buf.truncate(int);
+                    None => return Ok(Value::Null),
+                };
+                let buf = self.read_bulk(len)?;
                 if self.buf_bulk {
-                    return Ok(Value::BufBulk(buf));
+                    return Ok(Value::BufBulk(buf.to_vec()));
                 }
-                parse_string(buf.as_slice()).map(Value::Bulk)
+                parse_string(buf).map(Value::Bulk)
             }
             // Value::Array
             b'*' => {
-                let int = parse_integer(bytes)?;
-                if int == -1 {
+                let len = match Self::parse_length(bytes)? {
+                    Some(len) => len,
                     // Null array
-                    return Ok(Value::NullArray);
-                }
-                if int < -1 || int >= RESP_MAX_SIZE {
-                    return Err(DecodeError::InvalidInput);
-                }
+                    None => return Ok(Value::NullArray),
+                };
 
-                let mut array: Vec<Value> = Vec::with_capacity(int as usize);
-                for _ in 0..int {
+                // Every element takes at least 3 bytes, don't trust `len`
+                // for more than that
+                let remaining = (self.input.len() - self.pos) / 3;
+                let mut array: Vec<Value> = Vec::with_capacity(len.min(remaining));
+                for _ in 0..len {
                     let val = self.decode()?;
                     array.push(val);
                 }
@@ -240,6 +335,6 @@ fn parse_string(bytes: &[u8]) -> Result<String, DecodeError> {
 
 #[inline]
 fn parse_integer(bytes: &[u8]) -> Result<i64, DecodeError> {
-    let str_integer = parse_string(bytes)?;
+    let str_integer = core::str::from_utf8(bytes).map_err(|_err| DecodeError::InvalidData)?;
     (str_integer.parse::<i64>()).map_err(|_err| DecodeError::InvalidData)
 }